@@ -17,11 +17,116 @@ import kotlinx.serialization.encodeToString
 import kotlinx.serialization.json.Json
 
 
+@Serializable
+data class Gestures (
+    val pinchSensitivity: Float = 1.0f,
+    val panSensitivity: Float = 1.0f,
+    val twistSensitivity: Float = 1.0f,
+    // "azimuth", "roll" or "none"
+    val twist: String = "azimuth",
+    val minRadius: Float = 0.5f,
+    val maxRadius: Float = 200.0f,
+)
+
+@Serializable
+data class WorkPlane (
+    val origin: List<Float> = listOf(0.0f, 0.0f, 0.0f),
+    val normal: List<Float> = listOf(0.0f, 1.0f, 0.0f),
+)
+
+@Serializable
+data class Projection (
+    // "depth", "axisPlane", "workPlane" or "surface"
+    val target: String = "depth",
+    val depth: Float = 10.0f,
+    // "x", "y" or "z"
+    val axis: String = "y",
+    val axisOffset: Float = 0.0f,
+    val workPlane: WorkPlane = WorkPlane(),
+    val surfaceOffset: Float = 0.1f,
+)
+
+@Serializable
+data class Symmetry (
+    // Mirror planes through origin, perpendicular to each axis
+    val mirrorX: Boolean = false,
+    val mirrorY: Boolean = false,
+    val mirrorZ: Boolean = false,
+    val origin: List<Float> = listOf(0.0f, 0.0f, 0.0f),
+    // Copies around radialAxis counting the stroke itself, 1 is off
+    val radialCopies: Int = 1,
+    // "x", "y" or "z"
+    val radialAxis: String = "y",
+)
+
+@Serializable
+data class Profile (
+    // "square", "star" or "polygon"
+    val shape: String = "square",
+    // Star tips and the radius between them
+    val points: Int = 5,
+    val innerRadius: Float = 0.5f,
+    // Polygon corners as [x, y], counter-clockwise around the stroke
+    val vertices: List<List<Float>> = emptyList(),
+)
+
+// Options for RustBridge.check_sketch_mesh and export_sketch_mesh
+@Serializable
+data class RepairOptions (
+    // Merge vertices closer than weldDistance with the same normal and UV
+    val weld: Boolean = true,
+    val weldDistance: Float = 1e-5f,
+    val removeDegenerate: Boolean = true,
+    val removeDuplicates: Boolean = true,
+    val fixWinding: Boolean = true,
+    // Larger holes are left open, 0 leaves every hole open
+    val maxHoleEdges: Int = 16,
+)
+
+// For RustBridge.import_mesh and mesh objects in the scene
+@Serializable
+data class Shading (
+    // "flat", "smooth" or "auto", which keeps edges sharper than creaseAngle hard
+    val mode: String = "auto",
+    // Degrees between faces
+    val creaseAngle: Float = 30.0f,
+)
+
+@Serializable
+data class StrokeSettings (
+    val projection: Projection = Projection(),
+    val symmetry: Symmetry = Symmetry(),
+    // null keeps the raw touch samples / every point
+    val resampleSpacing: Float? = null,
+    val outlierThreshold: Float? = 5.0f,
+    val controlPointDensity: Float = 0.1f,
+    val smoothing: Float = 0.0f,
+    val maxAngle: Float = 5.0f,
+    val maxDepth: Int = 6,
+    // "tube", "ribbon", "line" or "extrude"
+    val style: String = "tube",
+    val radius: Float = 0.1f,
+    // Line width in pixels
+    val lineWidth: Float = 6.0f,
+    val profile: Profile = Profile(),
+    // "constant", "pressure" or "velocity"
+    val width: String = "pressure",
+    val thinSpeed: Float = 2.0f,
+    val minScale: Float = 0.2f,
+    val taper: Float = 0.15f,
+    val radialSegments: Int = 8,
+    val caps: Boolean = true,
+    // "parallelTransport" or "cameraFacing"
+    val frames: String = "parallelTransport",
+)
+
 @Serializable
 data class AppInitOpts (
     val backgroundColor: List<Float>,
     val environmentLightColor: List<Float>,
     val moveStrength: Float,
+    val gestures: Gestures = Gestures(),
+    val stroke: StrokeSettings = StrokeSettings(),
     val scene: Scene,
 )
 
@@ -30,6 +135,8 @@ fun packAppInitOpts(appState: AppState) : AppInitOpts {
         backgroundColor = colorToList(appState.backgroundColor),
         environmentLightColor = colorToList(appState.environmentLightColor),
         moveStrength = appState.moveStrength,
+        gestures = appState.gestures,
+        stroke = appState.stroke,
         scene = appState.scene
     )
 }
@@ -68,27 +175,37 @@ class BevySurfaceView : SurfaceView, SurfaceHolder.Callback2 {
 
 
     override fun onTouchEvent(event: MotionEvent): Boolean {
-        when (event.action) {
-            MotionEvent.ACTION_DOWN -> {
-                Log.d("Touch", "ACTION_DOWN at: " + event.x + ", " + event.y)
+        when (event.actionMasked) {
+            MotionEvent.ACTION_DOWN, MotionEvent.ACTION_POINTER_DOWN -> {
+                val i = event.actionIndex
+                Log.d("Touch", "ACTION_DOWN at: " + event.getX(i) + ", " + event.getY(i))
                 if (bevy_app != Long.MAX_VALUE) {
-                    RustBridge.device_enter_touch(bevy_app, event.x , event.y )
+                    RustBridge.device_pointer_down(bevy_app, event.getPointerId(i), event.getX(i), event.getY(i), event.getPressure(i))
                 }
                 return true
             }
 
             MotionEvent.ACTION_MOVE -> {
-//                Log.d("Touch", "ACTION_MOVE at: " + event.x + ", " + event.y)
                 if (bevy_app != Long.MAX_VALUE) {
-                    RustBridge.device_touch_move(bevy_app, event.x , event.y )
+                    for (i in 0 until event.pointerCount) {
+                        RustBridge.device_pointer_move(bevy_app, event.getPointerId(i), event.getX(i), event.getY(i), event.getPressure(i))
+                    }
+                }
+                return true
+            }
+
+            MotionEvent.ACTION_UP, MotionEvent.ACTION_POINTER_UP -> {
+                if (bevy_app != Long.MAX_VALUE) {
+                    RustBridge.device_pointer_up(bevy_app, event.getPointerId(event.actionIndex))
                 }
                 return true
             }
 
-            MotionEvent.ACTION_UP -> {
-//                Log.d("Touch", "ACTION_UP at: " + event.x + ", " + event.y)
+            MotionEvent.ACTION_CANCEL -> {
                 if (bevy_app != Long.MAX_VALUE) {
-                    RustBridge.device_exit_touch(bevy_app)
+                    for (i in 0 until event.pointerCount) {
+                        RustBridge.device_pointer_up(bevy_app, event.getPointerId(i))
+                    }
                 }
                 return true
             }
@@ -113,7 +230,12 @@ class BevySurfaceView : SurfaceView, SurfaceHolder.Callback2 {
                     Log.i("camera", appState.scene.cameraPos.toString())
                     val json = Json { encodeDefaults = true }
                     val jsonout = json.encodeToString(initopts)
-                    bevy_app = RustBridge.create_bevy_app(this.context.assets, h.surface, scaleFactor, jsonout)
+                    val app = RustBridge.create_bevy_app(this.context.assets, h.surface, scaleFactor, jsonout)
+                    if (app != 0L) {
+                        bevy_app = app
+                    } else {
+                        Log.e("RustBridge", "Invalid scene: " + RustBridge.validate_opts(jsonout))
+                    }
                 }
 
             }
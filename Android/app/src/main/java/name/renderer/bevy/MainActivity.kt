@@ -41,20 +41,79 @@ import kotlinx.serialization.json.Json
 import kotlin.math.roundToInt
 
 
+@Serializable
+data class BMaterial(
+    val baseColor: List<Float> = listOf(1f, 1f, 1f),
+    val alpha: Float = 1f,
+    val alphaMode: String = "opaque",
+    val alphaCutoff: Float = 0.5f,
+    val metallic: Float = 0f,
+    val perceptualRoughness: Float = 0.5f,
+    val emissive: List<Float> = listOf(0f, 0f, 0f),
+    val reflectance: Float = 0.5f,
+    val doubleSided: Boolean = false,
+    val unlit: Boolean = false,
+    val baseColorTexture: String? = null,
+    val normalMapTexture: String? = null,
+    val metallicRoughnessTexture: String? = null,
+)
+
 @Serializable
 data class BObject(
-    val data: String,
+    // Assigned by the renderer when null
+    val id: Int? = null,
+    val data: String = "",
     val type: String,
     val label: String,
     val color: List<Float>,
     val pos: List<Float>,
     val scale: Float,
+    // Per-axis scale, multiplied with scale
+    val scaleAxes: List<Float>? = null,
+    // Quaternion [x, y, z, w]
+    val rotation: List<Float> = listOf(0f, 0f, 0f, 1f),
+    // Overrides color when set
+    val material: BMaterial? = null,
+    val visible: Boolean = true,
+    // Stroke objects only: "tube", "ribbon", "line" or "extrude"
+    val style: String = "tube",
+    // Cross-section of extruded strokes
+    val profile: Profile? = null,
+    // Centerline of line strokes as [x, y, z, width]
+    val points: List<List<Float>> = emptyList(),
+    // Sketch layer the stroke is on
+    val layer: Int = 0,
+    // Stroke radius at full width
+    val radius: Float = 0f,
+    // Touch samples as drawn and the smoothed curve, as [x, y, z]
+    val samples: List<List<Float>> = emptyList(),
+    val curve: List<List<Float>> = emptyList(),
+)
+
+@Serializable
+data class BOrbit(
+    val azimuth: Float = 0f,
+    val elevation: Float = 0f,
+    val radius: Float = 10f,
+)
+
+@Serializable
+data class BLight(
+    // "point" or "directional"
+    val type: String,
+    val color: List<Float> = listOf(1f, 1f, 1f),
+    val intensity: Float,
+    val pos: List<Float> = listOf(0f, 0f, 0f),
+    val rotation: List<Float> = listOf(0f, 0f, 0f, 1f),
+    val shadows: Boolean = false,
 )
 
 @Serializable
 data class Scene(
     val objects : List<BObject> = listOf(),
-    val cameraPos : List<Float> = listOf(0f, 0f, 0f)
+    val cameraPos : List<Float> = listOf(0f, 0f, 0f),
+    val camera : BOrbit = BOrbit(),
+    val lights : List<BLight> = listOf()
 )
 
 // Final AppState data class with all properties
@@ -79,6 +138,8 @@ data class AppState(
     val backgroundColor: Color = Color.White,
     val environmentLightColor: Color = Color.White,
     val moveStrength: Float = 0.01f,
+    val gestures: Gestures = Gestures(),
+    val stroke: StrokeSettings = StrokeSettings(),
     val fontName: String = "Times",
     val viewScale: Float = 1.0f,
     val viewOffsetX: Float = 0f,
@@ -179,7 +240,8 @@ fun MyApp() {
                         type = "sphere",
                         label = "none",
                         pos = listOf(0.0f, 0.0f, 0.0f),
-                        scale = 1f
+                        scale = 1f,
+                        material = shapeMaterial(appState)
                     )
                     appState = appState.copy(scene = appState.scene.copy(objects = nobjs))
                     val trimmedHistory = history.subList(0, currentIndex + 1)
@@ -195,7 +257,8 @@ fun MyApp() {
                         type = "cube",
                         label = "none",
                         pos = listOf(0.0f, 0.0f, 0.0f),
-                        scale = 1f
+                        scale = 1f,
+                        material = shapeMaterial(appState)
                     )
                     appState = appState.copy(scene = appState.scene.copy(objects = nobjs))
                     val trimmedHistory = history.subList(0, currentIndex + 1)
@@ -211,7 +274,8 @@ fun MyApp() {
                         type = "plane",
                         label = "none",
                         pos = listOf(0.0f, 0.0f, 0.0f),
-                        scale = 1f
+                        scale = 1f,
+                        material = shapeMaterial(appState)
                     )
                     appState = appState.copy(scene = appState.scene.copy(objects = nobjs))
                     val trimmedHistory = history.subList(0, currentIndex + 1)
@@ -310,6 +374,17 @@ fun listToColor(cs: List<Float>) : Color {
     return Color(red = cs[0], green = cs[1], blue = cs[2])
 }
 
+// Material for new shapes, from the sliders of the shape screen
+fun shapeMaterial(state: AppState) : BMaterial {
+    return BMaterial(
+        baseColor = colorToList(state.shapeColor),
+        alpha = state.shapeOpacity,
+        alphaMode = if (state.shapeOpacity < 1f) "blend" else "opaque",
+        metallic = state.shapeMetallic,
+        perceptualRoughness = state.shapeRoughness,
+    )
+}
+
 // The main surface card of the application
 @Composable
 fun SurfaceCard(
@@ -361,13 +436,15 @@ fun SurfaceCard(
                     inputStream?.let { inputStream ->
                         val bytes = inputStream.readBytes()
                         val text = String(bytes, Charsets.UTF_8)
-                        // Update file
-                        val json = Json { encodeDefaults = true }
+                        // Update file. Primitive parameters are not modelled here.
+                        val json = Json { encodeDefaults = true; ignoreUnknownKeys = true }
                         val myopts = json.decodeFromString<AppInitOpts>(text)
                         onUpdateAppState(appState.copy(scene = myopts.scene,
                             backgroundColor = listToColor(myopts.backgroundColor),
                             environmentLightColor = listToColor(myopts.environmentLightColor),
-                            moveStrength = myopts.moveStrength
+                            moveStrength = myopts.moveStrength,
+                            gestures = myopts.gestures,
+                            stroke = myopts.stroke
                         ))
                     }
                 }
@@ -382,8 +459,13 @@ fun SurfaceCard(
             uri?.let {
                 // User has created a file.
                 val outputStream = context.contentResolver.openOutputStream(uri)
-                val opts = packAppInitOpts(appState)
-                val text = Json.encodeToString(opts)
+                // Prefer the live scene, which includes edits made in the viewport
+                val app = surfaceView?.bevy_app ?: Long.MAX_VALUE
+                val text = if (app != Long.MAX_VALUE) {
+                    RustBridge.get_scene(app)
+                } else {
+                    Json.encodeToString(packAppInitOpts(appState))
+                }
                 outputStream?.use {
                     it.write(text.toByteArray(Charsets.UTF_8))
                 }
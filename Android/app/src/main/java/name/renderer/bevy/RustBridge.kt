@@ -12,14 +12,62 @@ object RustBridge {
 
     external fun init_ndk_context(ctx: Context)
     external fun create_bevy_app(asset_manager: AssetManager, surface: Surface, scale_factor: Float, opts: String): Long
+    external fun validate_opts(opts: String): String
     external fun enter_frame(bevy_app: Long)
     external fun device_motion(bevy_app: Long, x: Float, y: Float, z: Float)
     external fun update_camera_offset(bevy_app: Long, x: Float, y: Float, z: Float)
     external fun device_enter_touch(bevy_app: Long, x: Float, y: Float)
     external fun device_touch_move(bevy_app: Long, x: Float, y: Float)
     external fun device_exit_touch(bevy_app: Long)
+    // Multi-touch, `id` is the MotionEvent pointer id, `pressure` is MotionEvent.getPressure
+    external fun device_pointer_down(bevy_app: Long, id: Int, x: Float, y: Float, pressure: Float)
+    external fun device_pointer_move(bevy_app: Long, id: Int, x: Float, y: Float, pressure: Float)
+    external fun device_pointer_up(bevy_app: Long, id: Int)
     external fun release_bevy_app(bevy_app: Long)
+    // 0: orbit, 1: sketch, 2: select
     external fun switch_mode(bevy_app: Long, mode: Int)
+    // 0: translate, 1: rotate, 2: scale
+    external fun set_gizmo_mode(bevy_app: Long, mode: Int)
+    // Grid step and angle step in degrees, 0 turns snapping off
+    external fun set_gizmo_snap(bevy_app: Long, grid: Float, angle: Float)
+    // Undo/redo of scene edits, strokes and camera moves. Return false if nothing happened.
+    external fun undo(bevy_app: Long): Boolean
+    external fun redo(bevy_app: Long): Boolean
+    external fun can_undo(bevy_app: Long): Boolean
+    external fun can_redo(bevy_app: Long): Boolean
+    // Stroke smoothing settings JSON, returns {"ok":..} with validation errors
+    external fun set_stroke_settings(bevy_app: Long, settings: String): String
+    // Plane strokes land on when projecting on the work plane, returns {"ok":..}
+    external fun set_work_plane(bevy_app: Long, origin: FloatArray, normal: FloatArray): String
     external fun get_mesh(bevy_app: Long) : String
-    external fun import_mesh(bevy_app: Long, file: ByteArray)
+    external fun get_scene(bevy_app: Long) : String
+    // Non-positive maxTriangles and maxError leave that decimation limit out, here and in
+    // export_sketch_mesh. maxError is how far the surface may move, in mesh units, as the root
+    // mean square distance to the original triangle, boundary and seam planes. A Shading JSON
+    // generates normals, null keeps those in the file
+    external fun import_mesh(bevy_app: Long, file: ByteArray, maxTriangles: Int, maxError: Float, shading: String?) : String
+    // {"id": .., "point": [x, y, z]} of the object picked in select mode, or null
+    external fun get_selection(bevy_app: Long) : String
+    // Per-object editing by ID, each returns the {"ok": ..} JSON envelope
+    external fun list_objects(bevy_app: Long) : String
+    external fun set_object_transform(bevy_app: Long, id: Int, pos: FloatArray, rotation: FloatArray, scale: FloatArray) : String
+    external fun set_object_material(bevy_app: Long, id: Int, material: String) : String
+    external fun set_object_visible(bevy_app: Long, id: Int, visible: Boolean) : String
+    external fun duplicate_object(bevy_app: Long, id: Int) : String
+    external fun delete_object(bevy_app: Long, id: Int) : String
+    // Replaces both objects by one mesh and returns its ID. op: 0 union, 1 carve tool out of
+    // target, 2 intersection. Fails for any other op and for objects too detailed to combine
+    external fun boolean_objects(bevy_app: Long, target: Int, tool: Int, op: Int) : String
+    // Sketch layers, each returns the {"ok": ..} JSON envelope. add_layer makes the new layer
+    // active and takes null for a default name. A negative layer exports every layer.
+    external fun list_layers(bevy_app: Long) : String
+    external fun add_layer(bevy_app: Long, name: String?) : String
+    external fun set_active_layer(bevy_app: Long, id: Int) : String
+    external fun set_layer_visible(bevy_app: Long, id: Int, visible: Boolean) : String
+    external fun set_layer_locked(bevy_app: Long, id: Int, locked: Boolean) : String
+    external fun export_strokes(bevy_app: Long, layer: Int) : String
+    // repair is a mesh repair options JSON, or null to export without repairing. check_sketch_mesh
+    // returns a report of the problems found and fixed, using the default options for null.
+    external fun export_sketch_mesh(bevy_app: Long, layer: Int, maxTriangles: Int, maxError: Float, repair: String?) : String
+    external fun check_sketch_mesh(bevy_app: Long, layer: Int, repair: String?) : String
 }
@@ -124,11 +124,28 @@ fun CurveScreen(
                     )
                 }
 
+                Row(horizontalArrangement = Arrangement.spacedBy(16.dp)) {
+                    Button(
+                        onClick = { surfaceView?.let { RustBridge.undo(it.bevy_app) } },
+                        modifier = Modifier.weight(1f)
+                    ) {
+                        Text("Undo")
+                    }
+                    Button(
+                        onClick = { surfaceView?.let { RustBridge.redo(it.bevy_app) } },
+                        modifier = Modifier.weight(1f)
+                    ) {
+                        Text("Redo")
+                    }
+                }
+
                 Button(
                     onClick = {
                         surfaceView?.let { surfaceView ->
                             val res = RustBridge.get_mesh(surfaceView.bevy_app)
-                            val obj = Json.decodeFromString<BObject>(res)
+                            // Skip stroke fields BObject does not model
+                            val json = Json { ignoreUnknownKeys = true }
+                            val obj = json.decodeFromString<BObject>(res)
                             val oldobjects = appState.scene.objects
                             val nobjs = oldobjects + obj
                             onUpdateAppState(appState.copy(scene = appState.scene.copy(objects = nobjs)))
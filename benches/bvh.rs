@@ -0,0 +1,131 @@
+//! Triangle hierarchy build and queries on a long sketch tube from
+//! `meshify` and on a dense sphere read back from OBJ.
+//!
+//! Run with `cargo bench --features bench --bench bvh`.
+
+use bevy::math::primitives::Sphere;
+use bevy::render::mesh::Meshable;
+use bevy_in_app::bench::{
+    Aabb, Bvh, Mesh, TubeOptions, export_obj_to_string, from_bevy_mesh, load_obj, meshify,
+};
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use glam::Vec3;
+use std::hint::black_box;
+
+/// Queries per iteration, spread over the mesh
+const QUERIES: usize = 256;
+
+/// A wiggly helix of 3000 points, like a long stroke
+fn tube() -> Mesh {
+    let curve: Vec<Vec3> = (0..3000)
+        .map(|i| {
+            let t = i as f32 * 0.01;
+            Vec3::new(t.cos() * (1.0 + 0.3 * (7.0 * t).sin()), t * 0.05, t.sin())
+        })
+        .collect();
+    let radii = vec![0.05; curve.len()];
+    let options = TubeOptions {
+        radial_segments: 12,
+        ..Default::default()
+    };
+    meshify(&curve, &radii, Vec3::new(0.0, 0.0, 5.0), &options)
+}
+
+/// An icosphere of about 34k triangles, written out and imported again
+fn imported_obj() -> Mesh {
+    let sphere = Sphere::new(1.0).mesh().ico(40).expect("ico sphere");
+    let obj = export_obj_to_string(&from_bevy_mesh(&sphere).expect("triangle list"));
+    load_obj(obj).expect("exported OBJ loads")
+}
+
+/// Deterministic points in the cube of half size `scale` around `center`
+fn points(seed: u64, center: Vec3, scale: f32) -> Vec<Vec3> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0
+    };
+    (0..QUERIES)
+        .map(|_| center + Vec3::new(next(), next(), next()) * scale)
+        .collect()
+}
+
+fn bench_mesh(c: &mut Criterion, name: &str, mesh: Mesh) {
+    let bounds = mesh.bvh().bounds().expect("mesh has triangles");
+    let center = bounds.center();
+    let scale = (bounds.max - bounds.min).max_element() * 0.5;
+    let origins = points(1, center, scale * 1.5);
+    let targets = points(2, center, scale * 0.5);
+    let probes = points(3, center, scale);
+    let mut group = c.benchmark_group(name);
+
+    group.bench_function("build", |b| b.iter(|| Bvh::new(black_box(&mesh))));
+    group.bench_function("build lazily", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut mesh = mesh.clone();
+                mesh.geometry_changed();
+                mesh
+            },
+            |mesh| {
+                mesh.bvh();
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("ray cast", |b| {
+        b.iter(|| {
+            for (&origin, &target) in origins.iter().zip(&targets) {
+                let dir = (target - origin).normalize();
+                black_box(mesh.ray_cast(origin, dir, f32::INFINITY));
+            }
+        })
+    });
+    group.bench_function("closest point", |b| {
+        b.iter(|| {
+            for &p in &probes {
+                black_box(mesh.closest_point(p, f32::INFINITY));
+            }
+        })
+    });
+    group.bench_function("aabb overlap", |b| {
+        let half = Vec3::splat(scale * 0.05);
+        b.iter(|| {
+            for &p in &probes {
+                black_box(mesh.overlap_aabb(&Aabb::new(p - half, p + half)));
+            }
+        })
+    });
+    group.bench_function("sphere overlap", |b| {
+        b.iter(|| {
+            for &p in &probes {
+                black_box(mesh.overlap_sphere(p, scale * 0.05));
+            }
+        })
+    });
+    group.bench_function("triangle overlap", |b| {
+        let offsets = [Vec3::X, Vec3::Y, Vec3::Z].map(|v| v * scale * 0.1);
+        b.iter(|| {
+            for &p in &probes {
+                black_box(mesh.overlap_triangle(offsets.map(|offset| p + offset)));
+            }
+        })
+    });
+    group.bench_function("mesh overlap", |b| {
+        let mut shifted = mesh.clone();
+        shifted.translate(Vec3::new(0.05, 0.03, 0.0) * scale);
+        shifted.bvh();
+        b.iter(|| black_box(mesh.overlap_mesh(&shifted)))
+    });
+    group.finish();
+}
+
+fn bvh(c: &mut Criterion) {
+    bench_mesh(c, "meshify tube", tube());
+    bench_mesh(c, "imported obj", imported_obj());
+}
+
+criterion_group!(benches, bvh);
+criterion_main!(benches);
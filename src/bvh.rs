@@ -0,0 +1,773 @@
+//! Bounding volume hierarchy over the triangles of a [`Mesh`], for ray
+//! casts, closest points and overlap tests that would otherwise have to look
+//! at every triangle.
+//!
+//! The tree is built top-down, splitting each node where the surface area
+//! heuristic over a few bins of triangle centroids is cheapest. Nodes live in
+//! one array, with the two children of a node next to each other. A mesh
+//! builds its tree on the first query and keeps it, see [`Mesh::bvh`].
+//!
+//! The tree only stores triangle indices, so every query takes the mesh it
+//! was built for. Triangles with out of range indices are left out.
+
+use glam::Vec3;
+
+use crate::geometry::Mesh;
+
+/// Nodes with at most this many triangles are not split further.
+const LEAF_SIZE: usize = 4;
+
+/// Centroid bins tried per split.
+const BINS: usize = 12;
+
+/// Axes shorter than this, relative to the vectors they are crossed from,
+/// are too noisy to separate triangles by.
+const PARALLEL_TOLERANCE: f32 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Contains nothing, and grows to whatever is added to it
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::INFINITY,
+        max: Vec3::NEG_INFINITY,
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+
+    fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Aabb::EMPTY, |bounds, p| Aabb {
+            min: bounds.min.min(p),
+            max: bounds.max.max(p),
+        })
+    }
+
+    fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Half the surface area, 0 when empty
+    fn area(&self) -> f32 {
+        let size = (self.max - self.min).max(Vec3::ZERO);
+        size.x * size.y + size.y * size.z + size.z * size.x
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    fn distance_squared(&self, p: Vec3) -> f32 {
+        (self.min - p)
+            .max(p - self.max)
+            .max(Vec3::ZERO)
+            .length_squared()
+    }
+
+    /// Slab test with the reciprocal of the ray direction. Returns the
+    /// distance at which the ray enters the box, or 0 if it starts inside.
+    fn ray(&self, origin: Vec3, inv_dir: Vec3, max_distance: f32) -> Option<f32> {
+        let t1 = (self.min - origin) * inv_dir;
+        let t2 = (self.max - origin) * inv_dir;
+        let near = t1.min(t2).max_element().max(0.0);
+        let far = t1.max(t2).min_element().min(max_distance);
+        // NaN from 0 * inf makes the comparison false, which counts as a miss
+        (far >= near).then_some(near)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub triangle: u32,
+    /// Along the ray, in multiples of its direction
+    pub distance: f32,
+    pub point: Vec3,
+    /// Unit normal of the triangle, facing back along the ray
+    pub normal: Vec3,
+    /// Weights of the three corners at `point`, for interpolating normals
+    /// and UVs
+    pub barycentric: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClosestPoint {
+    pub triangle: u32,
+    pub distance: f32,
+    pub point: Vec3,
+    /// Weights of the three corners at `point`
+    pub barycentric: Vec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    /// For a leaf, where its triangles start in `order`. Otherwise the index
+    /// of the first child, with the second one right after it.
+    first: u32,
+    /// Number of triangles in a leaf, 0 for inner nodes
+    count: u32,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+
+    fn children(&self) -> [usize; 2] {
+        [self.first as usize, self.first as usize + 1]
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Triangle indices, grouped by leaf
+    order: Vec<u32>,
+}
+
+fn corners(mesh: &Mesh, triangle: u32) -> Option<[Vec3; 3]> {
+    let [a, b, c] = mesh.triangles().get(triangle as usize)?;
+    Some([
+        *mesh.positions().get(*a as usize)?,
+        *mesh.positions().get(*b as usize)?,
+        *mesh.positions().get(*c as usize)?,
+    ])
+}
+
+impl Bvh {
+    pub fn new(mesh: &Mesh) -> Self {
+        let mut bounds = Vec::with_capacity(mesh.triangles().len());
+        let mut order = Vec::with_capacity(mesh.triangles().len());
+        for t in 0..mesh.triangles().len() as u32 {
+            match corners(mesh, t) {
+                Some(tri) => {
+                    bounds.push(Aabb::from_points(tri));
+                    order.push(t);
+                }
+                None => bounds.push(Aabb::EMPTY),
+            }
+        }
+        if order.is_empty() {
+            return Bvh::default();
+        }
+        let centroids: Vec<Vec3> = bounds.iter().map(Aabb::center).collect();
+
+        let root = Node {
+            bounds: order
+                .iter()
+                .fold(Aabb::EMPTY, |all, &t| all.union(bounds[t as usize])),
+            first: 0,
+            count: order.len() as u32,
+        };
+        let mut nodes = vec![root];
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = nodes[index];
+            let range = node.first as usize..(node.first + node.count) as usize;
+            if range.len() <= LEAF_SIZE {
+                continue;
+            }
+            let Some((left, right)) = split(&mut order[range], &bounds, &centroids) else {
+                // All centroids coincide, nothing to split by
+                continue;
+            };
+            let children = nodes.len() as u32;
+            nodes.push(Node {
+                bounds: left.0,
+                first: node.first,
+                count: left.1,
+            });
+            nodes.push(Node {
+                bounds: right.0,
+                first: node.first + left.1,
+                count: right.1,
+            });
+            nodes[index].first = children;
+            nodes[index].count = 0;
+            stack.extend([children as usize, children as usize + 1]);
+        }
+        Bvh { nodes, order }
+    }
+
+    /// Bounds of every triangle, `None` for an empty mesh
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|root| root.bounds)
+    }
+
+    fn leaf(&self, node: &Node) -> &[u32] {
+        &self.order[node.first as usize..(node.first + node.count) as usize]
+    }
+
+    /// Closest triangle hit by the ray within `max_distance`. Both sides of
+    /// a triangle count.
+    pub fn ray_cast(
+        &self,
+        mesh: &Mesh,
+        origin: Vec3,
+        dir: Vec3,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        let inv_dir = dir.recip();
+        let mut best: Option<(u32, f32, Vec3)> = None;
+        let mut limit = max_distance;
+        let mut stack = Vec::new();
+        if let Some(root) = self.nodes.first() {
+            if let Some(t) = root.bounds.ray(origin, inv_dir, limit) {
+                stack.push((0, t));
+            }
+        }
+        while let Some((index, entry)) = stack.pop() {
+            if entry > limit {
+                continue;
+            }
+            let node = &self.nodes[index];
+            if node.is_leaf() {
+                for &t in self.leaf(node) {
+                    let Some(tri) = corners(mesh, t) else {
+                        continue;
+                    };
+                    if let Some((distance, uv)) = ray_triangle(origin, dir, tri) {
+                        if distance <= limit {
+                            limit = distance;
+                            best = Some((t, distance, uv));
+                        }
+                    }
+                }
+                continue;
+            }
+            // Push the farther child first, so the nearer one is searched
+            // first and tightens the limit for the other
+            let mut hits = node.children().map(|child| {
+                let t = self.nodes[child].bounds.ray(origin, inv_dir, limit);
+                (child, t)
+            });
+            if hits[1].1.unwrap_or(f32::INFINITY) > hits[0].1.unwrap_or(f32::INFINITY) {
+                hits.swap(0, 1);
+            }
+            stack.extend(hits.iter().filter_map(|&(child, t)| Some((child, t?))));
+        }
+
+        let (triangle, distance, barycentric) = best?;
+        let [a, b, c] = corners(mesh, triangle)?;
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        Some(RayHit {
+            triangle,
+            distance,
+            point: origin + dir * distance,
+            normal: if normal.dot(dir) > 0.0 {
+                -normal
+            } else {
+                normal
+            },
+            barycentric,
+        })
+    }
+
+    /// Closest point on the surface within `max_distance` of `p`.
+    pub fn closest_point(&self, mesh: &Mesh, p: Vec3, max_distance: f32) -> Option<ClosestPoint> {
+        let mut best: Option<ClosestPoint> = None;
+        let mut limit = max_distance * max_distance;
+        let mut stack = Vec::new();
+        if let Some(root) = self.nodes.first() {
+            stack.push((0, root.bounds.distance_squared(p)));
+        }
+        while let Some((index, entry)) = stack.pop() {
+            if entry > limit {
+                continue;
+            }
+            let node = &self.nodes[index];
+            if node.is_leaf() {
+                for &t in self.leaf(node) {
+                    let Some(tri) = corners(mesh, t) else {
+                        continue;
+                    };
+                    let (point, barycentric) = closest_on_triangle(p, tri);
+                    let d = point.distance_squared(p);
+                    if d <= limit {
+                        limit = d;
+                        best = Some(ClosestPoint {
+                            triangle: t,
+                            distance: d.sqrt(),
+                            point,
+                            barycentric,
+                        });
+                    }
+                }
+                continue;
+            }
+            let mut near = node
+                .children()
+                .map(|child| (child, self.nodes[child].bounds.distance_squared(p)));
+            if near[1].1 > near[0].1 {
+                near.swap(0, 1);
+            }
+            stack.extend(near);
+        }
+        best
+    }
+
+    /// Every triangle whose bounds pass `visit_node`, that `visit` then
+    /// keeps.
+    fn collect(
+        &self,
+        mesh: &Mesh,
+        visit_node: impl Fn(&Aabb) -> bool,
+        visit: impl Fn([Vec3; 3]) -> bool,
+    ) -> Vec<u32> {
+        let mut found = Vec::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !visit_node(&node.bounds) {
+                continue;
+            }
+            if node.is_leaf() {
+                found.extend(
+                    self.leaf(node)
+                        .iter()
+                        .filter(|&&t| corners(mesh, t).is_some_and(&visit)),
+                );
+            } else {
+                stack.extend(node.children());
+            }
+        }
+        found
+    }
+
+    /// Triangles that touch the box.
+    pub fn overlap_aabb(&self, mesh: &Mesh, bounds: &Aabb) -> Vec<u32> {
+        self.collect(
+            mesh,
+            |node| node.intersects(bounds),
+            |tri| triangle_aabb(tri, bounds),
+        )
+    }
+
+    /// Triangles that touch the sphere.
+    pub fn overlap_sphere(&self, mesh: &Mesh, center: Vec3, radius: f32) -> Vec<u32> {
+        let r2 = radius * radius;
+        self.collect(
+            mesh,
+            |node| node.distance_squared(center) <= r2,
+            |tri| closest_on_triangle(center, tri).0.distance_squared(center) <= r2,
+        )
+    }
+
+    /// Triangles that touch the triangle with corners `tri`.
+    pub fn overlap_triangle(&self, mesh: &Mesh, tri: [Vec3; 3]) -> Vec<u32> {
+        let bounds = Aabb::from_points(tri);
+        self.collect(
+            mesh,
+            |node| node.intersects(&bounds),
+            |other| triangles_overlap(tri, other),
+        )
+    }
+
+    /// Pairs of touching triangles, the first from `mesh` and the second
+    /// from `other`, whose tree is `other_bvh`. Both meshes must be in the
+    /// same space.
+    pub fn overlap_mesh(&self, mesh: &Mesh, other_bvh: &Bvh, other: &Mesh) -> Vec<(u32, u32)> {
+        let mut pairs = Vec::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() && !other_bvh.nodes.is_empty() {
+            stack.push((0, 0));
+        }
+        while let Some((i, j)) = stack.pop() {
+            let (a, b) = (&self.nodes[i], &other_bvh.nodes[j]);
+            if !a.bounds.intersects(&b.bounds) {
+                continue;
+            }
+            match (a.is_leaf(), b.is_leaf()) {
+                (true, true) => {
+                    for &s in self.leaf(a) {
+                        let Some(first) = corners(mesh, s) else {
+                            continue;
+                        };
+                        let bounds = Aabb::from_points(first);
+                        for &t in other_bvh.leaf(b) {
+                            let Some(second) = corners(other, t) else {
+                                continue;
+                            };
+                            if bounds.intersects(&Aabb::from_points(second))
+                                && triangles_overlap(first, second)
+                            {
+                                pairs.push((s, t));
+                            }
+                        }
+                    }
+                }
+                // Descend into the larger node, so both sides shrink evenly
+                (false, b_leaf) if b_leaf || a.bounds.area() >= b.bounds.area() => {
+                    stack.extend(a.children().map(|child| (child, j)));
+                }
+                _ => stack.extend(b.children().map(|child| (i, child))),
+            }
+        }
+        pairs
+    }
+}
+
+/// Split the triangles in `order` at the cheapest of the bin borders along
+/// the longest axis of their centroids. Returns the bounds and triangle
+/// count of both halves, in the order they are left in.
+fn split(
+    order: &mut [u32],
+    bounds: &[Aabb],
+    centroids: &[Vec3],
+) -> Option<((Aabb, u32), (Aabb, u32))> {
+    let spread = Aabb::from_points(order.iter().map(|&t| centroids[t as usize]));
+    let extent = spread.max - spread.min;
+    let axis = extent.max_position();
+    if extent[axis] <= 0.0 {
+        return None;
+    }
+    let bin = |t: u32| {
+        let offset = (centroids[t as usize][axis] - spread.min[axis]) / extent[axis];
+        ((offset * BINS as f32) as usize).min(BINS - 1)
+    };
+
+    let mut bins = [(Aabb::EMPTY, 0u32); BINS];
+    for &t in order.iter() {
+        let b = &mut bins[bin(t)];
+        b.0 = b.0.union(bounds[t as usize]);
+        b.1 += 1;
+    }
+    // Bounds and count of everything left of each border, then right of it
+    let mut left = [(Aabb::EMPTY, 0u32); BINS - 1];
+    let mut acc = (Aabb::EMPTY, 0);
+    for i in 0..BINS - 1 {
+        acc = (acc.0.union(bins[i].0), acc.1 + bins[i].1);
+        left[i] = acc;
+    }
+    let mut right = [(Aabb::EMPTY, 0u32); BINS - 1];
+    acc = (Aabb::EMPTY, 0);
+    for i in (0..BINS - 1).rev() {
+        acc = (acc.0.union(bins[i + 1].0), acc.1 + bins[i + 1].1);
+        right[i] = acc;
+    }
+    // The first and last bins hold the extreme centroids, so every border
+    // leaves triangles on both sides
+    let border = (0..BINS - 1)
+        .min_by(|&i, &j| {
+            let cost = |k: usize| {
+                left[k].0.area() * left[k].1 as f32 + right[k].0.area() * right[k].1 as f32
+            };
+            cost(i).total_cmp(&cost(j))
+        })
+        .unwrap_or(0);
+
+    let mut mid = 0;
+    for i in 0..order.len() {
+        if bin(order[i]) <= border {
+            order.swap(mid, i);
+            mid += 1;
+        }
+    }
+    Some((left[border], right[border]))
+}
+
+/// Möller–Trumbore, hitting both sides of the triangle. Returns the distance
+/// and the barycentric weights of the hit.
+fn ray_triangle(origin: Vec3, dir: Vec3, [a, b, c]: [Vec3; 3]) -> Option<(f32, Vec3)> {
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    (t >= 0.0).then_some((t, Vec3::new(1.0 - u - v, u, v)))
+}
+
+/// Closest point to `p` on the triangle and its barycentric weights, by the
+/// Voronoi regions of the corners and edges (Ericson, Real-Time Collision
+/// Detection 5.1.5).
+fn closest_on_triangle(p: Vec3, [a, b, c]: [Vec3; 3]) -> (Vec3, Vec3) {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return (a, Vec3::X);
+    }
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return (b, Vec3::Y);
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return (a + ab * v, Vec3::new(1.0 - v, v, 0.0));
+    }
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return (c, Vec3::Z);
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return (a + ac * w, Vec3::new(1.0 - w, 0.0, w));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return (b + (c - b) * w, Vec3::new(0.0, 1.0 - w, w));
+    }
+    let denom = va + vb + vc;
+    if denom.abs() <= f32::MIN_POSITIVE {
+        // Degenerate triangle whose corners all failed the tests above
+        return (a, Vec3::X);
+    }
+    let v = vb / denom;
+    let w = vc / denom;
+    (a + ab * v + ac * w, Vec3::new(1.0 - v - w, v, w))
+}
+
+/// Whether `axis` separates the two point sets. Axes made from nearly
+/// parallel vectors are skipped, see [`PARALLEL_TOLERANCE`].
+fn separates(axis: Vec3, scale: f32, first: &[Vec3], second: &[Vec3]) -> bool {
+    if axis.length_squared() <= (PARALLEL_TOLERANCE * scale).powi(2) {
+        return false;
+    }
+    let range = |points: &[Vec3]| {
+        points
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| {
+                let d = axis.dot(*p);
+                (lo.min(d), hi.max(d))
+            })
+    };
+    let (lo1, hi1) = range(first);
+    let (lo2, hi2) = range(second);
+    hi1 < lo2 || hi2 < lo1
+}
+
+/// Separating axis test between two triangles. Touching counts as
+/// overlapping. Besides the face normals and the edge cross products, the
+/// in-plane edge normals are tried for coplanar triangles.
+fn triangles_overlap(first: [Vec3; 3], second: [Vec3; 3]) -> bool {
+    let edges = |[a, b, c]: [Vec3; 3]| [b - a, c - b, a - c];
+    let (e1, e2) = (edges(first), edges(second));
+    let n1 = e1[0].cross(e1[1]);
+    let n2 = e2[0].cross(e2[1]);
+    let length = |e: &[Vec3; 3]| e.iter().map(|e| e.length()).fold(0.0, f32::max);
+    let (l1, l2) = (length(&e1), length(&e2));
+
+    let faces = [(n1, l1 * l1), (n2, l2 * l2)];
+    let crossed = e1
+        .iter()
+        .flat_map(|a| e2.iter().map(move |b| (a.cross(*b), l1 * l2)));
+    let in_plane = e1
+        .iter()
+        .map(|a| (n1.cross(*a), l1 * l1 * l1))
+        .chain(e2.iter().map(|b| (n2.cross(*b), l2 * l2 * l2)));
+    !faces
+        .into_iter()
+        .chain(crossed)
+        .chain(in_plane)
+        .any(|(axis, scale)| separates(axis, scale, &first, &second))
+}
+
+/// Separating axis test between a triangle and a box (Akenine-Möller).
+fn triangle_aabb(tri: [Vec3; 3], bounds: &Aabb) -> bool {
+    let (min, max) = (bounds.min, bounds.max);
+    let corners: [Vec3; 8] = std::array::from_fn(|i| {
+        Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        )
+    });
+    let [a, b, c] = tri;
+    let edges = [b - a, c - b, a - c];
+    let length = edges.iter().map(|e| e.length()).fold(0.0, f32::max);
+
+    let box_axes = [Vec3::X, Vec3::Y, Vec3::Z];
+    let normal = (edges[0].cross(edges[1]), length * length);
+    let crossed = box_axes
+        .iter()
+        .flat_map(|axis| edges.iter().map(move |e| (axis.cross(*e), length)));
+    !box_axes
+        .iter()
+        .map(|&axis| (axis, 0.0))
+        .chain([normal])
+        .chain(crossed)
+        .any(|(axis, scale)| separates(axis, scale, &tri, &corners))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::tests::welded_cube;
+
+    /// Reproducible points in the cube from -1 to 1
+    struct Points(u64);
+
+    impl Points {
+        fn next(&mut self) -> Vec3 {
+            let mut coordinate = || {
+                self.0 = self
+                    .0
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (self.0 >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+            };
+            Vec3::new(coordinate(), coordinate(), coordinate())
+        }
+    }
+
+    /// Two smooth blobs apart from each other, so the hierarchy has empty
+    /// space to skip
+    fn scene() -> Mesh {
+        let mut blob = welded_cube();
+        for _ in 0..3 {
+            blob.subdivide();
+        }
+        blob.translate(Vec3::splat(-0.5));
+        let mut mesh = blob.clone();
+        blob.scale(0.5);
+        blob.translate(Vec3::new(0.8, 0.3, -0.2));
+        mesh.append(&blob);
+        mesh
+    }
+
+    fn triangles(mesh: &Mesh) -> impl Iterator<Item = (u32, [Vec3; 3])> + '_ {
+        (0..mesh.triangles().len() as u32).filter_map(|t| Some((t, corners(mesh, t)?)))
+    }
+
+    #[test]
+    fn ray_cast_matches_brute_force() {
+        let mesh = scene();
+        let mut points = Points(1);
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = points.next() * 1.5;
+            let dir = points.next() * 0.5 - origin;
+            let brute = triangles(&mesh)
+                .filter_map(|(_, tri)| ray_triangle(origin, dir, tri))
+                .map(|(distance, _)| distance)
+                .fold(None, |best: Option<f32>, d| {
+                    Some(best.map_or(d, |b| b.min(d)))
+                });
+            let hit = mesh.ray_cast(origin, dir, f32::INFINITY);
+            assert_eq!(hit.is_some(), brute.is_some());
+            let (Some(hit), Some(brute)) = (hit, brute) else {
+                continue;
+            };
+            hits += 1;
+            assert!((hit.distance - brute).abs() < 1e-5);
+            assert!(hit.point.abs_diff_eq(origin + dir * hit.distance, 1e-4));
+            assert!(hit.normal.dot(dir) <= 0.0);
+            let tri = corners(&mesh, hit.triangle).unwrap();
+            let b = hit.barycentric;
+            assert!((tri[0] * b.x + tri[1] * b.y + tri[2] * b.z).abs_diff_eq(hit.point, 1e-4));
+            // Nothing is hit short of the closest triangle
+            assert!(mesh.ray_cast(origin, dir, hit.distance * 0.999).is_none());
+        }
+        assert!(hits > 100, "only {} rays hit", hits);
+    }
+
+    #[test]
+    fn closest_point_matches_brute_force() {
+        let mesh = scene();
+        let mut points = Points(2);
+        for _ in 0..200 {
+            let p = points.next() * 1.5;
+            let brute = triangles(&mesh)
+                .map(|(_, tri)| closest_on_triangle(p, tri).0.distance(p))
+                .fold(f32::INFINITY, f32::min);
+            let closest = mesh.closest_point(p, f32::INFINITY).unwrap();
+            assert!((closest.distance - brute).abs() < 1e-5);
+            assert!((closest.point.distance(p) - brute).abs() < 1e-5);
+            assert!(mesh.closest_point(p, brute * 0.99).is_none());
+        }
+    }
+
+    #[test]
+    fn overlap_queries_match_brute_force() {
+        let mesh = scene();
+        let mut points = Points(3);
+        for _ in 0..50 {
+            let center = points.next();
+            let radius = 0.2 * (points.next().x + 1.0);
+            let mut found = mesh.overlap_sphere(center, radius);
+            found.sort();
+            let brute: Vec<u32> = triangles(&mesh)
+                .filter(|(_, tri)| {
+                    closest_on_triangle(center, *tri).0.distance_squared(center) <= radius * radius
+                })
+                .map(|(t, _)| t)
+                .collect();
+            assert_eq!(found, brute);
+
+            let bounds = Aabb::new(center - radius, center + radius);
+            let mut found = mesh.overlap_aabb(&bounds);
+            found.sort();
+            let brute: Vec<u32> = triangles(&mesh)
+                .filter(|(_, tri)| triangle_aabb(*tri, &bounds))
+                .map(|(t, _)| t)
+                .collect();
+            assert_eq!(found, brute);
+        }
+    }
+
+    #[test]
+    fn empty_mesh_has_no_hits() {
+        let mesh = Mesh::default();
+        assert!(mesh.bvh().bounds().is_none());
+        assert!(mesh.ray_cast(Vec3::ZERO, Vec3::X, f32::INFINITY).is_none());
+        assert!(mesh.closest_point(Vec3::ZERO, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn edits_drop_the_hierarchy() {
+        let mut mesh = welded_cube();
+        assert!(
+            mesh.ray_cast(Vec3::new(0.5, 0.5, -1.0), Vec3::Z, 10.0)
+                .is_some()
+        );
+        for p in mesh.positions_mut() {
+            *p += Vec3::X * 5.0;
+        }
+        assert!(
+            mesh.ray_cast(Vec3::new(0.5, 0.5, -1.0), Vec3::Z, 10.0)
+                .is_none()
+        );
+        assert!(
+            mesh.ray_cast(Vec3::new(5.5, 0.5, -1.0), Vec3::Z, 10.0)
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,523 @@
+//! Boolean operations on closed meshes, with BSP trees as in csg.js.
+//!
+//! Each mesh is turned into a tree of its polygons, and the polygons of one
+//! mesh are clipped against the tree of the other to keep the parts outside
+//! or inside of it. Polygons are split where planes cross them, and the new
+//! corners get normals and UVs interpolated along the split edge, so the
+//! result keeps the shading and texturing of both inputs.
+//!
+//! Works in `f64`. Points within a millionth of the size of the inputs count
+//! as lying on a plane, so faces the inputs share are merged cleanly. The
+//! inputs should be closed, otherwise there is no inside to go by.
+
+use std::collections::HashMap;
+
+use glam::{DVec2, DVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::geometry::Mesh;
+
+/// Distance from a plane, as a fraction of the size of the inputs, within
+/// which a point lies on it.
+const PLANE_TOLERANCE: f64 = 1e-6;
+
+/// Most triangles the two inputs may have together. Splitting polygons can
+/// grow the trees with the square of the input, and the operation runs on
+/// the caller's thread.
+pub const MAX_TRIANGLES: usize = 20_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BooleanOp {
+    /// Everything inside either mesh
+    #[default]
+    Union,
+    /// The first mesh with the second carved out of it
+    Difference,
+    /// Only what is inside both meshes
+    Intersection,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    pos: DVec3,
+    normal: DVec3,
+    uv: DVec2,
+}
+
+impl Vertex {
+    fn lerp(&self, other: &Vertex, t: f64) -> Vertex {
+        Vertex {
+            pos: self.pos.lerp(other.pos, t),
+            normal: self.normal.lerp(other.normal, t),
+            uv: self.uv.lerp(other.uv, t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    normal: DVec3,
+    /// Distance of the plane from the origin along `normal`
+    w: f64,
+}
+
+impl Plane {
+    fn from_points(a: DVec3, b: DVec3, c: DVec3) -> Option<Plane> {
+        let normal = (b - a).cross(c - a).try_normalize()?;
+        Some(Plane {
+            normal,
+            w: normal.dot(a),
+        })
+    }
+
+    fn flip(&mut self) {
+        self.normal = -self.normal;
+        self.w = -self.w;
+    }
+
+    fn distance(&self, p: DVec3) -> f64 {
+        self.normal.dot(p) - self.w
+    }
+}
+
+/// A convex polygon, wound counter-clockwise around its plane normal.
+#[derive(Debug, Clone)]
+struct Polygon {
+    vertices: Vec<Vertex>,
+    plane: Plane,
+}
+
+impl Polygon {
+    fn flip(&mut self) {
+        self.vertices.reverse();
+        for v in &mut self.vertices {
+            v.normal = -v.normal;
+        }
+        self.plane.flip();
+    }
+}
+
+/// Where a polygon lies relative to a plane.
+enum Split {
+    CoplanarFront(Polygon),
+    CoplanarBack(Polygon),
+    Front(Polygon),
+    Back(Polygon),
+    Spanning { front: Polygon, back: Polygon },
+}
+
+fn split(plane: &Plane, polygon: Polygon, tolerance: f64) -> Split {
+    const FRONT: u8 = 1;
+    const BACK: u8 = 2;
+    let sides: Vec<u8> = polygon
+        .vertices
+        .iter()
+        .map(|v| {
+            let d = plane.distance(v.pos);
+            if d < -tolerance {
+                BACK
+            } else if d > tolerance {
+                FRONT
+            } else {
+                0
+            }
+        })
+        .collect();
+    match sides.iter().fold(0, |all, side| all | side) {
+        0 if plane.normal.dot(polygon.plane.normal) > 0.0 => Split::CoplanarFront(polygon),
+        0 => Split::CoplanarBack(polygon),
+        FRONT => Split::Front(polygon),
+        BACK => Split::Back(polygon),
+        _ => {
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+            let n = polygon.vertices.len();
+            for i in 0..n {
+                let j = (i + 1) % n;
+                let (vi, vj) = (&polygon.vertices[i], &polygon.vertices[j]);
+                if sides[i] != BACK {
+                    front.push(*vi);
+                }
+                if sides[i] != FRONT {
+                    back.push(*vi);
+                }
+                if sides[i] | sides[j] == FRONT | BACK {
+                    let di = plane.distance(vi.pos);
+                    let t = di / (di - plane.distance(vj.pos));
+                    let v = vi.lerp(vj, t);
+                    front.push(v);
+                    back.push(v);
+                }
+            }
+            let piece = |vertices| Polygon {
+                vertices,
+                plane: polygon.plane,
+            };
+            Split::Spanning {
+                front: piece(front),
+                back: piece(back),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    plane: Option<Plane>,
+    front: Option<usize>,
+    back: Option<usize>,
+    /// Polygons lying in `plane`
+    polygons: Vec<Polygon>,
+}
+
+/// A solid as a BSP tree. Nodes are kept in a list and walked without
+/// recursion, as trees of long thin strokes get deep.
+struct Bsp {
+    nodes: Vec<Node>,
+    tolerance: f64,
+}
+
+impl Bsp {
+    fn new(polygons: Vec<Polygon>, tolerance: f64) -> Self {
+        let mut bsp = Self {
+            nodes: Vec::new(),
+            tolerance,
+        };
+        bsp.build(polygons);
+        bsp
+    }
+
+    /// Add polygons to the tree, splitting them by the planes already in it.
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        if polygons.is_empty() {
+            return;
+        }
+        if self.nodes.is_empty() {
+            self.nodes.push(Node::default());
+        }
+        let mut stack = vec![(0, polygons)];
+        while let Some((index, polygons)) = stack.pop() {
+            let plane = *self.nodes[index]
+                .plane
+                .get_or_insert(polygons[polygons.len() / 2].plane);
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+            for polygon in polygons {
+                match split(&plane, polygon, self.tolerance) {
+                    Split::CoplanarFront(p) | Split::CoplanarBack(p) => {
+                        self.nodes[index].polygons.push(p)
+                    }
+                    Split::Front(p) => front.push(p),
+                    Split::Back(p) => back.push(p),
+                    Split::Spanning { front: f, back: b } => {
+                        front.push(f);
+                        back.push(b);
+                    }
+                }
+            }
+            for (polygons, is_front) in [(front, true), (back, false)] {
+                if polygons.is_empty() {
+                    continue;
+                }
+                let node = &self.nodes[index];
+                let child = if is_front { node.front } else { node.back };
+                let child = child.unwrap_or_else(|| {
+                    self.nodes.push(Node::default());
+                    let child = self.nodes.len() - 1;
+                    let node = &mut self.nodes[index];
+                    if is_front {
+                        node.front = Some(child);
+                    } else {
+                        node.back = Some(child);
+                    }
+                    child
+                });
+                stack.push((child, polygons));
+            }
+        }
+    }
+
+    /// Swap inside and outside.
+    fn invert(&mut self) {
+        for node in &mut self.nodes {
+            for polygon in &mut node.polygons {
+                polygon.flip();
+            }
+            if let Some(plane) = &mut node.plane {
+                plane.flip();
+            }
+            std::mem::swap(&mut node.front, &mut node.back);
+        }
+    }
+
+    /// The parts of `polygons` outside this solid.
+    fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+        if self.nodes.is_empty() {
+            return polygons;
+        }
+        let mut kept = Vec::new();
+        let mut stack = vec![(0, polygons)];
+        while let Some((index, polygons)) = stack.pop() {
+            let node = &self.nodes[index];
+            let Some(plane) = node.plane else {
+                kept.extend(polygons);
+                continue;
+            };
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+            for polygon in polygons {
+                match split(&plane, polygon, self.tolerance) {
+                    Split::CoplanarFront(p) | Split::Front(p) => front.push(p),
+                    Split::CoplanarBack(p) | Split::Back(p) => back.push(p),
+                    Split::Spanning { front: f, back: b } => {
+                        front.push(f);
+                        back.push(b);
+                    }
+                }
+            }
+            match node.front {
+                Some(child) => stack.push((child, front)),
+                None => kept.extend(front),
+            }
+            // Behind a leaf is inside the solid
+            if let Some(child) = node.back {
+                stack.push((child, back));
+            }
+        }
+        kept
+    }
+
+    /// Remove the parts of this tree's polygons inside `other`.
+    fn clip_to(&mut self, other: &Bsp) {
+        for node in &mut self.nodes {
+            let polygons = std::mem::take(&mut node.polygons);
+            node.polygons = other.clip_polygons(polygons);
+        }
+    }
+
+    fn polygons(&self) -> Vec<Polygon> {
+        self.nodes
+            .iter()
+            .flat_map(|n| n.polygons.iter().cloned())
+            .collect()
+    }
+}
+
+fn polygons(mesh: &Mesh) -> Vec<Polygon> {
+    let has_normals = mesh.normals.len() == mesh.positions().len();
+    let has_uvs = mesh.uvs.len() == mesh.positions().len();
+    mesh.triangles()
+        .iter()
+        .filter(|t| t.iter().all(|&i| (i as usize) < mesh.positions().len()))
+        .filter_map(|t| {
+            let [a, b, c] = t.map(|i| mesh.positions()[i as usize].as_dvec3());
+            let plane = Plane::from_points(a, b, c)?;
+            let vertices = t
+                .iter()
+                .map(|&i| Vertex {
+                    pos: mesh.positions()[i as usize].as_dvec3(),
+                    normal: if has_normals {
+                        mesh.normals[i as usize].as_dvec3()
+                    } else {
+                        plane.normal
+                    },
+                    uv: if has_uvs {
+                        mesh.uvs[i as usize].as_dvec2()
+                    } else {
+                        DVec2::ZERO
+                    },
+                })
+                .collect();
+            Some(Polygon { vertices, plane })
+        })
+        .collect()
+}
+
+fn size(mesh: &Mesh) -> f64 {
+    if mesh.positions().is_empty() {
+        return 0.0;
+    }
+    let (min, max) = mesh
+        .positions()
+        .iter()
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        });
+    (max - min).length() as f64
+}
+
+/// Fan each convex polygon into triangles, sharing identical corners.
+fn to_mesh(polygons: &[Polygon], has_uvs: bool) -> Mesh {
+    let mut mesh = Mesh::default();
+    let mut index = HashMap::new();
+    for polygon in polygons {
+        let corners: Vec<u32> = polygon
+            .vertices
+            .iter()
+            .map(|v| {
+                let pos = v.pos.as_vec3();
+                let normal = v
+                    .normal
+                    .as_vec3()
+                    .normalize_or(polygon.plane.normal.as_vec3());
+                let uv = v.uv.as_vec2();
+                let key = (
+                    pos.to_array().map(f32::to_bits),
+                    normal.to_array().map(f32::to_bits),
+                    uv.to_array().map(f32::to_bits),
+                );
+                *index.entry(key).or_insert_with(|| {
+                    mesh.positions_mut().push(pos);
+                    mesh.normals.push(normal);
+                    if has_uvs {
+                        mesh.uvs.push(uv);
+                    }
+                    mesh.positions().len() as u32 - 1
+                })
+            })
+            .collect();
+        for k in 1..corners.len().saturating_sub(1) {
+            let triangle = [corners[0], corners[k], corners[k + 1]];
+            if triangle[0] != triangle[1]
+                && triangle[1] != triangle[2]
+                && triangle[2] != triangle[0]
+            {
+                mesh.triangles_mut().push(triangle);
+            }
+        }
+    }
+    mesh
+}
+
+/// Combine two closed meshes. Normals and UVs are carried over from the
+/// inputs, and faces of `b` that end up inside-out in a difference have
+/// their normals turned. UVs are zero on a mesh that had none.
+///
+/// Fails when the inputs have more than [`MAX_TRIANGLES`] together.
+pub fn boolean(a: &Mesh, b: &Mesh, op: BooleanOp) -> Result<Mesh, String> {
+    let count = a.triangles().len() + b.triangles().len();
+    if count > MAX_TRIANGLES {
+        return Err(format!(
+            "the inputs have {} triangles, more than the {} a boolean operation takes",
+            count, MAX_TRIANGLES
+        ));
+    }
+    let tolerance = PLANE_TOLERANCE * size(a).max(size(b)).max(f64::MIN_POSITIVE);
+    let mut a_tree = Bsp::new(polygons(a), tolerance);
+    let mut b_tree = Bsp::new(polygons(b), tolerance);
+    match op {
+        BooleanOp::Union => {
+            a_tree.clip_to(&b_tree);
+            b_tree.clip_to(&a_tree);
+            b_tree.invert();
+            b_tree.clip_to(&a_tree);
+            b_tree.invert();
+            a_tree.build(b_tree.polygons());
+        }
+        BooleanOp::Difference => {
+            a_tree.invert();
+            a_tree.clip_to(&b_tree);
+            b_tree.clip_to(&a_tree);
+            b_tree.invert();
+            b_tree.clip_to(&a_tree);
+            b_tree.invert();
+            a_tree.build(b_tree.polygons());
+            a_tree.invert();
+        }
+        BooleanOp::Intersection => {
+            a_tree.invert();
+            b_tree.clip_to(&a_tree);
+            b_tree.invert();
+            a_tree.clip_to(&b_tree);
+            b_tree.clip_to(&a_tree);
+            a_tree.build(b_tree.polygons());
+            a_tree.invert();
+        }
+    }
+    let has_uvs = a.uvs.len() == a.positions().len() || b.uvs.len() == b.positions().len();
+    Ok(to_mesh(&a_tree.polygons(), has_uvs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::tests::welded_cube;
+    use crate::repair::{RepairOptions, repair};
+
+    fn moved(mesh: &Mesh, offset: Vec3) -> Mesh {
+        let mut mesh = mesh.clone();
+        mesh.translate(offset);
+        mesh
+    }
+
+    fn volume(mesh: &Mesh) -> f32 {
+        let p = mesh.positions();
+        mesh.triangles()
+            .iter()
+            .map(|t| p[t[0] as usize].dot(p[t[1] as usize].cross(p[t[2] as usize])))
+            .sum::<f32>()
+            / 6.0
+    }
+
+    fn watertight(mesh: &Mesh) -> bool {
+        let report = repair(mesh, &RepairOptions::CHECK).1;
+        report.watertight && report.flipped_triangles == 0
+    }
+
+    #[test]
+    fn overlapping_cubes() {
+        let a = welded_cube();
+        let b = moved(&a, Vec3::splat(0.5));
+        // The cubes share an eighth of their volume
+        for (op, expected) in [
+            (BooleanOp::Union, 1.875),
+            (BooleanOp::Difference, 0.875),
+            (BooleanOp::Intersection, 0.125),
+        ] {
+            let result = boolean(&a, &b, op).unwrap();
+            assert!(watertight(&result), "{:?} is not watertight", op);
+            assert!(
+                (volume(&result) - expected).abs() < 1e-4,
+                "{:?} has volume {}",
+                op,
+                volume(&result)
+            );
+        }
+    }
+
+    #[test]
+    fn touching_cubes_merge() {
+        let a = welded_cube();
+        let b = moved(&a, Vec3::X);
+        let union = boolean(&a, &b, BooleanOp::Union).unwrap();
+        assert!(watertight(&union));
+        assert!((volume(&union) - 2.0).abs() < 1e-4);
+        assert!(
+            boolean(&a, &b, BooleanOp::Intersection)
+                .unwrap()
+                .triangles()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn disjoint_cubes() {
+        let a = welded_cube();
+        let b = moved(&a, Vec3::splat(3.0));
+        let union = boolean(&a, &b, BooleanOp::Union).unwrap();
+        assert_eq!(union.triangles().len(), 24);
+        let difference = boolean(&a, &b, BooleanOp::Difference).unwrap();
+        assert!((volume(&difference) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn inputs_over_the_budget_are_refused() {
+        let mut big = welded_cube();
+        for _ in 0..6 {
+            big.subdivide();
+        }
+        assert!(big.triangles().len() > MAX_TRIANGLES);
+        assert!(boolean(&big, &welded_cube(), BooleanOp::Union).is_err());
+    }
+}
@@ -0,0 +1,594 @@
+//! Quadric error decimation (Garland and Heckbert), for fitting sketches and
+//! imported meshes into a phone's triangle budget.
+//!
+//! Edges are collapsed half-edge style: one end vertex is removed and its
+//! triangles are moved onto the other end, which keeps its position, normal
+//! and UV. The cheapest collapse by the summed quadrics of both ends goes
+//! first.
+//!
+//! Vertices are welded by position as in [`HalfEdgeMesh`](crate::halfedge),
+//! and the split copies of a vertex are kept as its wedges. An edge is a seam
+//! when the wedges on either side of it differ, like at a UV seam or the
+//! crease around a stroke cap. Boundaries and seams get extra planes in the
+//! quadrics that hold vertices on them, and a collapse is only allowed when
+//! every wedge of the removed vertex has a counterpart at the kept one, so
+//! seams may get coarser but never move or open.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+use glam::DVec3;
+
+use crate::{geometry::Mesh, halfedge::weld};
+
+/// Weight of the planes holding vertices on boundaries and seams, against
+/// 1 for the planes of the triangles.
+const SEAM_WEIGHT: f64 = 100.0;
+
+/// Collapses that turn a triangle further than this, as the cosine of the
+/// angle between its normals before and after, are rejected. Narrow folds
+/// would slip through a plain flip check.
+const MIN_NORMAL_DOT: f64 = 0.2;
+
+/// Triangles using an edge, with their wedges at its lower and higher end
+type EdgeSides = Vec<(u32, [u32; 2])>;
+
+/// When to stop collapsing edges. Without either limit nothing is removed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecimateOptions {
+    /// Stop once the mesh has at most this many triangles
+    pub target_triangles: Option<usize>,
+    /// Skip collapses that move the surface further than this from the
+    /// original, in mesh units. The distance is the root mean square over
+    /// the planes of the original triangles merged into the kept vertex, and
+    /// of the boundaries and seams through them.
+    pub max_error: Option<f32>,
+}
+
+impl DecimateOptions {
+    /// Limits as passed over FFI, where non-positive values leave a limit
+    /// out.
+    pub fn from_limits(max_triangles: i32, max_error: f32) -> Self {
+        Self {
+            target_triangles: usize::try_from(max_triangles).ok().filter(|&n| n > 0),
+            max_error: (max_error > 0.0).then_some(max_error),
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.target_triangles.is_none() && self.max_error.is_none()
+    }
+}
+
+/// Sum of squared distances to a set of planes, as the upper triangle of a
+/// symmetric 4x4 matrix.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// Plane through `point` with unit `normal`
+    fn plane(normal: DVec3, point: DVec3, weight: f64) -> Self {
+        let [a, b, c] = normal.to_array();
+        let d = -normal.dot(point);
+        Self(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|x| x * weight),
+        )
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.0.iter_mut().zip(&other.0) {
+            *a += b;
+        }
+    }
+
+    fn error(&self, p: DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// Removing vertex `from` by moving its triangles onto `to`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Collapse {
+    cost: f64,
+    /// How far the collapse moves the surface, see
+    /// [`DecimateOptions::max_error`]
+    distance: f64,
+    from: u32,
+    to: u32,
+    /// Versions of both vertices when the cost was computed
+    stamps: [u32; 2],
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cost.total_cmp(&other.cost)
+    }
+}
+
+struct Decimator {
+    /// Welded positions
+    positions: Vec<DVec3>,
+    quadrics: Vec<Quadric>,
+    /// The same planes unweighted, and how many there are, to tell how far
+    /// a collapse moves the surface. `quadrics` weights boundaries and seams
+    /// up to steer the order of collapses.
+    surface: Vec<(Quadric, u32)>,
+    /// Bumped whenever a vertex moves or its quadric changes, so queued
+    /// collapses computed before can be told apart
+    stamps: Vec<u32>,
+    /// Triangles over wedges, `None` once collapsed away
+    triangles: Vec<Option<[u32; 3]>>,
+    /// Welded vertex of each wedge
+    vertex_of: Vec<u32>,
+    /// Triangles around each vertex, including collapsed ones until the
+    /// vertex is next touched
+    around: Vec<Vec<u32>>,
+    live: usize,
+}
+
+impl Decimator {
+    fn new(mesh: &Mesh) -> (Self, Vec<u32>) {
+        let (welded, vertex) = weld(mesh.positions());
+        let has_normals = mesh.normals.len() == mesh.positions().len();
+        let has_uvs = mesh.uvs.len() == mesh.positions().len();
+
+        // Copies of a vertex with the same attributes are one wedge
+        let mut wedge_of = HashMap::new();
+        let mut wedges = Vec::new();
+        let mut vertex_of = Vec::new();
+        let wedge: Vec<u32> = (0..mesh.positions().len())
+            .map(|i| {
+                let normal = has_normals.then(|| mesh.normals[i].to_array().map(f32::to_bits));
+                let uv = has_uvs.then(|| mesh.uvs[i].to_array().map(f32::to_bits));
+                *wedge_of.entry((vertex[i], normal, uv)).or_insert_with(|| {
+                    wedges.push(i as u32);
+                    vertex_of.push(vertex[i]);
+                    wedges.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        let triangles: Vec<Option<[u32; 3]>> = mesh
+            .triangles()
+            .iter()
+            .filter(|t| t.iter().all(|&i| (i as usize) < mesh.positions().len()))
+            .map(|t| t.map(|i| wedge[i as usize]))
+            .filter(|t| {
+                let [a, b, c] = t.map(|w| vertex_of[w as usize]);
+                a != b && b != c && c != a
+            })
+            .map(Some)
+            .collect();
+
+        let mut decimator = Self {
+            positions: welded.iter().map(|p| p.as_dvec3()).collect(),
+            quadrics: vec![Quadric::default(); welded.len()],
+            surface: vec![(Quadric::default(), 0); welded.len()],
+            stamps: vec![0; welded.len()],
+            live: triangles.len(),
+            triangles,
+            vertex_of,
+            around: vec![Vec::new(); welded.len()],
+        };
+        decimator.init_quadrics();
+        (decimator, wedges)
+    }
+
+    fn corners(&self, t: [u32; 3]) -> [DVec3; 3] {
+        t.map(|w| self.positions[self.vertex_of[w as usize] as usize])
+    }
+
+    fn init_quadrics(&mut self) {
+        let mut edges: HashMap<(u32, u32), EdgeSides> = HashMap::new();
+        for (i, t) in self.triangles.iter().enumerate() {
+            let Some(t) = *t else { continue };
+            let [a, b, c] = self.corners(t);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            let plane = Quadric::plane(normal, a, 1.0);
+            for k in 0..3 {
+                let v = self.vertex_of[t[k] as usize];
+                self.quadrics[v as usize].add(&plane);
+                let surface = &mut self.surface[v as usize];
+                surface.0.add(&plane);
+                surface.1 += 1;
+                self.around[v as usize].push(i as u32);
+
+                let (wa, wb) = (t[k], t[(k + 1) % 3]);
+                let (va, vb) = (self.vertex_of[wa as usize], self.vertex_of[wb as usize]);
+                let (key, ends) = if va < vb {
+                    ((va, vb), [wa, wb])
+                } else {
+                    ((vb, va), [wb, wa])
+                };
+                edges.entry(key).or_default().push((i as u32, ends));
+            }
+        }
+
+        for ((va, vb), sides) in edges {
+            let seam = sides.len() != 2 || sides[0].1 != sides[1].1;
+            if !seam {
+                continue;
+            }
+            let (pa, pb) = (self.positions[va as usize], self.positions[vb as usize]);
+            for (t, _) in sides {
+                let [a, b, c] = self.corners(self.triangles[t as usize].unwrap());
+                let face = (b - a).cross(c - a);
+                let normal = (pb - pa).cross(face).normalize_or_zero();
+                let plane = Quadric::plane(normal, pa, SEAM_WEIGHT);
+                let unweighted = Quadric::plane(normal, pa, 1.0);
+                for v in [va, vb] {
+                    self.quadrics[v as usize].add(&plane);
+                    let surface = &mut self.surface[v as usize];
+                    surface.0.add(&unweighted);
+                    surface.1 += 1;
+                }
+            }
+        }
+    }
+
+    /// Live triangles around vertex `v`, dropping collapsed ones.
+    fn live_around(&mut self, v: u32) -> Vec<u32> {
+        let triangles = &self.triangles;
+        self.around[v as usize].retain(|&t| triangles[t as usize].is_some());
+        self.around[v as usize].clone()
+    }
+
+    fn vertices(&self, t: u32) -> [u32; 3] {
+        self.triangles[t as usize]
+            .unwrap()
+            .map(|w| self.vertex_of[w as usize])
+    }
+
+    /// Vertices sharing an edge with `v`, with the number of triangles on
+    /// that edge.
+    fn neighbours(&mut self, v: u32) -> HashMap<u32, usize> {
+        let mut neighbours = HashMap::new();
+        for t in self.live_around(v) {
+            for n in self.vertices(t) {
+                if n != v {
+                    *neighbours.entry(n).or_insert(0) += 1;
+                }
+            }
+        }
+        neighbours
+    }
+
+    fn push(&self, heap: &mut BinaryHeap<Reverse<Collapse>>, from: u32, to: u32) {
+        let target = self.positions[to as usize];
+        let mut quadric = self.quadrics[from as usize];
+        quadric.add(&self.quadrics[to as usize]);
+        let (mut surface, from_planes) = self.surface[from as usize];
+        let (to_surface, to_planes) = self.surface[to as usize];
+        surface.add(&to_surface);
+        let planes = (from_planes + to_planes).max(1) as f64;
+        heap.push(Reverse(Collapse {
+            cost: quadric.error(target).max(0.0),
+            distance: (surface.error(target).max(0.0) / planes).sqrt(),
+            from,
+            to,
+            stamps: [self.stamps[from as usize], self.stamps[to as usize]],
+        }));
+    }
+
+    /// Wedges of `from` mapped to the wedges of `to` they merge into, or
+    /// `None` when the collapse would damage the mesh.
+    fn plan(&mut self, from: u32, to: u32) -> Option<HashMap<u32, u32>> {
+        let from_neighbours = self.neighbours(from);
+        let to_neighbours = self.neighbours(to);
+        let shared = *from_neighbours.get(&to)?;
+        // Only manifold edges, and a boundary vertex may only slide along
+        // its boundary
+        let on_boundary = from_neighbours.values().any(|&n| n == 1);
+        if shared > 2 || (on_boundary && shared != 1) {
+            return None;
+        }
+        // Link condition: the only vertices next to both ends are the tips
+        // of the triangles on the edge, otherwise the surface pinches
+        let common = from_neighbours
+            .keys()
+            .filter(|n| to_neighbours.contains_key(n))
+            .count();
+        if common != shared {
+            return None;
+        }
+
+        let mut wedges = HashMap::new();
+        let around = self.live_around(from);
+        for &t in &around {
+            let corners = self.triangles[t as usize].unwrap();
+            let vertices = self.vertices(t);
+            let Some(k) = vertices.iter().position(|&v| v == to) else {
+                continue;
+            };
+            let j = vertices.iter().position(|&v| v == from).unwrap();
+            if *wedges.entry(corners[j]).or_insert(corners[k]) != corners[k] {
+                return None;
+            }
+        }
+
+        let target = self.positions[to as usize];
+        for &t in &around {
+            let corners = self.triangles[t as usize].unwrap();
+            let vertices = self.vertices(t);
+            if vertices.contains(&to) {
+                continue;
+            }
+            let j = vertices.iter().position(|&v| v == from).unwrap();
+            // A wedge only on triangles that stay would lose its seam
+            if !wedges.contains_key(&corners[j]) {
+                return None;
+            }
+            let before = self.corners(corners);
+            let mut after = before;
+            after[j] = target;
+            let normal = |[a, b, c]: [DVec3; 3]| (b - a).cross(c - a).normalize_or_zero();
+            if normal(before).dot(normal(after)) < MIN_NORMAL_DOT {
+                return None;
+            }
+        }
+        Some(wedges)
+    }
+
+    fn collapse(&mut self, from: u32, to: u32, wedges: &HashMap<u32, u32>) {
+        for t in self.live_around(from) {
+            let corners = self.triangles[t as usize].unwrap();
+            if self.vertices(t).contains(&to) {
+                self.triangles[t as usize] = None;
+                self.live -= 1;
+            } else {
+                self.triangles[t as usize] = Some(corners.map(|w| *wedges.get(&w).unwrap_or(&w)));
+                self.around[to as usize].push(t);
+            }
+        }
+        self.around[from as usize].clear();
+        let quadric = self.quadrics[from as usize];
+        self.quadrics[to as usize].add(&quadric);
+        let (surface, planes) = self.surface[from as usize];
+        self.surface[to as usize].0.add(&surface);
+        self.surface[to as usize].1 += planes;
+        self.stamps[from as usize] += 1;
+        self.stamps[to as usize] += 1;
+    }
+
+    fn run(&mut self, options: &DecimateOptions) {
+        let target = options.target_triangles.unwrap_or(0);
+        let max_distance = options.max_error.map_or(f64::INFINITY, |e| e as f64);
+        let mut heap = BinaryHeap::new();
+        for v in 0..self.positions.len() as u32 {
+            for n in self.neighbours(v).into_keys().filter(|&n| n > v) {
+                self.push(&mut heap, v, n);
+                self.push(&mut heap, n, v);
+            }
+        }
+
+        while self.live > target {
+            let Some(Reverse(next)) = heap.pop() else {
+                break;
+            };
+            let (from, to) = (next.from as usize, next.to as usize);
+            if next.stamps != [self.stamps[from], self.stamps[to]] {
+                continue;
+            }
+            // The cheapest collapses are not always the shortest moves, so
+            // keep looking past one that goes too far
+            if next.distance > max_distance {
+                continue;
+            }
+            let Some(wedges) = self.plan(next.from, next.to) else {
+                continue;
+            };
+            self.collapse(next.from, next.to, &wedges);
+            // Every collapse queued for `to` is stale now
+            for n in self.neighbours(next.to).into_keys() {
+                self.push(&mut heap, next.to, n);
+                self.push(&mut heap, n, next.to);
+            }
+        }
+    }
+}
+
+/// Collapse edges of `mesh` until it is down to `target_triangles`, or no
+/// edge can be collapsed within `max_error` without opening a boundary or
+/// seam, flipping a triangle or pinching the surface.
+pub fn decimate(mesh: &Mesh, options: &DecimateOptions) -> Mesh {
+    if options.is_none() || mesh.triangles().is_empty() {
+        return mesh.clone();
+    }
+    let (mut decimator, wedges) = Decimator::new(mesh);
+    decimator.run(options);
+
+    let mut index = HashMap::new();
+    let mut result = Mesh::default();
+    let has_normals = mesh.normals.len() == mesh.positions().len();
+    let has_uvs = mesh.uvs.len() == mesh.positions().len();
+    for t in decimator.triangles.iter().flatten() {
+        let corners = t.map(|w| {
+            *index.entry(w).or_insert_with(|| {
+                let source = wedges[w as usize] as usize;
+                let vertex = decimator.vertex_of[w as usize] as usize;
+                result
+                    .positions_mut()
+                    .push(decimator.positions[vertex].as_vec3());
+                if has_normals {
+                    result.normals.push(mesh.normals[source]);
+                }
+                if has_uvs {
+                    result.uvs.push(mesh.uvs[source]);
+                }
+                result.positions().len() as u32 - 1
+            })
+        });
+        result.triangles_mut().push(corners);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::tests::welded_cube;
+    use crate::repair::{RepairOptions, repair};
+    use glam::{Vec2, Vec3};
+
+    /// Smooth closed blob from subdividing the cube
+    fn blob() -> Mesh {
+        let mut mesh = welded_cube();
+        for _ in 0..3 {
+            mesh.subdivide();
+        }
+        mesh
+    }
+
+    /// Flat square of `n` by `n` quads in the XY plane
+    fn grid(n: u32) -> Mesh {
+        let mut positions = Vec::new();
+        for y in 0..=n {
+            for x in 0..=n {
+                positions.push(Vec3::new(x as f32, y as f32, 0.0) / n as f32);
+            }
+        }
+        let mut triangles = Vec::new();
+        for y in 0..n {
+            for x in 0..n {
+                let i = y * (n + 1) + x;
+                triangles.push([i, i + 1, i + n + 2]);
+                triangles.push([i, i + n + 2, i + n + 1]);
+            }
+        }
+        Mesh::new(positions, Vec::new(), Vec::new(), triangles)
+    }
+
+    fn area(mesh: &Mesh) -> f32 {
+        mesh.triangles()
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| mesh.positions()[i as usize]);
+                (b - a).cross(c - a).length() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn closed_mesh_stays_closed() {
+        let blob = blob();
+        assert!(repair(&blob, &RepairOptions::CHECK).1.watertight);
+        for target in [500, 100, 10] {
+            let options = DecimateOptions {
+                target_triangles: Some(target),
+                max_error: None,
+            };
+            let decimated = decimate(&blob, &options);
+            assert!(decimated.triangles().len() < blob.triangles().len());
+            let report = repair(&decimated, &RepairOptions::CHECK).1;
+            assert!(report.watertight, "{} triangles: {:?}", target, report);
+        }
+    }
+
+    #[test]
+    fn boundary_stays_one_loop() {
+        let grid = grid(8);
+        let options = DecimateOptions {
+            target_triangles: Some(2),
+            max_error: None,
+        };
+        let decimated = decimate(&grid, &options);
+        assert!(decimated.triangles().len() < grid.triangles().len());
+        let report = repair(&decimated, &RepairOptions::CHECK).1;
+        assert_eq!(report.holes.len(), 1);
+        assert_eq!(report.non_manifold_edges, 0);
+    }
+
+    #[test]
+    fn max_error_is_a_distance() {
+        // Flat areas collapse at no distance at all, while the outline is
+        // held by the boundary planes
+        let flat = DecimateOptions {
+            target_triangles: None,
+            max_error: Some(1e-6),
+        };
+        let grid = grid(8);
+        let decimated = decimate(&grid, &flat);
+        assert!(decimated.triangles().len() < grid.triangles().len() / 2);
+        assert!((area(&decimated) - 1.0).abs() < 1e-5);
+
+        // A tiny distance keeps the curved blob as it is, a large one lets
+        // it go down to a few triangles
+        let blob = blob();
+        assert_eq!(
+            decimate(&blob, &flat).triangles().len(),
+            blob.triangles().len()
+        );
+        let coarse = DecimateOptions {
+            target_triangles: None,
+            max_error: Some(1.0),
+        };
+        assert!(decimate(&blob, &coarse).triangles().len() < blob.triangles().len() / 10);
+    }
+
+    #[test]
+    fn uv_seams_stay_split() {
+        // Give the two halves of the grid UVs that jump at x = 0.5
+        let grid = grid(8);
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut triangles = Vec::new();
+        for t in grid.triangles() {
+            let corners = t.map(|i| grid.positions()[i as usize]);
+            let left = corners.iter().map(|p| p.x).sum::<f32>() < 1.5;
+            let base = positions.len() as u32;
+            for p in corners {
+                positions.push(p);
+                uvs.push(if left {
+                    p.truncate()
+                } else {
+                    p.truncate() + Vec2::X
+                });
+            }
+            triangles.push([base, base + 1, base + 2]);
+        }
+        let seamed = Mesh::new(positions, Vec::new(), uvs, triangles);
+        let options = DecimateOptions {
+            target_triangles: None,
+            max_error: Some(1e-6),
+        };
+        let decimated = decimate(&seamed, &options);
+        assert!(decimated.triangles().len() < seamed.triangles().len() / 2);
+        for t in decimated.triangles() {
+            let [a, b, c] = t.map(|i| decimated.uvs[i as usize]);
+            let width = a.x.max(b.x).max(c.x) - a.x.min(b.x).min(c.x);
+            assert!(width <= 0.5 + 1e-5, "triangle spans the seam");
+        }
+        assert!((area(&decimated) - 1.0).abs() < 1e-5);
+    }
+}
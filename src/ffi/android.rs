@@ -1,12 +1,12 @@
-use crate::AppInitOpts;
 use crate::android_asset_io::AndroidAssetManager;
 use crate::app_view::{AndroidViewObj, NativeWindow};
+use crate::decimate::DecimateOptions;
 use android_logger::Config;
 use bevy::input::ButtonState;
 use bevy::prelude::*;
 use jni::JNIEnv;
-use jni::objects::JString;
-use jni::sys::{jfloat, jint, jlong, jobject, jstring};
+use jni::objects::{JByteArray, JString};
+use jni::sys::{jboolean, jbyteArray, jfloat, jint, jlong, jobject, jstring};
 use jni_fn::jni_fn;
 use log::LevelFilter;
 
@@ -58,7 +58,16 @@ pub fn create_bevy_app(
     };
 
     log::info!("Creating Bevy App with options: {}", rust_str);
-    let state: AppInitOpts = serde_json::from_str(rust_str.as_str()).unwrap();
+    let state = match crate::validation::parse_app_opts(rust_str.as_str()) {
+        Ok(state) => state,
+        Err(errors) => {
+            for e in &errors {
+                log::error!("Invalid app options: {}", e);
+            }
+            // The host can call `validate_opts` to get the full error list
+            return 0;
+        }
+    };
     log::info!("DEC state {:?}", state);
 
     let mut bevy_app = crate::create_breakout_app(AndroidAssetManager(a_asset_manager), state);
@@ -69,6 +78,18 @@ pub fn create_bevy_app(
     Box::into_raw(Box::new(bevy_app)) as jlong
 }
 
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn validate_opts(mut env: JNIEnv, _: jobject, opts: jstring) -> jstring {
+    let jstr = unsafe { JString::from_raw(opts) };
+    let rust_str: String = env.get_string(&jstr).expect("Failed to get string").into();
+    let res = crate::validation::parse_app_opts(rust_str.as_str()).map(|_| ());
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
 #[unsafe(no_mangle)]
 #[jni_fn("name.renderer.bevy.RustBridge")]
 pub fn enter_frame(_env: *mut JNIEnv, _: jobject, obj: jlong) {
@@ -114,25 +135,114 @@ pub fn switch_mode(_env: *mut JNIEnv, _: jobject, obj: jlong, x: jint) {
     crate::switch_mode(app, x as u32);
 }
 
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_gizmo_mode(_env: *mut JNIEnv, _: jobject, obj: jlong, mode: jint) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::set_gizmo_mode(app, mode as u32);
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_gizmo_snap(_env: *mut JNIEnv, _: jobject, obj: jlong, grid: jfloat, angle: jfloat) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::set_gizmo_snap(app, grid as f32, angle as f32);
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_stroke_settings(mut env: JNIEnv, _: jobject, obj: jlong, settings: jstring) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let jstr = unsafe { JString::from_raw(settings) };
+    let rust_str: String = env.get_string(&jstr).expect("Failed to get string").into();
+    let res = crate::set_stroke_settings(app, rust_str.as_str());
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_work_plane(
+    env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    origin: jni::sys::jfloatArray,
+    normal: jni::sys::jfloatArray,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let read = |arr: jni::sys::jfloatArray, out: &mut [f32]| {
+        let arr = unsafe { jni::objects::JFloatArray::from_raw(arr) };
+        env.get_float_array_region(&arr, 0, out)
+    };
+    let (mut o, mut n) = ([0.0; 3], [0.0; 3]);
+    let res = match read(origin, &mut o).and_then(|_| read(normal, &mut n)) {
+        Ok(()) => crate::set_work_plane(app, Vec3::from_array(o), Vec3::from_array(n)),
+        Err(e) => Err(format!("expected origin[3] and normal[3]: {}", e)),
+    };
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
 #[unsafe(no_mangle)]
 #[jni_fn("name.renderer.bevy.RustBridge")]
 pub fn device_touch_move(_env: *mut JNIEnv, _: jobject, obj: jlong, x: jfloat, y: jfloat) {
     let app = unsafe { &mut *(obj as *mut App) };
-    crate::change_touch(app, Some(vec2(x as f32, y as f32)));
+    crate::pointer_move(app, 0, vec2(x as f32, y as f32), 1.0);
 }
 
 #[unsafe(no_mangle)]
 #[jni_fn("name.renderer.bevy.RustBridge")]
 pub fn device_enter_touch(_env: *mut JNIEnv, _: jobject, obj: jlong, x: jfloat, y: jfloat) {
     let app = unsafe { &mut *(obj as *mut App) };
-    crate::touch_enter(app, Vec2::new(x as f32, y as f32));
+    crate::pointer_down(app, 0, Vec2::new(x as f32, y as f32), 1.0);
 }
 
 #[unsafe(no_mangle)]
 #[jni_fn("name.renderer.bevy.RustBridge")]
 pub fn device_exit_touch(_env: *mut JNIEnv, _: jobject, obj: jlong) {
     let app = unsafe { &mut *(obj as *mut App) };
-    crate::touch_exit(app);
+    crate::pointer_up(app, 0);
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn device_pointer_down(
+    _env: *mut JNIEnv,
+    _: jobject,
+    obj: jlong,
+    id: jint,
+    x: jfloat,
+    y: jfloat,
+    pressure: jfloat,
+) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::pointer_down(app, id as u64, vec2(x as f32, y as f32), pressure as f32);
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn device_pointer_move(
+    _env: *mut JNIEnv,
+    _: jobject,
+    obj: jlong,
+    id: jint,
+    x: jfloat,
+    y: jfloat,
+    pressure: jfloat,
+) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::pointer_move(app, id as u64, vec2(x as f32, y as f32), pressure as f32);
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn device_pointer_up(_env: *mut JNIEnv, _: jobject, obj: jlong, id: jint) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::pointer_up(app, id as u64);
 }
 
 #[unsafe(no_mangle)]
@@ -146,6 +256,409 @@ pub fn get_mesh(env: JNIEnv, _: jobject, obj: jlong) -> jstring {
     java_str.into_raw()
 }
 
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn get_scene(env: JNIEnv, _: jobject, obj: jlong) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let rust_str = crate::get_scene(app);
+    let java_str = env
+        .new_string(rust_str.as_str())
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+/// `max_triangles` and `max_error` decimate as in [`export_sketch_mesh`].
+/// Normals are generated with the `shading` JSON, or kept from the file when
+/// it is null.
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn import_mesh(
+    mut env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    file: jbyteArray,
+    max_triangles: jint,
+    max_error: jfloat,
+    shading: jstring,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let file = unsafe { JByteArray::from_raw(file) };
+    let decimate = DecimateOptions::from_limits(max_triangles, max_error);
+    let shading = match parse_shading(&mut env, shading) {
+        Ok(shading) => shading,
+        Err(errors) => {
+            let java_str = env
+                .new_string(crate::ffi_result_json::<(), _>(&Err(errors)))
+                .expect("Couldn't create Java string");
+            return java_str.into_raw();
+        }
+    };
+    let rust_str = match env.convert_byte_array(&file) {
+        Ok(bytes) => crate::ffi_result_json(&crate::import_mesh(app, &bytes, &decimate, shading)),
+        Err(e) => {
+            log::error!("Failed to read mesh bytes: {}", e);
+            let error = crate::file_io::ImportError::Read {
+                message: e.to_string(),
+            };
+            crate::ffi_result_json::<(), _>(&Err(error))
+        }
+    };
+    let java_str = env
+        .new_string(rust_str.as_str())
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn undo(_env: *mut JNIEnv, _: jobject, obj: jlong) -> jboolean {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::undo(app) as jboolean
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn redo(_env: *mut JNIEnv, _: jobject, obj: jlong) -> jboolean {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::redo(app) as jboolean
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn can_undo(_env: *mut JNIEnv, _: jobject, obj: jlong) -> jboolean {
+    let app = unsafe { &*(obj as *mut App) };
+    crate::can_undo(app) as jboolean
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn can_redo(_env: *mut JNIEnv, _: jobject, obj: jlong) -> jboolean {
+    let app = unsafe { &*(obj as *mut App) };
+    crate::can_redo(app) as jboolean
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn get_selection(env: JNIEnv, _: jobject, obj: jlong) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res: Result<_, ()> = Ok(crate::get_selection(app));
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn list_objects(env: JNIEnv, _: jobject, obj: jlong) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res: Result<_, ()> = Ok(crate::list_objects(app));
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_object_transform(
+    env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    id: jint,
+    pos: jni::sys::jfloatArray,
+    rotation: jni::sys::jfloatArray,
+    scale: jni::sys::jfloatArray,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let read = |arr: jni::sys::jfloatArray, out: &mut [f32]| {
+        let arr = unsafe { jni::objects::JFloatArray::from_raw(arr) };
+        env.get_float_array_region(&arr, 0, out)
+    };
+    let (mut p, mut r, mut s) = ([0.0; 3], [0.0; 4], [0.0; 3]);
+    let res = match read(pos, &mut p)
+        .and_then(|_| read(rotation, &mut r))
+        .and_then(|_| read(scale, &mut s))
+    {
+        Ok(()) => {
+            let transform = Transform::from_translation(Vec3::from_array(p))
+                .with_rotation(Quat::from_array(r).normalize())
+                .with_scale(Vec3::from_array(s));
+            crate::edit_object(
+                app,
+                id as u32,
+                crate::scene3d::ObjectEdit::Transform(transform),
+            )
+        }
+        Err(e) => Err(format!("expected pos[3], rotation[4] and scale[3]: {}", e)),
+    };
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_object_material(
+    mut env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    id: jint,
+    material: jstring,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let jstr = unsafe { JString::from_raw(material) };
+    let rust_str: String = env.get_string(&jstr).expect("Failed to get string").into();
+    let rust_str = match crate::parse_material(rust_str.as_str()) {
+        Ok(m) => crate::ffi_result_json(&crate::edit_object(
+            app,
+            id as u32,
+            crate::scene3d::ObjectEdit::Material(m),
+        )),
+        Err(errors) => crate::ffi_result_json::<(), _>(&Err(errors)),
+    };
+    let java_str = env
+        .new_string(rust_str.as_str())
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_object_visible(
+    env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    id: jint,
+    visible: jboolean,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res = crate::edit_object(
+        app,
+        id as u32,
+        crate::scene3d::ObjectEdit::Visible(visible != 0),
+    );
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn duplicate_object(env: JNIEnv, _: jobject, obj: jlong, id: jint) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res = crate::duplicate_object(app, id as u32);
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn delete_object(env: JNIEnv, _: jobject, obj: jlong, id: jint) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res = crate::edit_object(app, id as u32, crate::scene3d::ObjectEdit::Delete);
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+/// `op` is 0 for union, 1 to carve `tool` out of `target` and 2 for
+/// intersection.
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn boolean_objects(
+    env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    target: jint,
+    tool: jint,
+    op: jint,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res = match u32::try_from(op) {
+        Ok(op) => crate::boolean_objects(app, target as u32, tool as u32, op),
+        Err(_) => Err(format!("unknown boolean op {}", op)),
+    };
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn list_layers(env: JNIEnv, _: jobject, obj: jlong) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res: Result<_, ()> = Ok(crate::list_layers(app));
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+/// `name` may be null for a default name.
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn add_layer(mut env: JNIEnv, _: jobject, obj: jlong, name: jstring) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let jstr = unsafe { JString::from_raw(name) };
+    let name: Option<String> = if jstr.is_null() {
+        None
+    } else {
+        Some(env.get_string(&jstr).expect("Failed to get string").into())
+    };
+    let res: Result<_, ()> = Ok(crate::add_layer(app, name));
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_active_layer(env: JNIEnv, _: jobject, obj: jlong, id: jint) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res = crate::set_active_layer(app, id as u32);
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_layer_visible(
+    env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    id: jint,
+    visible: jboolean,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res = crate::set_layer_visible(app, id as u32, visible != 0);
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn set_layer_locked(
+    env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    id: jint,
+    locked: jboolean,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res = crate::set_layer_locked(app, id as u32, locked != 0);
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+/// A negative `layer` exports every layer.
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn export_strokes(env: JNIEnv, _: jobject, obj: jlong, layer: jint) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let res = crate::export_strokes(app, u32::try_from(layer).ok());
+    let java_str = env
+        .new_string(crate::ffi_result_json(&res))
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+/// Repair options JSON from the host. `null` gives `None`.
+fn parse_repair(
+    env: &mut JNIEnv,
+    repair: jstring,
+) -> Result<Option<crate::repair::RepairOptions>, Vec<crate::validation::ValidationError>> {
+    let jstr = unsafe { JString::from_raw(repair) };
+    if jstr.is_null() {
+        return Ok(None);
+    }
+    let rust_str: String = env.get_string(&jstr).expect("Failed to get string").into();
+    crate::parse_repair(rust_str.as_str()).map(Some)
+}
+
+/// Shading mode JSON from the host. `null` gives `None`.
+fn parse_shading(
+    env: &mut JNIEnv,
+    shading: jstring,
+) -> Result<Option<crate::geometry::Shading>, Vec<crate::validation::ValidationError>> {
+    let jstr = unsafe { JString::from_raw(shading) };
+    if jstr.is_null() {
+        return Ok(None);
+    }
+    let rust_str: String = env.get_string(&jstr).expect("Failed to get string").into();
+    crate::parse_shading(rust_str.as_str()).map(Some)
+}
+
+/// A negative `layer` exports every layer. The mesh is repaired first when
+/// `repair` is not null. Non-positive `max_triangles` and `max_error` leave
+/// that limit out, and without either nothing is decimated.
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn export_sketch_mesh(
+    mut env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    layer: jint,
+    max_triangles: jint,
+    max_error: jfloat,
+    repair: jstring,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let decimate = DecimateOptions::from_limits(max_triangles, max_error);
+    let rust_str = match parse_repair(&mut env, repair) {
+        Ok(repair) => crate::ffi_result_json(&crate::export_sketch_mesh(
+            app,
+            u32::try_from(layer).ok(),
+            repair.as_ref(),
+            &decimate,
+        )),
+        Err(errors) => crate::ffi_result_json::<(), _>(&Err(errors)),
+    };
+    let java_str = env
+        .new_string(rust_str.as_str())
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
+/// Report on the mesh [`export_sketch_mesh`] would export with the same
+/// `layer` and `repair`. A null `repair` uses the default options.
+#[unsafe(no_mangle)]
+#[jni_fn("name.renderer.bevy.RustBridge")]
+pub fn check_sketch_mesh(
+    mut env: JNIEnv,
+    _: jobject,
+    obj: jlong,
+    layer: jint,
+    repair: jstring,
+) -> jstring {
+    let app = unsafe { &mut *(obj as *mut App) };
+    let rust_str = match parse_repair(&mut env, repair) {
+        Ok(repair) => crate::ffi_result_json(&crate::check_sketch_mesh(
+            app,
+            u32::try_from(layer).ok(),
+            &repair.unwrap_or_default(),
+        )),
+        Err(errors) => crate::ffi_result_json::<(), _>(&Err(errors)),
+    };
+    let java_str = env
+        .new_string(rust_str.as_str())
+        .expect("Couldn't create Java string");
+    java_str.into_raw()
+}
+
 #[unsafe(no_mangle)]
 #[jni_fn("name.renderer.bevy.RustBridge")]
 pub fn release_bevy_app(_env: *mut JNIEnv, _: jobject, obj: jlong) {
@@ -1,12 +1,5 @@
 use crate::app_view::{IOSViewObj, create_bevy_window};
-use bevy::{
-    ecs::system::SystemState,
-    input::{
-        ButtonState,
-        touch::{TouchInput, TouchPhase},
-    },
-    prelude::*,
-};
+use bevy::{input::ButtonState, prelude::*};
 
 #[unsafe(no_mangle)]
 pub fn create_bevy_app(view: *mut objc::runtime::Object, scale_factor: f32) -> *mut libc::c_void {
@@ -29,41 +22,54 @@ pub fn enter_frame(obj: *mut libc::c_void) {
     app.update();
 }
 
+/// `id` tells the fingers of a multi-touch gesture apart, e.g. the address
+/// of the `UITouch`. `force` is its normalized force, or 1 without 3D Touch.
 #[unsafe(no_mangle)]
-pub fn touch_started(obj: *mut libc::c_void, x: f32, y: f32) {
-    touched(obj, TouchPhase::Started, Vec2::new(x, y));
+pub fn touch_started(obj: *mut libc::c_void, id: u64, x: f32, y: f32, force: f32) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::pointer_down(app, id, Vec2::new(x, y), force);
 }
 
 #[unsafe(no_mangle)]
-pub fn touch_moved(obj: *mut libc::c_void, x: f32, y: f32) {
-    touched(obj, TouchPhase::Moved, Vec2::new(x, y));
+pub fn touch_moved(obj: *mut libc::c_void, id: u64, x: f32, y: f32, force: f32) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::pointer_move(app, id, Vec2::new(x, y), force);
 }
 
 #[unsafe(no_mangle)]
-pub fn touch_ended(obj: *mut libc::c_void, x: f32, y: f32) {
-    touched(obj, TouchPhase::Ended, Vec2::new(x, y));
+pub fn touch_ended(obj: *mut libc::c_void, id: u64) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::pointer_up(app, id);
 }
 
 #[unsafe(no_mangle)]
-pub fn touch_cancelled(obj: *mut libc::c_void, x: f32, y: f32) {
-    touched(obj, TouchPhase::Canceled, Vec2::new(x, y));
+pub fn touch_cancelled(obj: *mut libc::c_void, id: u64) {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::pointer_up(app, id);
 }
 
-fn touched(obj: *mut libc::c_void, phase: TouchPhase, position: Vec2) {
+#[unsafe(no_mangle)]
+pub fn undo(obj: *mut libc::c_void) -> bool {
     let app = unsafe { &mut *(obj as *mut App) };
-    let mut windows_system_state: SystemState<Query<(Entity, &Window)>> =
-        SystemState::from_world(app.world_mut());
-    let (entity, _) = windows_system_state.get(app.world_mut()).single().unwrap();
-
-    let touch = TouchInput {
-        window: entity,
-        phase,
-        position,
-        force: None,
-        id: 0,
-    };
-
-    app.world_mut().send_event(touch);
+    crate::undo(app)
+}
+
+#[unsafe(no_mangle)]
+pub fn redo(obj: *mut libc::c_void) -> bool {
+    let app = unsafe { &mut *(obj as *mut App) };
+    crate::redo(app)
+}
+
+#[unsafe(no_mangle)]
+pub fn can_undo(obj: *mut libc::c_void) -> bool {
+    let app = unsafe { &*(obj as *mut App) };
+    crate::can_undo(app)
+}
+
+#[unsafe(no_mangle)]
+pub fn can_redo(obj: *mut libc::c_void) -> bool {
+    let app = unsafe { &*(obj as *mut App) };
+    crate::can_redo(app)
 }
 
 #[unsafe(no_mangle)]
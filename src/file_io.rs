@@ -1,6 +1,9 @@
 use crate::geometry::Mesh as GMesh;
+use crate::halfedge::HalfEdgeMesh;
+use base64::Engine;
 use bevy::asset::RenderAssetUsages;
-use glam::{Vec2, Vec3};
+use glam::{Affine3A, Mat4, Vec2, Vec3};
+use serde::Serialize;
 use std::fmt::Write;
 use tobj::{self, LoadOptions};
 
@@ -14,7 +17,7 @@ pub fn to_bevy_mesh(mesh: &GMesh) -> Mesh {
     );
 
     // Convert positions to Vec<[f32; 3]>
-    let positions: Vec<[f32; 3]> = mesh.positions.iter().map(|v| [v.x, v.y, v.z]).collect();
+    let positions: Vec<[f32; 3]> = mesh.positions().iter().map(|v| [v.x, v.y, v.z]).collect();
     let vertex_count = positions.len();
     // Use provided normals or a default
     let normals: Vec<[f32; 3]> = if !mesh.normals.is_empty() {
@@ -41,16 +44,53 @@ pub fn to_bevy_mesh(mesh: &GMesh) -> Mesh {
     }
 
     // Set indices
-    let indices: Vec<u32> = mesh.triangles.iter().flat_map(|tri| tri.to_vec()).collect();
+    let indices: Vec<u32> = mesh
+        .triangles()
+        .iter()
+        .flat_map(|tri| tri.to_vec())
+        .collect();
     bevy_mesh.insert_indices(Indices::U32(indices));
 
     bevy_mesh
 }
 
+/// Read a triangle list Bevy mesh back, `None` for other topologies or
+/// meshes without positions.
+pub fn from_bevy_mesh(mesh: &Mesh) -> Option<GMesh> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let vec3s = |values: &[[f32; 3]]| values.iter().map(|v| Vec3::from_array(*v)).collect();
+    let positions: Vec<Vec3> = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+        VertexAttributeValues::Float32x3(p) => vec3s(p),
+        _ => return None,
+    };
+    let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        Some(VertexAttributeValues::Float32x3(n)) => vec3s(n),
+        _ => Vec::new(),
+    };
+    let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+        Some(VertexAttributeValues::Float32x2(uv)) => {
+            uv.iter().map(|v| Vec2::from_array(*v)).collect()
+        }
+        _ => Vec::new(),
+    };
+    let indices: Vec<u32> = match mesh.indices() {
+        Some(Indices::U16(idx)) => idx.iter().map(|&i| i as u32).collect(),
+        Some(Indices::U32(idx)) => idx.clone(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    let triangles = indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    Some(GMesh::new(positions, normals, uvs, triangles))
+}
+
 pub fn export_obj_to_string(mesh: &GMesh) -> String {
     let mut output = String::new();
 
-    for p in &mesh.positions {
+    for p in mesh.positions() {
         writeln!(output, "v {} {} {}", p.x, p.y, p.z).unwrap();
     }
 
@@ -65,7 +105,7 @@ pub fn export_obj_to_string(mesh: &GMesh) -> String {
     let has_uv = !mesh.uvs.is_empty();
     let has_normals = !mesh.normals.is_empty();
 
-    for face in &mesh.triangles {
+    for face in mesh.triangles() {
         let f = |i: u32| {
             let vi = i + 1; // OBJ indices are 1-based
             match (has_uv, has_normals) {
@@ -82,23 +122,30 @@ pub fn export_obj_to_string(mesh: &GMesh) -> String {
     output
 }
 
-pub fn load_obj(data: String) -> Result<GMesh, String> {
+/// The first model of an OBJ file, with one index per vertex for the
+/// position, normal and UV alike.
+fn parse_obj(data: &str, triangulate: bool) -> Result<tobj::Mesh, String> {
     let (models, _) = tobj::load_obj_buf(
         &mut data.as_bytes(),
         &LoadOptions {
-            triangulate: true,
-            single_index: false,
+            triangulate,
+            // Normals and UVs are per vertex in our meshes
+            single_index: true,
             ..Default::default()
         },
         |_| Ok((Vec::new(), std::collections::HashMap::new())),
     )
     .map_err(|e| format!("Failed to load OBJ: {}", e))?;
 
-    if models.is_empty() {
-        return Err("OBJ file contains no geometry.".to_string());
-    }
+    models
+        .into_iter()
+        .next()
+        .map(|model| model.mesh)
+        .ok_or_else(|| "OBJ file contains no geometry.".to_string())
+}
 
-    let mesh_data = &models[0].mesh;
+pub fn load_obj(data: String) -> Result<GMesh, String> {
+    let mesh_data = &parse_obj(&data, true)?;
 
     // Convert positions
     let mut positions = Vec::new();
@@ -130,10 +177,763 @@ pub fn load_obj(data: String) -> Result<GMesh, String> {
         }
     }
 
-    Ok(GMesh {
-        positions,
-        normals,
-        uvs,
-        triangles,
-    })
+    let mut mesh = GMesh::new(positions, normals, uvs, triangles);
+    if mesh.normals.len() != mesh.positions().len() {
+        mesh.recalculate_normals();
+    }
+    Ok(mesh)
+}
+
+/// Load an OBJ file keeping its quads and other polygons, for subdivision.
+pub fn load_obj_polygons(data: &str) -> Result<HalfEdgeMesh, String> {
+    let mesh_data = parse_obj(data, false)?;
+    let positions: Vec<Vec3> = mesh_data
+        .positions
+        .chunks(3)
+        .map(Vec3::from_slice)
+        .collect();
+    let normals: Vec<Vec3> = mesh_data.normals.chunks(3).map(Vec3::from_slice).collect();
+    let uvs: Vec<Vec2> = mesh_data
+        .texcoords
+        .chunks(2)
+        .map(Vec2::from_slice)
+        .collect();
+
+    // Without arities every face is a triangle
+    let mut faces = Vec::new();
+    let mut indices = mesh_data.indices.as_slice();
+    if mesh_data.face_arities.is_empty() {
+        faces.extend(indices.chunks_exact(3).map(<[u32]>::to_vec));
+    }
+    for &arity in &mesh_data.face_arities {
+        let (face, rest) = indices.split_at(arity as usize);
+        faces.push(face.to_vec());
+        indices = rest;
+    }
+    Ok(HalfEdgeMesh::from_polygons(
+        &positions, &normals, &uvs, &faces,
+    ))
+}
+
+/// Mesh file formats understood by [`load_mesh_bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeshFormat {
+    Obj,
+    StlAscii,
+    StlBinary,
+    Ply,
+    Gltf,
+    Glb,
+}
+
+/// Why a mesh payload could not be imported.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImportError {
+    /// The payload was empty.
+    Empty,
+    /// The payload could not be handed over from the host.
+    Read { message: String },
+    /// The payload did not look like any supported format.
+    UnknownFormat,
+    /// The format was recognised but the data is malformed.
+    Parse { format: MeshFormat, message: String },
+    /// The file parsed but contains no triangles.
+    NoGeometry { format: MeshFormat },
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Empty => write!(f, "mesh payload is empty"),
+            ImportError::Read { message } => write!(f, "failed to read mesh payload: {}", message),
+            ImportError::UnknownFormat => write!(f, "unrecognised mesh format"),
+            ImportError::Parse { format, message } => {
+                write!(f, "failed to parse {:?}: {}", format, message)
+            }
+            ImportError::NoGeometry { format } => {
+                write!(f, "{:?} file contains no geometry", format)
+            }
+        }
+    }
+}
+
+/// Guess the format of a mesh file from its contents.
+pub fn detect_format(bytes: &[u8]) -> Option<MeshFormat> {
+    if bytes.starts_with(b"glTF") {
+        return Some(MeshFormat::Glb);
+    }
+    // Binary STL headers may start with "solid" too, so check the size first.
+    if bytes.len() >= 84 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if count > 0 && stl_binary_len(count) == Some(bytes.len()) {
+            return Some(MeshFormat::StlBinary);
+        }
+    }
+    if bytes.starts_with(b"ply") {
+        return Some(MeshFormat::Ply);
+    }
+
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]);
+    let head = head.trim_start();
+    if head.starts_with("solid") && head.contains("facet") {
+        return Some(MeshFormat::StlAscii);
+    }
+    if head.starts_with('{') && head.contains("\"asset\"") {
+        return Some(MeshFormat::Gltf);
+    }
+    if head
+        .lines()
+        .any(|l| l.starts_with("v ") || l.starts_with("f ") || l.starts_with("o "))
+    {
+        return Some(MeshFormat::Obj);
+    }
+    None
+}
+
+/// Sniff the format of `bytes` and convert it into a mesh.
+pub fn load_mesh_bytes(bytes: &[u8]) -> Result<(MeshFormat, GMesh), ImportError> {
+    if bytes.is_empty() {
+        return Err(ImportError::Empty);
+    }
+    let format = detect_format(bytes).ok_or(ImportError::UnknownFormat)?;
+    let mesh = match format {
+        MeshFormat::Obj => load_obj(String::from_utf8_lossy(bytes).into_owned()),
+        MeshFormat::StlAscii => load_stl_ascii(&String::from_utf8_lossy(bytes)),
+        MeshFormat::StlBinary => load_stl_binary(bytes),
+        MeshFormat::Ply => load_ply(bytes),
+        MeshFormat::Gltf | MeshFormat::Glb => load_gltf(bytes),
+    }
+    .map_err(|message| ImportError::Parse { format, message })?;
+
+    if mesh.triangles().is_empty() {
+        return Err(ImportError::NoGeometry { format });
+    }
+    Ok((format, mesh))
+}
+
+/// Append one flat-shaded STL facet to `mesh`.
+fn push_facet(mesh: &mut GMesh, normal: Vec3, corners: [Vec3; 3]) {
+    let normal = if normal.length_squared() > 0.0 {
+        normal.normalize()
+    } else {
+        (corners[1] - corners[0])
+            .cross(corners[2] - corners[0])
+            .normalize_or_zero()
+    };
+    let base = mesh.positions().len() as u32;
+    for c in corners {
+        mesh.positions_mut().push(c);
+        mesh.normals.push(normal);
+    }
+    mesh.triangles_mut().push([base, base + 1, base + 2]);
+}
+
+/// Size of a binary STL file with `count` facets, if it fits in `usize`.
+/// It may not on 32-bit targets.
+fn stl_binary_len(count: usize) -> Option<usize> {
+    count.checked_mul(50).and_then(|n| n.checked_add(84))
+}
+
+pub fn load_stl_binary(bytes: &[u8]) -> Result<GMesh, String> {
+    if bytes.len() < 84 {
+        return Err("file is shorter than the STL header".to_string());
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    if stl_binary_len(count).is_none_or(|len| bytes.len() < len) {
+        return Err(format!(
+            "header declares {} facets but data is truncated",
+            count
+        ));
+    }
+
+    let read_vec = |off: usize| {
+        let f = |i: usize| {
+            let o = off + i * 4;
+            f32::from_le_bytes([bytes[o], bytes[o + 1], bytes[o + 2], bytes[o + 3]])
+        };
+        Vec3::new(f(0), f(1), f(2))
+    };
+
+    let mut mesh = GMesh::default();
+    for i in 0..count {
+        let off = 84 + i * 50;
+        push_facet(
+            &mut mesh,
+            read_vec(off),
+            [read_vec(off + 12), read_vec(off + 24), read_vec(off + 36)],
+        );
+    }
+    Ok(mesh)
+}
+
+pub fn load_stl_ascii(data: &str) -> Result<GMesh, String> {
+    let parse_vec = |parts: &[&str], line: usize| -> Result<Vec3, String> {
+        if parts.len() < 3 {
+            return Err(format!("line {}: expected three coordinates", line));
+        }
+        let mut v = [0.0f32; 3];
+        for (k, p) in parts[..3].iter().enumerate() {
+            v[k] = p
+                .parse()
+                .map_err(|_| format!("line {}: invalid number `{}`", line, p))?;
+        }
+        Ok(Vec3::from_array(v))
+    };
+
+    let mut mesh = GMesh::default();
+    let mut normal = Vec3::ZERO;
+    let mut corners = Vec::with_capacity(3);
+    for (i, line) in data.lines().enumerate() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["facet", "normal", rest @ ..] => {
+                normal = parse_vec(rest, i + 1)?;
+                corners.clear();
+            }
+            ["vertex", rest @ ..] => corners.push(parse_vec(rest, i + 1)?),
+            ["endfacet", ..] => {
+                if corners.len() != 3 {
+                    return Err(format!(
+                        "line {}: facet has {} vertices",
+                        i + 1,
+                        corners.len()
+                    ));
+                }
+                push_facet(&mut mesh, normal, [corners[0], corners[1], corners[2]]);
+                corners.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PlyEncoding {
+    Ascii,
+    BinaryLe,
+    BinaryBe,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => PlyScalar::I8,
+            "uchar" | "uint8" => PlyScalar::U8,
+            "short" | "int16" => PlyScalar::I16,
+            "ushort" | "uint16" => PlyScalar::U16,
+            "int" | "int32" => PlyScalar::I32,
+            "uint" | "uint32" => PlyScalar::U32,
+            "float" | "float32" => PlyScalar::F32,
+            "double" | "float64" => PlyScalar::F64,
+            _ => return Err(format!("unknown property type `{}`", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PlyProperty {
+    Scalar(String, PlyScalar),
+    List(String, PlyScalar, PlyScalar),
+}
+
+#[derive(Debug)]
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Reads scalar values out of the body of a PLY file.
+struct PlyBody<'a> {
+    encoding: PlyEncoding,
+    bytes: &'a [u8],
+    offset: usize,
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl PlyBody<'_> {
+    fn read(&mut self, ty: PlyScalar) -> Result<f64, String> {
+        if self.encoding == PlyEncoding::Ascii {
+            let tok = self.tokens.next().ok_or("unexpected end of data")?;
+            return tok
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number `{}`", tok));
+        }
+
+        let n = ty.size();
+        let raw = self
+            .bytes
+            .get(self.offset..self.offset + n)
+            .ok_or("unexpected end of data")?;
+        self.offset += n;
+        let mut buf = [0u8; 8];
+        buf[..n].copy_from_slice(raw);
+        if self.encoding == PlyEncoding::BinaryBe {
+            buf[..n].reverse();
+        }
+        Ok(match ty {
+            PlyScalar::I8 => buf[0] as i8 as f64,
+            PlyScalar::U8 => buf[0] as f64,
+            PlyScalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            PlyScalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            PlyScalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            PlyScalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            PlyScalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            PlyScalar::F64 => f64::from_le_bytes(buf),
+        })
+    }
+
+    /// Upper bound on how many more values of type `ty` the body holds.
+    fn max_remaining(&self, ty: PlyScalar) -> usize {
+        match self.encoding {
+            // Every value takes at least one character
+            PlyEncoding::Ascii => self.bytes.len(),
+            _ => (self.bytes.len() - self.offset) / ty.size(),
+        }
+    }
+
+    /// Upper bound on how many more rows of `element` the body holds, zero
+    /// for an element without properties.
+    fn max_rows(&self, element: &PlyElement) -> usize {
+        // Lists may be empty but still take their count
+        let row: usize = element
+            .properties
+            .iter()
+            .map(|prop| match (self.encoding, prop) {
+                (PlyEncoding::Ascii, _) => 1,
+                (_, PlyProperty::Scalar(_, ty) | PlyProperty::List(_, ty, _)) => ty.size(),
+            })
+            .sum();
+        (self.bytes.len() - self.offset)
+            .checked_div(row)
+            .unwrap_or(0)
+    }
+}
+
+pub fn load_ply(bytes: &[u8]) -> Result<GMesh, String> {
+    let marker = b"end_header";
+    let header_end = bytes
+        .windows(marker.len())
+        .position(|w| w == marker)
+        .ok_or("missing end_header")?;
+    let body_start = bytes[header_end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|p| header_end + p + 1)
+        .unwrap_or(bytes.len());
+    let header = String::from_utf8_lossy(&bytes[..header_end]);
+
+    let mut encoding = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in header.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["format", fmt, ..] => {
+                encoding = Some(match *fmt {
+                    "ascii" => PlyEncoding::Ascii,
+                    "binary_little_endian" => PlyEncoding::BinaryLe,
+                    "binary_big_endian" => PlyEncoding::BinaryBe,
+                    _ => return Err(format!("unsupported PLY format `{}`", fmt)),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("invalid element count `{}`", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => elements
+                .last_mut()
+                .ok_or("property declared before any element")?
+                .properties
+                .push(PlyProperty::List(
+                    name.to_string(),
+                    PlyScalar::parse(count_ty)?,
+                    PlyScalar::parse(item_ty)?,
+                )),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or("property declared before any element")?
+                .properties
+                .push(PlyProperty::Scalar(name.to_string(), PlyScalar::parse(ty)?)),
+            _ => {}
+        }
+    }
+    let encoding = encoding.ok_or("missing format line")?;
+
+    let body = &bytes[body_start..];
+    let text = if encoding == PlyEncoding::Ascii {
+        std::str::from_utf8(body).map_err(|_| "ASCII body is not valid UTF-8")?
+    } else {
+        ""
+    };
+    let mut reader = PlyBody {
+        encoding,
+        bytes: body,
+        offset: 0,
+        tokens: text.split_ascii_whitespace(),
+    };
+
+    let mut mesh = GMesh::default();
+    let mut has_normals = false;
+    let mut has_uvs = false;
+    for element in &elements {
+        // Malformed counts must not allocate or spin before failing
+        if element.count > reader.max_rows(element) {
+            return Err(format!(
+                "element `{}` of {} rows overruns the data",
+                element.name, element.count
+            ));
+        }
+        for _ in 0..element.count {
+            let mut pos = Vec3::ZERO;
+            let mut normal = Vec3::ZERO;
+            let mut uv = Vec2::ZERO;
+            for prop in &element.properties {
+                match prop {
+                    PlyProperty::Scalar(name, ty) => {
+                        let v = reader.read(*ty)? as f32;
+                        match name.as_str() {
+                            "x" => pos.x = v,
+                            "y" => pos.y = v,
+                            "z" => pos.z = v,
+                            "nx" => (normal.x, has_normals) = (v, true),
+                            "ny" => normal.y = v,
+                            "nz" => normal.z = v,
+                            "u" | "s" | "texture_u" => (uv.x, has_uvs) = (v, true),
+                            "v" | "t" | "texture_v" => uv.y = v,
+                            _ => {}
+                        }
+                    }
+                    PlyProperty::List(name, count_ty, item_ty) => {
+                        let n = reader.read(*count_ty)? as usize;
+                        // Malformed counts must not allocate before failing
+                        if n > reader.max_remaining(*item_ty) {
+                            return Err(format!("list of {} items overruns the data", n));
+                        }
+                        let indices = element.name == "face"
+                            && (name == "vertex_indices" || name == "vertex_index");
+                        let mut items = Vec::with_capacity(if indices { n } else { 0 });
+                        for _ in 0..n {
+                            let item = reader.read(*item_ty)?;
+                            if indices {
+                                if item < 0.0 {
+                                    return Err(format!("negative vertex index {}", item));
+                                }
+                                items.push(item as u32);
+                            }
+                        }
+                        // Fan-triangulate polygons
+                        for k in 1..items.len().saturating_sub(1) {
+                            mesh.triangles_mut()
+                                .push([items[0], items[k], items[k + 1]]);
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                mesh.positions_mut().push(pos);
+                mesh.normals.push(normal);
+                mesh.uvs.push(uv);
+            }
+        }
+    }
+
+    let n_vertices = mesh.positions().len() as u32;
+    if mesh.triangles().iter().flatten().any(|&i| i >= n_vertices) {
+        return Err("face references a vertex out of range".to_string());
+    }
+    if !has_uvs {
+        mesh.uvs.clear();
+    }
+    if !has_normals {
+        mesh.recalculate_normals();
+    }
+    Ok(mesh)
+}
+
+/// Decode a base64 `data:` URI buffer.
+fn decode_data_uri(uri: &str) -> Result<Vec<u8>, String> {
+    let payload = uri
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .map(|(_, data)| data)
+        .ok_or_else(|| format!("external buffer `{}` cannot be resolved", uri))?;
+    base64::engine::general_purpose::STANDARD
+        .decode(payload)
+        .map_err(|e| format!("invalid base64 buffer: {}", e))
+}
+
+pub fn load_gltf(bytes: &[u8]) -> Result<GMesh, String> {
+    let gltf = gltf::Gltf::from_slice(bytes).map_err(|e| e.to_string())?;
+
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone().ok_or("GLB binary chunk is missing")?,
+            gltf::buffer::Source::Uri(uri) => decode_data_uri(uri)?,
+        };
+        buffers.push(data);
+    }
+
+    let mut mesh = GMesh::default();
+    let mut missing_normals = false;
+    match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                append_gltf_node(
+                    &node,
+                    Mat4::IDENTITY,
+                    &buffers,
+                    &mut mesh,
+                    &mut missing_normals,
+                )?;
+            }
+        }
+        None => {
+            for m in gltf.meshes() {
+                append_gltf_mesh(
+                    &m,
+                    Mat4::IDENTITY,
+                    &buffers,
+                    &mut mesh,
+                    &mut missing_normals,
+                )?;
+            }
+        }
+    }
+
+    if missing_normals {
+        mesh.recalculate_normals();
+    }
+    if mesh.uvs.iter().all(|uv| *uv == Vec2::ZERO) {
+        mesh.uvs.clear();
+    }
+    Ok(mesh)
+}
+
+fn append_gltf_node(
+    node: &gltf::Node,
+    parent: Mat4,
+    buffers: &[Vec<u8>],
+    out: &mut GMesh,
+    missing_normals: &mut bool,
+) -> Result<(), String> {
+    let world = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(m) = node.mesh() {
+        append_gltf_mesh(&m, world, buffers, out, missing_normals)?;
+    }
+    for child in node.children() {
+        append_gltf_node(&child, world, buffers, out, missing_normals)?;
+    }
+    Ok(())
+}
+
+fn append_gltf_mesh(
+    mesh: &gltf::Mesh,
+    transform: Mat4,
+    buffers: &[Vec<u8>],
+    out: &mut GMesh,
+    missing_normals: &mut bool,
+) -> Result<(), String> {
+    // Mirrored nodes come out with their winding flipped, as glTF asks
+    let transform = Affine3A::from_mat4(transform);
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            log::warn!("Skipping glTF primitive with mode {:?}", primitive.mode());
+            continue;
+        }
+        let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| d.as_slice()));
+        let positions: Vec<Vec3> = reader
+            .read_positions()
+            .ok_or("primitive has no positions")?
+            .map(Vec3::from_array)
+            .collect();
+        let count = positions.len();
+
+        let normals = match reader.read_normals() {
+            Some(normals) => normals.map(Vec3::from_array).collect(),
+            None => {
+                *missing_normals = true;
+                vec![Vec3::ZERO; count]
+            }
+        };
+        let uvs = match reader.read_tex_coords(0) {
+            Some(uvs) => uvs.into_f32().map(Vec2::from_array).collect(),
+            None => vec![Vec2::ZERO; count],
+        };
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..count as u32).collect(),
+        };
+        if indices.iter().any(|&i| i as usize >= count) {
+            return Err("primitive index out of range".to_string());
+        }
+        let triangles = indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        let mut part = GMesh::new(positions, normals, uvs, triangles);
+        part.transform(transform);
+        out.append(&part);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stl_binary(facets: &[[Vec3; 3]]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 80];
+        bytes.extend_from_slice(&(facets.len() as u32).to_le_bytes());
+        for facet in facets {
+            // Zero normal, worked out from the corners
+            bytes.extend_from_slice(&[0u8; 12]);
+            for corner in facet {
+                for c in corner.to_array() {
+                    bytes.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&[0u8; 2]);
+        }
+        bytes
+    }
+
+    const TRIANGLE: [Vec3; 3] = [Vec3::ZERO, Vec3::X, Vec3::Y];
+
+    #[test]
+    fn binary_stl_loads() {
+        let bytes = stl_binary(&[TRIANGLE, TRIANGLE]);
+        assert_eq!(detect_format(&bytes), Some(MeshFormat::StlBinary));
+        let (_, mesh) = load_mesh_bytes(&bytes).unwrap();
+        assert_eq!(mesh.triangles().len(), 2);
+        assert_eq!(mesh.normals[0], Vec3::Z);
+    }
+
+    #[test]
+    fn truncated_binary_stl_fails() {
+        let mut bytes = stl_binary(&[TRIANGLE, TRIANGLE]);
+        bytes.truncate(bytes.len() - 10);
+        assert!(load_stl_binary(&bytes).is_err());
+        assert!(load_stl_binary(&bytes[..40]).is_err());
+    }
+
+    #[test]
+    fn binary_stl_with_huge_count_fails() {
+        let mut bytes = stl_binary(&[TRIANGLE]);
+        bytes[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(load_stl_binary(&bytes).is_err());
+    }
+
+    #[test]
+    fn ascii_stl_facet_needs_three_vertices() {
+        let data = "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                    endloop\nendfacet\nendsolid t\n";
+        assert!(load_stl_ascii(data).is_err());
+    }
+
+    fn ply_binary(face_count: u32, indices: &[u32]) -> Vec<u8> {
+        let mut bytes = b"ply\nformat binary_little_endian 1.0\nelement vertex 3\n\
+                          property float x\nproperty float y\nproperty float z\n\
+                          element face 1\nproperty list uint int vertex_indices\nend_header\n"
+            .to_vec();
+        for corner in TRIANGLE {
+            for c in corner.to_array() {
+                bytes.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&face_count.to_le_bytes());
+        for i in indices {
+            bytes.extend_from_slice(&i.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn binary_ply_loads() {
+        let (format, mesh) = load_mesh_bytes(&ply_binary(3, &[0, 1, 2])).unwrap();
+        assert_eq!(format, MeshFormat::Ply);
+        assert_eq!(mesh.triangles(), &[[0, 1, 2]]);
+    }
+
+    #[test]
+    fn ply_list_overrunning_the_data_fails() {
+        assert!(load_ply(&ply_binary(u32::MAX, &[0, 1, 2])).is_err());
+        assert!(load_ply(&ply_binary(4, &[0, 1, 2])).is_err());
+    }
+
+    #[test]
+    fn truncated_ply_fails() {
+        let bytes = ply_binary(3, &[0, 1, 2]);
+        let header = bytes.windows(10).position(|w| w == b"end_header").unwrap();
+        assert!(load_ply(&bytes[..header + 20]).is_err());
+        assert!(load_ply(&bytes[..header]).is_err());
+    }
+
+    #[test]
+    fn ply_element_overrunning_the_data_fails() {
+        let huge = b"ply\nformat binary_little_endian 1.0\nelement vertex 4000000000\n\
+                     property float x\nproperty float y\nproperty float z\nend_header\n";
+        assert!(load_ply(huge).is_err());
+        let empty = b"ply\nformat ascii 1.0\nelement vertex 0\nproperty float x\n\
+                      element junk 4000000000\nend_header\n";
+        assert!(load_ply(empty).is_err());
+    }
+
+    #[test]
+    fn negative_ply_index_fails() {
+        let error = load_ply(&ply_binary(3, &[0, 1, -1i32 as u32])).unwrap_err();
+        assert!(error.contains("negative"), "{}", error);
+    }
+
+    #[test]
+    fn ply_index_out_of_range_fails() {
+        assert!(load_ply(&ply_binary(3, &[0, 1, 7])).is_err());
+    }
+
+    #[test]
+    fn ascii_ply_loads() {
+        let data = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\n\
+                    property float y\nproperty float z\nelement face 1\n\
+                    property list uchar int vertex_indices\nend_header\n\
+                    0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n";
+        let mesh = load_ply(data.as_bytes()).unwrap();
+        assert_eq!(mesh.triangles(), &[[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn empty_and_unknown_input_fail() {
+        assert!(matches!(load_mesh_bytes(&[]), Err(ImportError::Empty)));
+        assert!(matches!(
+            load_mesh_bytes(b"not a mesh"),
+            Err(ImportError::UnknownFormat)
+        ));
+    }
 }
@@ -1,84 +1,92 @@
-use glam::{Vec2, Vec3};
-use nalgebra::{DMatrix, DVector};
+use glam::{Affine3A, DVec3, Mat3A, Quat, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::f32::consts::PI;
-
-#[derive(Debug, Clone)]
+use std::sync::OnceLock;
+
+use crate::{
+    bvh::{Aabb, Bvh, ClosestPoint, RayHit},
+    csg::{self, BooleanOp},
+    decimate::{self, DecimateOptions},
+    halfedge::{self, HalfEdgeMesh},
+    repair::{self, MeshReport, RepairOptions},
+    validation::Validator,
+};
+
+#[derive(Debug, Clone, Default)]
 pub struct Mesh {
-    pub positions: Vec<Vec3>,
+    /// Only reached through [`Mesh::positions_mut`] from outside, so the
+    /// triangle hierarchy never goes stale
+    positions: Vec<Vec3>,
     pub normals: Vec<Vec3>,
     pub uvs: Vec<Vec2>,
-    pub triangles: Vec<[u32; 3]>,
+    /// See `positions`
+    triangles: Vec<[u32; 3]>,
+    /// How normals are generated after each operation, see
+    /// [`Mesh::set_shading`]
+    shading: Option<Shading>,
+    /// Built by the first query, see [`Mesh::bvh`]
+    bvh: OnceLock<Bvh>,
 }
 
 type PointSet = Vec<Vec3>;
 type CubicBezierCurve = Vec<Vec3>;
 
-fn cox_de_boor(i: usize, k: usize, t: f32, knots: &[f32]) -> f32 {
-    if k == 0 {
-        if i < knots.len() - 1 && knots[i] <= t && t < knots[i + 1] { 1.0 }
-        else if i == knots.len() - 2 && knots[i] <= t && t <= knots[i + 1] { 1.0 }
-        else { 0.0 }
-    } else {
-        let mut left = 0.0;
-        let mut right = 0.0;
+const DEGREE: usize = 3;
 
-        if i + k < knots.len() {
-            let left_den = knots[i + k] - knots[i];
-            if left_den != 0.0 {
-                left = (t - knots[i]) / left_den * cox_de_boor(i, k - 1, t, knots);
-            }
-        }
+/// Crease angle in degrees used when a mesh has no shading of its own
+pub const DEFAULT_CREASE_ANGLE: f32 = 30.0;
 
-        if i + k + 1 < knots.len() {
-            let right_den = knots[i + k + 1] - knots[i + 1];
-            if right_den != 0.0 {
-                right = (knots[i + k + 1] - t) / right_den * cox_de_boor(i + 1, k - 1, t, knots);
-            }
-        }
+/// Slack in the cosine compared against the crease angle, so that faces in
+/// one plane always share normals
+const COPLANAR_TOLERANCE: f32 = 1e-5;
+
+/// Knot `j` of the open uniform knot vector for `n_ctrl_pts` control points,
+/// i.e. `[0, 0, 0, 0, 1, 2, ..., max_t, max_t, max_t, max_t]`.
+fn knot(j: usize, n_ctrl_pts: usize) -> f32 {
+    (j as f32 - DEGREE as f32).clamp(0.0, (n_ctrl_pts - DEGREE) as f32)
+}
 
-        left + right
+/// The four cubic basis functions that are non-zero at `t`, together with
+/// the index of the control point the first one belongs to.
+///
+/// Iterative Cox-de Boor, as in The NURBS Book (A2.2).
+fn basis_functions(t: f32, n_ctrl_pts: usize) -> (usize, [f32; 4]) {
+    let max_t = (n_ctrl_pts - DEGREE) as f32;
+    let t = t.clamp(0.0, max_t);
+    // Knot span holding t. The last span is closed so t = max_t lands in it.
+    let span = (t.floor() as usize + DEGREE).min(n_ctrl_pts - 1);
+
+    let mut n = [1.0, 0.0, 0.0, 0.0];
+    let mut left = [0.0; DEGREE + 1];
+    let mut right = [0.0; DEGREE + 1];
+    for j in 1..=DEGREE {
+        left[j] = t - knot(span + 1 - j, n_ctrl_pts);
+        right[j] = knot(span + j, n_ctrl_pts) - t;
+        let mut saved = 0.0;
+        for r in 0..j {
+            let temp = n[r] / (right[r + 1] + left[j - r]);
+            n[r] = saved + right[r + 1] * temp;
+            saved = left[j - r] * temp;
+        }
+        n[j] = saved;
     }
+    (span - DEGREE, n)
 }
 
 /// Fixed version of eval_bspline with proper parameter handling
-fn eval_bspline_fixed(control_points: &[Vec3], t: f32) -> Vec3 {
+pub(crate) fn eval_bspline_fixed(control_points: &[Vec3], t: f32) -> Vec3 {
     let n_ctrl_pts = control_points.len();
-    let degree = 3;
-
-    if n_ctrl_pts < degree + 1 {
+    if n_ctrl_pts < DEGREE + 1 {
         return control_points[0]; // fallback
     }
 
     // Parameter range is [0, n_ctrl_pts - degree]
-    let max_t = (n_ctrl_pts - degree) as f32;
-    let t = t.clamp(0.0, max_t);
-
-    // Open uniform knot vector
-    let mut knots = Vec::new();
-    for _ in 0..=degree { knots.push(0.0); }
-    for j in 1..=(n_ctrl_pts - degree - 1) { knots.push(j as f32); }
-    for _ in 0..=degree { knots.push(max_t); }
-
-    // Find the knot span
-    let mut span = degree;
-    if t >= max_t {
-        span = n_ctrl_pts - 1;
-    } else {
-        while span < n_ctrl_pts && t >= knots[span + 1] {
-            span += 1;
-        }
-    }
-
-    // Evaluate using basis functions
-    let mut point = Vec3::ZERO;
-    for i in (span - degree)..=span {
-        if i < control_points.len() {
-            let basis = cox_de_boor(i, degree, t, &knots);
-            point += control_points[i] * basis;
-        }
-    }
-
-    point
+    let (first, basis) = basis_functions(t, n_ctrl_pts);
+    basis
+        .iter()
+        .zip(&control_points[first..first + DEGREE + 1])
+        .fold(Vec3::ZERO, |acc, (b, p)| acc + *p * *b)
 }
 
 /// Compute tangent vector for the B-spline curve at parameter t
@@ -97,91 +105,382 @@ fn eval_bspline_tangent(control_points: &[Vec3], t: f32) -> Vec3 {
     (p2 - p1).normalize()
 }
 
-/// Convert B-spline curve to mesh using camera-aware tube generation
-pub fn meshify(curve: &CubicBezierCurve, camera_pos: Vec3, radius: f32) -> Mesh {
-    if curve.len() < 2 {
-        return Mesh {
-            positions: Vec::new(),
-            normals: Vec::new(),
-            uvs: Vec::new(),
-            triangles: Vec::new(),
-        };
+/// How the cross-section of a tube is oriented along the curve.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum FrameMode {
+    /// Rotation minimizing frames (double reflection), which do not twist
+    #[default]
+    ParallelTransport,
+    /// Each ring faces the camera, like a ribbon drawn towards the viewer.
+    /// Flips where the curve points at the camera.
+    CameraFacing,
+}
+
+/// Cross-section settings for [`meshify`].
+#[derive(Debug, Clone, Copy)]
+pub struct TubeOptions {
+    /// Vertices around each ring
+    pub radial_segments: usize,
+    /// Close both ends with a fan, so the tube is watertight
+    pub caps: bool,
+    pub frames: FrameMode,
+}
+
+impl Default for TubeOptions {
+    fn default() -> Self {
+        Self {
+            radial_segments: 8,
+            caps: true,
+            frames: FrameMode::ParallelTransport,
+        }
     }
+}
 
-    const RADIAL_SEGMENTS: usize = 8;
+/// Unit tangents of a polyline, reusing the previous one across repeated points.
+fn curve_tangents(curve: &[Vec3]) -> Vec<Vec3> {
+    let mut tangents: Vec<Vec3> = Vec::with_capacity(curve.len());
+    for i in 0..curve.len() {
+        let (a, b) = (curve[i.saturating_sub(1)], curve[(i + 1).min(curve.len() - 1)]);
+        let tangent = (b - a).normalize_or_zero();
+        let fallback = tangents.last().copied().unwrap_or(Vec3::X);
+        tangents.push(if tangent == Vec3::ZERO { fallback } else { tangent });
+    }
+    tangents
+}
+
+/// Ring frame facing the camera: `up` is perpendicular to both the tangent
+/// and the view direction.
+fn camera_facing_up(point: Vec3, tangent: Vec3, camera_pos: Vec3) -> Vec3 {
+    // Calculate view direction from curve point to camera
+    let view_dir = (camera_pos - point).normalize();
+
+    if tangent.dot(view_dir).abs() < 0.9 {
+        tangent.cross(view_dir).normalize()
+    } else {
+        // Use world up if tangent is too aligned with view direction
+        tangent.cross(Vec3::Y).normalize_or(tangent.any_orthonormal_vector())
+    }
+}
+
+/// `(right, up)` of each ring, perpendicular to the tangent.
+fn tube_frames(
+    curve: &[Vec3],
+    tangents: &[Vec3],
+    camera_pos: Vec3,
+    mode: FrameMode,
+) -> Vec<(Vec3, Vec3)> {
+    let frame = |up: Vec3, tangent: Vec3| (up.cross(tangent).normalize(), up);
+    match mode {
+        FrameMode::CameraFacing => curve
+            .iter()
+            .zip(tangents)
+            .map(|(&p, &t)| frame(camera_facing_up(p, t, camera_pos), t))
+            .collect(),
+        FrameMode::ParallelTransport => {
+            // Start facing the camera, then carry that frame along by double
+            // reflection (Wang et al. 2008, "Computation of rotation
+            // minimizing frames")
+            let mut up = camera_facing_up(curve[0], tangents[0], camera_pos);
+            let mut frames = vec![frame(up, tangents[0])];
+            for i in 0..curve.len() - 1 {
+                let v1 = curve[i + 1] - curve[i];
+                let c1 = v1.dot(v1);
+                if c1 > 0.0 {
+                    let up_l = up - (2.0 / c1) * v1.dot(up) * v1;
+                    let t_l = tangents[i] - (2.0 / c1) * v1.dot(tangents[i]) * v1;
+                    let v2 = tangents[i + 1] - t_l;
+                    let c2 = v2.dot(v2);
+                    up = if c2 > 0.0 {
+                        up_l - (2.0 / c2) * v2.dot(up_l) * v2
+                    } else {
+                        up_l
+                    };
+                }
+                // Keep it exactly perpendicular despite rounding
+                up = up
+                    .reject_from_normalized(tangents[i + 1])
+                    .normalize_or(tangents[i + 1].any_orthonormal_vector());
+                frames.push(frame(up, tangents[i + 1]));
+            }
+            frames
+        }
+    }
+}
+
+/// Cross-section shape swept along a stroke by [`extrude`], in units of the
+/// stroke radius. Points are in the `(right, up)` plane of each ring.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "shape", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum Profile {
+    /// As wide as the round tube
+    #[default]
+    Square,
+    Star {
+        /// Number of tips
+        points: u32,
+        /// Radius of the notches between the tips
+        inner_radius: f32,
+    },
+    /// Counter-clockwise around the curve. Every edge must face away from
+    /// the curve, so the ends can be closed with a fan.
+    Polygon { vertices: Vec<[f32; 2]> },
+}
+
+impl Profile {
+    /// Corners of the profile, counter-clockwise.
+    pub fn outline(&self) -> Vec<Vec2> {
+        match self {
+            Profile::Square => vec![
+                Vec2::new(1.0, -1.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(-1.0, 1.0),
+                Vec2::new(-1.0, -1.0),
+            ],
+            Profile::Star {
+                points,
+                inner_radius,
+            } => {
+                let n = 2 * *points as usize;
+                (0..n)
+                    .map(|i| {
+                        // First tip points up
+                        let angle = PI / 2.0 + 2.0 * PI * i as f32 / n as f32;
+                        let r = if i % 2 == 0 { 1.0 } else { *inner_radius };
+                        Vec2::from_angle(angle) * r
+                    })
+                    .collect()
+            }
+            Profile::Polygon { vertices } => vertices.iter().map(|&v| Vec2::from(v)).collect(),
+        }
+    }
+
+    pub fn validate(&self, v: &mut Validator) {
+        match self {
+            Profile::Square => {}
+            Profile::Star {
+                points,
+                inner_radius,
+            } => {
+                v.at_least("points", *points, 3);
+                if *points > 32 {
+                    v.field_error("points", "must be at most 32");
+                }
+                v.in_range("innerRadius", *inner_radius, 0.01, 1.0);
+            }
+            Profile::Polygon { vertices } => {
+                if vertices.len() < 3 {
+                    v.field_error("vertices", "must have at least 3 points");
+                    return;
+                }
+                v.finite("vertices", vertices.as_flattened());
+                let outline = self.outline();
+                let faces_out = (0..outline.len())
+                    .all(|i| outline[i].perp_dot(outline[(i + 1) % outline.len()]) > 0.0);
+                if !faces_out {
+                    v.field_error("vertices", "must wind counter-clockwise around the origin");
+                }
+            }
+        }
+    }
+}
+
+/// One vertex of a ring swept along the curve.
+struct SectionVertex {
+    /// Offset from the curve in the `(right, up)` plane, per unit radius
+    offset: Vec2,
+    normal: Vec2,
+    /// Texture coordinate around the ring
+    v: f32,
+}
+
+/// A ring and the pairs of its vertices joined into quads along the curve.
+struct Section {
+    vertices: Vec<SectionVertex>,
+    edges: Vec<[usize; 2]>,
+}
+
+impl Section {
+    /// Smooth ring, sharing each vertex between neighbouring quads.
+    fn circle(segments: usize) -> Self {
+        let vertices = (0..segments)
+            .map(|j| {
+                let direction = Vec2::from_angle(2.0 * PI * j as f32 / segments as f32);
+                SectionVertex {
+                    offset: direction,
+                    normal: direction,
+                    v: j as f32 / segments as f32,
+                }
+            })
+            .collect();
+        let edges = (0..segments).map(|j| [j, (j + 1) % segments]).collect();
+        Self { vertices, edges }
+    }
+
+    /// Flat sided ring, each side getting its own vertices so it is not
+    /// shaded round.
+    fn polygon(outline: &[Vec2]) -> Self {
+        let perimeter: f32 = (0..outline.len())
+            .map(|i| outline[i].distance(outline[(i + 1) % outline.len()]))
+            .sum();
+        let mut vertices = Vec::with_capacity(outline.len() * 2);
+        let mut edges = Vec::with_capacity(outline.len());
+        let mut length = 0.0;
+        for i in 0..outline.len() {
+            let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+            let normal = -(b - a).perp().normalize_or_zero();
+            let step = a.distance(b);
+            edges.push([vertices.len(), vertices.len() + 1]);
+            vertices.push(SectionVertex {
+                offset: a,
+                normal,
+                v: length / perimeter,
+            });
+            length += step;
+            vertices.push(SectionVertex {
+                offset: b,
+                normal,
+                v: length / perimeter,
+            });
+        }
+        Self { vertices, edges }
+    }
+}
+
+/// Sweep `section` along the curve, scaled by `radii`.
+fn sweep(
+    curve: &[Vec3],
+    radii: &[f32],
+    camera_pos: Vec3,
+    options: &TubeOptions,
+    section: &Section,
+) -> Mesh {
+    if curve.len() < 2 || radii.len() != curve.len() {
+        return Mesh::default();
+    }
 
+    let ring_size = section.vertices.len();
     let segments = curve.len();
     let mut positions = Vec::new();
     let mut normals = Vec::new();
     let mut uvs = Vec::new();
     let mut triangles = Vec::new();
+    let tangents = curve_tangents(curve);
+    let frames = tube_frames(curve, &tangents, camera_pos, options.frames);
 
     // Generate vertices
-    for (i, &point) in curve.iter().enumerate() {
-        // Calculate tangent
-        let tangent = if i == 0 {
-            (curve[1] - curve[0]).normalize()
-        } else if i == curve.len() - 1 {
-            (curve[i] - curve[i - 1]).normalize()
-        } else {
-            ((curve[i + 1] - curve[i - 1]) * 0.5).normalize()
-        };
-
-        // Calculate view direction from curve point to camera
-        let view_dir = (camera_pos - point).normalize();
-
-        // Create coordinate system for tube cross-section
-        let up = if tangent.dot(view_dir).abs() < 0.9 {
-            tangent.cross(view_dir).normalize()
-        } else {
-            // Use world up if tangent is too aligned with view direction
-            tangent.cross(Vec3::Y).normalize()
-        };
-        let right = up.cross(tangent).normalize();
-
-        // Generate points around the circumference
-        for j in 0..RADIAL_SEGMENTS {
-            let angle = 2.0 * PI * j as f32 / RADIAL_SEGMENTS as f32;
-            let cos_angle = angle.cos();
-            let sin_angle = angle.sin();
-
-            // Position on tube surface
-            let offset = (right * cos_angle + up * sin_angle) * radius;
-            let vertex_pos = point + offset;
-
+    for (i, (&point, &(right, up))) in curve.iter().zip(&frames).enumerate() {
+        let u = i as f32 / (segments - 1) as f32;
+        for vertex in &section.vertices {
+            let offset = right * vertex.offset.x + up * vertex.offset.y;
+            positions.push(point + offset * radii[i]);
             // Normal points outward
-            let normal = offset.normalize();
+            normals.push(right * vertex.normal.x + up * vertex.normal.y);
+            uvs.push(Vec2::new(u, vertex.v));
+        }
+    }
 
-            // UV coordinates
-            let u = i as f32 / (segments - 1) as f32;
-            let v = j as f32 / RADIAL_SEGMENTS as f32;
+    // Generate triangles, counter-clockwise seen from outside
+    for i in 0..(segments - 1) {
+        let ring = (i * ring_size) as u32;
+        let next_ring = ring + ring_size as u32;
+        for &[a, b] in &section.edges {
+            let (a, b) = (a as u32, b as u32);
+            // Two triangles per quad
+            triangles.push([ring + a, ring + b, next_ring + a]);
+            triangles.push([ring + b, next_ring + b, next_ring + a]);
+        }
+    }
 
-            positions.push(vertex_pos);
+    if options.caps {
+        // Fans around a center vertex, sharing the ring vertices
+        let ends = [(0, -tangents[0], 0.0), (segments - 1, tangents[segments - 1], 1.0)];
+        for (i, normal, u) in ends {
+            let center = positions.len() as u32;
+            positions.push(curve[i]);
             normals.push(normal);
-            uvs.push(Vec2::new(u, v));
+            uvs.push(Vec2::new(u, 0.5));
+            let ring = (i * ring_size) as u32;
+            for &[a, b] in &section.edges {
+                let (a, b) = (ring + a as u32, ring + b as u32);
+                if i == 0 {
+                    triangles.push([center, b, a]);
+                } else {
+                    triangles.push([center, a, b]);
+                }
+            }
         }
     }
 
-    // Generate triangles
-    for i in 0..(segments - 1) {
-        for j in 0..RADIAL_SEGMENTS {
-            let current = (i * RADIAL_SEGMENTS + j) as u32;
-            let next_ring = ((i + 1) * RADIAL_SEGMENTS + j) as u32;
-            let current_next = (i * RADIAL_SEGMENTS + (j + 1) % RADIAL_SEGMENTS) as u32;
-            let next_ring_next = ((i + 1) * RADIAL_SEGMENTS + (j + 1) % RADIAL_SEGMENTS) as u32;
+    Mesh::new(positions, normals, uvs, triangles)
+}
 
-            // Two triangles per quad
-            triangles.push([current, next_ring, current_next]);
-            triangles.push([current_next, next_ring, next_ring_next]);
-        }
+/// Convert B-spline curve to mesh using tube generation
+///
+/// `radii` gives the tube radius at each curve point. `camera_pos` orients
+/// the rings, see [`FrameMode`].
+pub fn meshify(
+    curve: &CubicBezierCurve,
+    radii: &[f32],
+    camera_pos: Vec3,
+    options: &TubeOptions,
+) -> Mesh {
+    let section = Section::circle(options.radial_segments.max(3));
+    sweep(curve, radii, camera_pos, options, &section)
+}
+
+/// Sweep `profile` along the curve, like [`meshify`] does with a circle.
+/// `options.radial_segments` is not used.
+pub fn extrude(
+    curve: &CubicBezierCurve,
+    radii: &[f32],
+    profile: &Profile,
+    camera_pos: Vec3,
+    options: &TubeOptions,
+) -> Mesh {
+    let outline = profile.outline();
+    if outline.len() < 3 {
+        return Mesh::default();
     }
+    let section = Section::polygon(&outline);
+    sweep(curve, radii, camera_pos, options, &section)
+}
 
-    Mesh {
-        positions,
-        normals,
-        uvs,
-        triangles,
+/// A flat band along the curve, `2 * radii` wide and turned to face the
+/// camera. Both sides are meshed, so it shows from behind as well.
+pub fn ribbon(curve: &CubicBezierCurve, radii: &[f32], camera_pos: Vec3) -> Mesh {
+    if curve.len() < 2 || radii.len() != curve.len() {
+        return Mesh::default();
+    }
+
+    let segments = curve.len();
+    let tangents = curve_tangents(curve);
+    let frames = tube_frames(curve, &tangents, camera_pos, FrameMode::CameraFacing);
+    let mut mesh = Mesh::default();
+    // The front is made first, then the back from the same points
+    for side in [1.0, -1.0] {
+        let start = mesh.positions.len() as u32;
+        for (i, (&point, &(toward_camera, across))) in curve.iter().zip(&frames).enumerate() {
+            let u = i as f32 / (segments - 1) as f32;
+            for (v, sign) in [(0.0, -1.0), (1.0, 1.0)] {
+                mesh.positions.push(point + across * sign * radii[i]);
+                mesh.normals.push(toward_camera * side);
+                mesh.uvs.push(Vec2::new(u, v));
+            }
+        }
+        for i in 0..(segments - 1) as u32 {
+            let (a, b) = (start + 2 * i, start + 2 * i + 1);
+            let (c, d) = (a + 2, b + 2);
+            if side > 0.0 {
+                mesh.triangles.push([a, b, c]);
+                mesh.triangles.push([b, d, c]);
+            } else {
+                mesh.triangles.push([a, c, b]);
+                mesh.triangles.push([b, c, d]);
+            }
+        }
     }
+    mesh
 }
 
 pub fn curvify(point_set: &PointSet) -> CubicBezierCurve {
@@ -190,7 +489,9 @@ pub fn curvify(point_set: &PointSet) -> CubicBezierCurve {
     }
 
     // Use your existing control point fitting
-    let control_points = fit_bspline_control_points(point_set);
+    let n_ctrl_pts =
+        determine_control_point_count(point_set.len(), DEFAULT_CONTROL_POINT_DENSITY);
+    let control_points = fit_bspline_control_points(point_set, n_ctrl_pts, 0.0);
 
     // Sample using fixed evaluation
     let samples = control_points.len() * 20;
@@ -207,19 +508,6 @@ pub fn curvify(point_set: &PointSet) -> CubicBezierCurve {
     result
 }
 
-// Include your existing functions (they look correct)
-fn cubic_bspline_basis(i: usize, t: f32, n_ctrl_pts: usize, u: &[f32]) -> f32 {
-    let degree = 3;
-    let m = n_ctrl_pts + degree + 1;
-
-    let mut knots = Vec::with_capacity(m);
-    for _ in 0..degree+1 { knots.push(0.0); }
-    for j in 1..=(n_ctrl_pts - degree - 1) { knots.push(j as f32); }
-    for _ in 0..degree+1 { knots.push((n_ctrl_pts - degree) as f32); }
-
-    cox_de_boor(i, degree, t, &knots)
-}
-
 fn chord_length_param(points: &PointSet, max_val: f32) -> Vec<f32> {
     let mut u = vec![0.0f32; points.len()];
     for i in 1..points.len() {
@@ -235,55 +523,147 @@ fn chord_length_param(points: &PointSet, max_val: f32) -> Vec<f32> {
     u
 }
 
-fn determine_control_point_count(n_points: usize) -> usize {
-    std::cmp::max(4, n_points / 10)
+/// One control point per ten input points
+pub const DEFAULT_CONTROL_POINT_DENSITY: f32 = 0.1;
+
+/// Number of control points for `n_points` samples, at least 4 for a cubic
+/// and never more than the samples themselves.
+pub fn determine_control_point_count(n_points: usize, density: f32) -> usize {
+    let n = (n_points as f32 * density).round() as usize;
+    n.clamp(4, n_points.max(4))
 }
 
-fn fit_bspline_control_points(points: &PointSet) -> Vec<Vec3> {
-    let n_points = points.len();
-    if n_points < 4 {
-        return points.clone();
-    }
+/// Symmetric positive definite matrix with three sub-diagonals, stored by
+/// rows as `rows[i][k] = A[i][i - k]`.
+struct BandedMatrix {
+    rows: Vec<[f64; DEGREE + 1]>,
+}
 
-    let n_ctrl_pts = determine_control_point_count(n_points);
-    let degree = 3;
+impl BandedMatrix {
+    fn new(n: usize) -> Self {
+        Self {
+            rows: vec![[0.0; DEGREE + 1]; n],
+        }
+    }
 
-    let max_knot = (n_ctrl_pts - degree) as f32;
-    let u = chord_length_param(points, max_knot);
+    /// Add to `A[i][j]`, `j <= i <= j + 3`.
+    fn add(&mut self, i: usize, j: usize, value: f64) {
+        self.rows[i][i - j] += value;
+    }
 
-    let mut data = Vec::with_capacity(n_points * n_ctrl_pts);
-    for &param in &u {
-        for i in 0..n_ctrl_pts {
-            let val = cubic_bspline_basis(i, param, n_ctrl_pts, &[]);
-            data.push(val);
+    fn add_diagonal(&mut self, value: f64) {
+        for row in &mut self.rows {
+            row[0] += value;
         }
     }
 
-    let n = DMatrix::from_row_slice(n_points, n_ctrl_pts, &data);
+    fn max_diagonal(&self) -> f64 {
+        self.rows.iter().map(|r| r[0]).fold(0.0, f64::max)
+    }
 
-    let mut qx = DVector::from_element(n_points, 0.0);
-    let mut qy = DVector::from_element(n_points, 0.0);
-    let mut qz = DVector::from_element(n_points, 0.0);
+    /// Solve `A x = b` by Cholesky factorization, in O(n) for the fixed
+    /// bandwidth. Returns None if the matrix is not numerically positive
+    /// definite.
+    fn cholesky_solve(&self, b: &[DVec3]) -> Option<Vec<DVec3>> {
+        let n = self.rows.len();
+        let tolerance = self.max_diagonal() * 1e-10;
+        // Lower factor in the same layout
+        let mut l = vec![[0.0f64; DEGREE + 1]; n];
+        for i in 0..n {
+            for j in i.saturating_sub(DEGREE)..=i {
+                let mut sum = self.rows[i][i - j];
+                for k in i.saturating_sub(DEGREE)..j {
+                    sum -= l[i][i - k] * l[j][j - k];
+                }
+                if i == j {
+                    if sum.is_nan() || sum <= tolerance {
+                        return None;
+                    }
+                    l[i][0] = sum.sqrt();
+                } else {
+                    l[i][i - j] = sum / l[j][0];
+                }
+            }
+        }
 
-    for i in 0..n_points {
-        qx[i] = points[i].x;
-        qy[i] = points[i].y;
-        qz[i] = points[i].z;
+        // L y = b, then L^T x = y
+        let mut x = b.to_vec();
+        for i in 0..n {
+            for k in i.saturating_sub(DEGREE)..i {
+                x[i] = x[i] - x[k] * l[i][i - k];
+            }
+            x[i] /= l[i][0];
+        }
+        for i in (0..n).rev() {
+            for k in i + 1..(i + DEGREE + 1).min(n) {
+                x[i] = x[i] - x[k] * l[k][k - i];
+            }
+            x[i] /= l[i][0];
+        }
+        Some(x)
     }
+}
 
-    let nt = n.transpose();
-    let nt_n = &nt * &n;
-    let nt_n_inv = nt_n.clone().try_inverse().expect("Matrix inversion failed");
+/// Least squares fit of `n_ctrl_pts` cubic B-spline control points.
+///
+/// Solves the normal equations `(N^T N + s D^T D) P = N^T Q` by banded
+/// Cholesky, where `D` takes second differences of the control points so
+/// `smoothing` (`s`, relative to the data) straightens the curve. Degenerate
+/// strokes, e.g. repeated points, are retried with a small ridge term and,
+/// failing that, the points themselves are returned.
+pub fn fit_bspline_control_points(
+    points: &PointSet,
+    n_ctrl_pts: usize,
+    smoothing: f32,
+) -> Vec<Vec3> {
+    let n_points = points.len();
+    if n_points < 4 || n_ctrl_pts < 4 || n_ctrl_pts > n_points {
+        return points.clone();
+    }
 
-    let p_x = &nt_n_inv * &nt * qx;
-    let p_y = &nt_n_inv * &nt * qy;
-    let p_z = &nt_n_inv * &nt * qz;
+    let max_knot = (n_ctrl_pts - DEGREE) as f32;
+    let u = chord_length_param(points, max_knot);
 
-    let mut control_points = Vec::with_capacity(n_ctrl_pts);
-    for i in 0..n_ctrl_pts {
-        control_points.push(Vec3::new(p_x[i], p_y[i], p_z[i]));
+    // Work relative to the centroid, so the ridge pulls towards the stroke
+    // rather than the origin
+    let centroid =
+        points.iter().fold(DVec3::ZERO, |acc, p| acc + p.as_dvec3()) / n_points as f64;
+
+    // Each row of N has four non-zeros, so N^T N has three sub-diagonals
+    let mut a = BandedMatrix::new(n_ctrl_pts);
+    let mut b = vec![DVec3::ZERO; n_ctrl_pts];
+    for (p, &t) in points.iter().zip(&u) {
+        let (first, basis) = basis_functions(t, n_ctrl_pts);
+        let q = p.as_dvec3() - centroid;
+        for r in 0..=DEGREE {
+            b[first + r] += q * basis[r] as f64;
+            for c in 0..=r {
+                a.add(first + r, first + c, (basis[r] * basis[c]) as f64);
+            }
+        }
+    }
+
+    let weight = smoothing as f64 * n_points as f64 / n_ctrl_pts as f64;
+    if weight > 0.0 {
+        for i in 0..n_ctrl_pts - 2 {
+            let d = [1.0, -2.0, 1.0];
+            for r in 0..3 {
+                for c in 0..=r {
+                    a.add(i + r, i + c, weight * d[r] * d[c]);
+                }
+            }
+        }
+    }
+
+    let solution = a.cholesky_solve(&b).or_else(|| {
+        log::warn!("Degenerate stroke fit, retrying with regularization");
+        a.add_diagonal(a.max_diagonal().max(1.0) * 1e-6);
+        a.cholesky_solve(&b)
+    });
+    match solution {
+        Some(x) => x.iter().map(|p| (*p + centroid).as_vec3()).collect(),
+        None => points.clone(),
     }
-    control_points
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -293,211 +673,553 @@ pub enum Axis {
     Z,
 }
 
+impl Axis {
+    fn unit(self) -> Vec3 {
+        match self {
+            Axis::X => Vec3::X,
+            Axis::Y => Vec3::Y,
+            Axis::Z => Vec3::Z,
+        }
+    }
+}
+
+fn default_crease_angle() -> f32 {
+    DEFAULT_CREASE_ANGLE
+}
+
+/// How vertex normals are generated from the faces around them.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "mode", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum Shading {
+    /// Every face has its own normal, for faceted low-poly looks
+    Flat,
+    /// Normals are averaged across every edge, however sharp
+    Smooth,
+    /// Edges where the faces meet at more than `crease_angle` degrees are
+    /// hard, the rest are smooth
+    Auto {
+        #[serde(default = "default_crease_angle")]
+        crease_angle: f32,
+    },
+}
+
+impl Default for Shading {
+    fn default() -> Self {
+        Shading::Auto {
+            crease_angle: DEFAULT_CREASE_ANGLE,
+        }
+    }
+}
+
+impl Shading {
+    pub fn validate(&self, v: &mut Validator) {
+        if let Shading::Auto { crease_angle } = self {
+            v.in_range("creaseAngle", *crease_angle, 0.0, 180.0);
+        }
+    }
+
+    /// Smallest cosine between two face normals that still share a vertex
+    /// normal
+    fn min_cos(&self) -> f32 {
+        match self {
+            Shading::Flat => 1.0,
+            Shading::Smooth => -1.0,
+            Shading::Auto { crease_angle } => crease_angle.to_radians().cos(),
+        }
+    }
+}
+
 impl Mesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Vec2>,
+        triangles: Vec<[u32; 3]>,
+    ) -> Self {
+        Mesh {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            shading: None,
+            bvh: OnceLock::new(),
+        }
+    }
+
+    /// Generate normals with `shading`, and again after every operation
+    /// that changes the surface. Meshes without a shading keep the normals
+    /// they were built or loaded with.
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = Some(shading);
+        self.recalculate_normals();
+    }
+
+    pub fn shading(&self) -> Option<Shading> {
+        self.shading
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    pub fn triangles(&self) -> &[[u32; 3]] {
+        &self.triangles
+    }
+
+    /// Vertex positions to edit, dropping the triangle hierarchy
+    pub fn positions_mut(&mut self) -> &mut Vec<Vec3> {
+        self.geometry_changed();
+        &mut self.positions
+    }
+
+    /// Triangles to edit, dropping the triangle hierarchy
+    pub fn triangles_mut(&mut self) -> &mut Vec<[u32; 3]> {
+        self.geometry_changed();
+        &mut self.triangles
+    }
+
+    /// The triangle hierarchy for the queries below, built on first use and
+    /// dropped whenever vertices or triangles change.
+    pub fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| Bvh::new(self))
+    }
+
+    /// Drop the triangle hierarchy, to be rebuilt by the next query
+    pub fn geometry_changed(&mut self) {
+        self.bvh.take();
+    }
+
+    /// Closest triangle hit by the ray within `max_distance`, see [`Bvh`]
+    pub fn ray_cast(&self, origin: Vec3, dir: Vec3, max_distance: f32) -> Option<RayHit> {
+        self.bvh().ray_cast(self, origin, dir, max_distance)
+    }
+
+    /// Closest point on the surface within `max_distance` of `p`
+    pub fn closest_point(&self, p: Vec3, max_distance: f32) -> Option<ClosestPoint> {
+        self.bvh().closest_point(self, p, max_distance)
+    }
+
+    /// Indices of the triangles touching the box
+    pub fn overlap_aabb(&self, bounds: &Aabb) -> Vec<u32> {
+        self.bvh().overlap_aabb(self, bounds)
+    }
+
+    /// Indices of the triangles touching the sphere
+    pub fn overlap_sphere(&self, center: Vec3, radius: f32) -> Vec<u32> {
+        self.bvh().overlap_sphere(self, center, radius)
+    }
+
+    /// Indices of the triangles touching the triangle with corners `tri`
+    pub fn overlap_triangle(&self, tri: [Vec3; 3]) -> Vec<u32> {
+        self.bvh().overlap_triangle(self, tri)
+    }
+
+    /// Pairs of touching triangles of this mesh and `other`, which has to be
+    /// in the same space
+    pub fn overlap_mesh(&self, other: &Mesh) -> Vec<(u32, u32)> {
+        self.bvh().overlap_mesh(self, other.bvh(), other)
+    }
+
+    /// Add the vertices and triangles of `other` to this mesh
+    pub fn append(&mut self, other: &Mesh) {
+        self.geometry_changed();
+        // Attributes only one of the meshes has are dropped, as they would not line up
+        let has_normals = self.normals.len() == self.positions.len()
+            && other.normals.len() == other.positions.len();
+        let has_uvs = self.uvs.len() == self.positions.len()
+            && other.uvs.len() == other.positions.len();
+        let offset = self.positions.len() as u32;
+        self.positions.extend_from_slice(&other.positions);
+        if has_normals {
+            self.normals.extend_from_slice(&other.normals);
+        } else {
+            self.normals.clear();
+        }
+        if has_uvs {
+            self.uvs.extend_from_slice(&other.uvs);
+        } else {
+            self.uvs.clear();
+        }
+        self.triangles.extend(other.triangles.iter().map(|t| t.map(|i| i + offset)));
+        self.reshade();
+    }
+
     /// Uniform translation - move entire mesh by offset
     pub fn translate(&mut self, offset: Vec3) {
+        self.geometry_changed();
         for pos in &mut self.positions {
             *pos += offset;
         }
         // Normals don't change with translation
     }
 
-    /// Move a single vertex by offset
+    /// Move a single vertex by offset, together with the copies of it split
+    /// off along hard edges
     pub fn translate_vertex(&mut self, vertex_index: usize, offset: Vec3) {
-        if vertex_index < self.positions.len() {
-            self.positions[vertex_index] += offset;
+        if let Some(&from) = self.positions.get(vertex_index) {
+            self.geometry_changed();
+            for pos in &mut self.positions {
+                if *pos == from {
+                    *pos += offset;
+                }
+            }
             self.recalculate_normals();
         }
     }
 
-    /// Rotate around specified axis by angle in radians
-    pub fn rotate(&mut self, axis: Axis, angle: f32) {
-        let cos_a = angle.cos();
-        let sin_a = angle.sin();
-
-        match axis {
-            Axis::X => { // X-axis rotation
-                for pos in &mut self.positions {
-                    let y = pos.y * cos_a - pos.z * sin_a;
-                    let z = pos.y * sin_a + pos.z * cos_a;
-                    pos.y = y;
-                    pos.z = z;
-                }
+    /// Apply an affine transform. Normals are carried by the inverse
+    /// transpose, so authored normals and hard edges survive rotation,
+    /// non-uniform scale and shear alike, and UVs are left as they are.
+    /// Tangents are derived from both when converting to a Bevy mesh, so
+    /// they follow. Mirroring transforms also flip the winding, which keeps
+    /// faces pointing outwards.
+    pub fn transform(&mut self, matrix: Affine3A) {
+        self.geometry_changed();
+        for pos in &mut self.positions {
+            *pos = matrix.transform_point3(*pos);
+        }
 
-                for normal in &mut self.normals {
-                    let y = normal.y * cos_a - normal.z * sin_a;
-                    let z = normal.y * sin_a + normal.z * cos_a;
-                    normal.y = y;
-                    normal.z = z;
-                }
-            },
-            Axis::Y => { // Y-axis rotation
-                for pos in &mut self.positions {
-                    let x = pos.x * cos_a + pos.z * sin_a;
-                    let z = -pos.x * sin_a + pos.z * cos_a;
-                    pos.x = x;
-                    pos.z = z;
-                }
+        // The cofactor matrix is the inverse transpose times the
+        // determinant, and stays finite for scales that flatten the mesh
+        let linear = matrix.matrix3;
+        let cofactor = Mat3A::from_cols(
+            linear.y_axis.cross(linear.z_axis),
+            linear.z_axis.cross(linear.x_axis),
+            linear.x_axis.cross(linear.y_axis),
+        );
+        let det = linear.determinant();
+        let sign = if det < 0.0 { -1.0 } else { 1.0 };
+        for normal in &mut self.normals {
+            *normal = (cofactor * *normal * sign).normalize_or_zero();
+        }
+        if det < 0.0 {
+            self.flip_winding_order();
+        }
+        // Non-uniform scales change the angles between faces
+        self.reshade();
+    }
 
-                for normal in &mut self.normals {
-                    let x = normal.x * cos_a + normal.z * sin_a;
-                    let z = -normal.x * sin_a + normal.z * cos_a;
-                    normal.x = x;
-                    normal.z = z;
-                }
-            },
-            Axis::Z => { // Z-axis rotation
-                for pos in &mut self.positions {
-                    let x = pos.x * cos_a - pos.y * sin_a;
-                    let y = pos.x * sin_a + pos.y * cos_a;
-                    pos.x = x;
-                    pos.y = y;
-                }
+    /// Rotate about the origin
+    pub fn rotate_quat(&mut self, rotation: Quat) {
+        self.transform(Affine3A::from_quat(rotation));
+    }
 
-                for normal in &mut self.normals {
-                    let x = normal.x * cos_a - normal.y * sin_a;
-                    let y = normal.x * sin_a + normal.y * cos_a;
-                    normal.x = x;
-                    normal.y = y;
-                }
-            },
+    /// Rotate about `axis` through the origin by `angle` in radians,
+    /// counter-clockwise looking down the axis. `axis` need not be unit
+    /// length.
+    pub fn rotate_axis_angle(&mut self, axis: Vec3, angle: f32) {
+        if let Some(axis) = axis.try_normalize() {
+            self.rotate_quat(Quat::from_axis_angle(axis, angle));
         }
     }
 
-    /// Uniform scaling by a single factor
+    /// Rotate around specified axis by angle in radians
+    pub fn rotate(&mut self, axis: Axis, angle: f32) {
+        self.rotate_axis_angle(axis.unit(), angle);
+    }
+
+    /// Uniform scaling by a single factor. A negative factor mirrors the
+    /// mesh through the origin
     pub fn scale(&mut self, factor: f32) {
-        for pos in &mut self.positions {
-            *pos *= factor;
-        }
-        // Normals don't need to change for uniform scaling
+        self.transform(Affine3A::from_scale(Vec3::splat(factor)));
     }
 
     /// Non-uniform scaling along each axis
     pub fn scale_xyz(&mut self, scale_x: f32, scale_y: f32, scale_z: f32) {
-        for pos in &mut self.positions {
-            pos.x *= scale_x;
-            pos.y *= scale_y;
-            pos.z *= scale_z;
-        }
-        // Non-uniform scaling affects normals
-        self.recalculate_normals();
+        self.transform(Affine3A::from_scale(Vec3::new(scale_x, scale_y, scale_z)));
     }
 
-    /// Reflect across specified axis (flip coordinates)
+    /// Reflect across the plane through the origin perpendicular to `axis`
     pub fn reflect(&mut self, axis: Axis) {
-        match axis {
-            Axis::X => { // Reflect across X-axis
-                for pos in &mut self.positions {
-                    pos.x = -pos.x;
-                }
-            },
-            Axis::Y => { // Reflect across Y-axis
-                for pos in &mut self.positions {
-                    pos.y = -pos.y;
-                }
-            },
-            Axis::Z => { // Reflect across Z-axis
-                for pos in &mut self.positions {
-                    pos.z = -pos.z;
-                }
-            },
-        }
-
-        self.flip_winding_order();
-        self.recalculate_normals();
+        self.transform(Affine3A::from_scale(Vec3::ONE - 2.0 * axis.unit()));
     }
 
-    /// Subdivide each triangle into 4 smaller triangles (Loop subdivision)
+    /// Subdivide each triangle into 4 smaller triangles (Loop subdivision).
+    /// Edges where the normals are split stay sharp, see [`HalfEdgeMesh`]
     pub fn subdivide(&mut self) {
-        let mut edge_to_midpoint = std::collections::HashMap::new();
-        let mut new_triangles = Vec::new();
-
-        // Helper to get or create midpoint vertex
-        let mut get_midpoint = |edge: (u32, u32),
-                                positions: &mut Vec<Vec3>,
-                                normals: &mut Vec<Vec3>,
-                                uvs: &mut Vec<Vec2>| -> u32 {
-            let ordered_edge = if edge.0 < edge.1 { edge } else { (edge.1, edge.0) };
-
-            if let Some(&midpoint_idx) = edge_to_midpoint.get(&ordered_edge) {
-                return midpoint_idx;
-            }
+        self.replace(HalfEdgeMesh::from_mesh(self).loop_subdivide().to_mesh());
+    }
 
-            let v0_idx = edge.0 as usize;
-            let v1_idx = edge.1 as usize;
+    /// Catmull-Clark subdivision, turning each triangle into 3 quads
+    pub fn catmull_clark(&mut self) {
+        self.replace(HalfEdgeMesh::from_mesh(self).catmull_clark().to_mesh());
+    }
 
-            let midpoint_pos = (positions[v0_idx] + positions[v1_idx]) * 0.5;
-            let midpoint_normal = (normals[v0_idx] + normals[v1_idx]).normalize();
-            let midpoint_uv = (uvs[v0_idx] + uvs[v1_idx]) * 0.5;
+    /// Reduce the triangle count with quadric error decimation, keeping
+    /// boundaries and UV seams in place. See [`decimate`]
+    pub fn decimate(&mut self, options: &DecimateOptions) {
+        self.replace(decimate::decimate(self, options));
+    }
 
-            let new_idx = positions.len() as u32;
-            positions.push(midpoint_pos);
-            normals.push(midpoint_normal);
-            uvs.push(midpoint_uv);
+    /// Combine with another closed mesh, see [`csg`]
+    pub fn boolean(&mut self, other: &Mesh, op: BooleanOp) -> Result<(), String> {
+        self.replace(csg::boolean(self, other, op)?);
+        Ok(())
+    }
 
-            edge_to_midpoint.insert(ordered_edge, new_idx);
-            new_idx
-        };
+    /// Weld, clean up and close the mesh as far as `options` asks, and
+    /// report its problems. See [`repair`]
+    pub fn repair(&mut self, options: &RepairOptions) -> MeshReport {
+        let (mesh, report) = repair::repair(self, options);
+        self.replace(mesh);
+        report
+    }
 
-        // Process each triangle
-        for triangle in &self.triangles {
-            let v0 = triangle[0];
-            let v1 = triangle[1];
-            let v2 = triangle[2];
+    /// Take the surface of `mesh`, keeping this mesh's shading
+    fn replace(&mut self, mesh: Mesh) {
+        let shading = self.shading;
+        *self = mesh;
+        self.shading = shading;
+        self.reshade();
+    }
 
-            // Get midpoints for each edge
-            let m01 = get_midpoint((v0, v1), &mut self.positions, &mut self.normals, &mut self.uvs);
-            let m12 = get_midpoint((v1, v2), &mut self.positions, &mut self.normals, &mut self.uvs);
-            let m20 = get_midpoint((v2, v0), &mut self.positions, &mut self.normals, &mut self.uvs);
+    /// Generate normals again if the mesh has a shading
+    fn reshade(&mut self) {
+        if self.shading.is_some() {
+            self.recalculate_normals();
+        }
+    }
 
-            // Create 4 new triangles
-            new_triangles.push([v0, m01, m20]);    // Corner triangle 0
-            new_triangles.push([v1, m12, m01]);    // Corner triangle 1
-            new_triangles.push([v2, m20, m12]);    // Corner triangle 2
-            new_triangles.push([m01, m12, m20]);   // Center triangle
+    /// Generate normals from the faces with the mesh's shading, or the
+    /// default crease angle if it has none. Each corner averages the faces
+    /// around its point that meet its own face within the crease angle,
+    /// weighted by their angle at the point. Vertices whose corners end up
+    /// with different normals are split, so existing vertices keep their
+    /// indices and the copies are added at the end. Points are welded
+    /// first, so faces on either side of a UV seam still share normals.
+    pub(crate) fn recalculate_normals(&mut self) {
+        let min_cos = self.shading.unwrap_or_default().min_cos() - COPLANAR_TOLERANCE;
+        let (welded, point) = halfedge::weld(&self.positions);
+
+        // Unit face normals and the angle of each corner. Degenerate faces
+        // have no direction and add nothing.
+        let faces: Vec<(Vec3, [f32; 3])> = self
+            .triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| self.positions[i as usize]);
+                let normal = (b - a).cross(c - a).normalize_or_zero();
+                if normal == Vec3::ZERO {
+                    return (normal, [0.0; 3]);
+                }
+                let angle = |p: Vec3, q: Vec3, r: Vec3| (q - p).angle_between(r - p);
+                (normal, [angle(a, b, c), angle(b, c, a), angle(c, a, b)])
+            })
+            .collect();
+
+        // Corners around each welded point, as (triangle, corner)
+        let mut start = vec![0; welded.len() + 1];
+        for t in &self.triangles {
+            for &i in t {
+                start[point[i as usize] as usize + 1] += 1;
+            }
+        }
+        for p in 0..welded.len() {
+            start[p + 1] += start[p];
+        }
+        let mut fill = start.clone();
+        let mut corners = vec![(0, 0); start[welded.len()]];
+        for (t, tri) in self.triangles.iter().enumerate() {
+            for (k, &i) in tri.iter().enumerate() {
+                let p = point[i as usize] as usize;
+                corners[fill[p]] = (t, k);
+                fill[p] += 1;
+            }
         }
 
-        self.triangles = new_triangles;
-        self.recalculate_normals();
+        let n = self.positions.len();
+        let has_uvs = self.uvs.len() == n;
+        let mut normals = self.normals.clone();
+        normals.resize(n, Vec3::Y);
+        let mut used = vec![false; n];
+        let mut copies = HashMap::new();
+        for t in 0..self.triangles.len() {
+            let own = faces[t].0;
+            for k in 0..3 {
+                let i = self.triangles[t][k] as usize;
+                let p = point[i] as usize;
+                let sum: Vec3 = corners[start[p]..start[p + 1]]
+                    .iter()
+                    .map(|&(other, corner)| (faces[other].0, faces[other].1[corner]))
+                    .filter(|(normal, _)| own == Vec3::ZERO || own.dot(*normal) >= min_cos)
+                    .map(|(normal, angle)| normal * angle)
+                    .sum();
+                let fallback = if own == Vec3::ZERO { Vec3::Y } else { own };
+                let normal = sum.try_normalize().unwrap_or(fallback);
+
+                if !used[i] {
+                    used[i] = true;
+                    normals[i] = normal;
+                } else if normals[i] != normal {
+                    let key = (i, (normal + Vec3::ZERO).to_array().map(f32::to_bits));
+                    let copy = *copies.entry(key).or_insert_with(|| {
+                        self.positions.push(self.positions[i]);
+                        if has_uvs {
+                            self.uvs.push(self.uvs[i]);
+                        }
+                        normals.push(normal);
+                        self.positions.len() as u32 - 1
+                    });
+                    self.triangles[t][k] = copy;
+                }
+            }
+        }
+        self.normals = normals;
+        self.geometry_changed();
     }
 
-    /// Recalculate normals based on triangle geometry
-    fn recalculate_normals(&mut self) {
-        // Reset all normals to zero
-        self.normals.fill(Vec3::ZERO);
+    /// Flip the winding order of all triangles (used after reflection)
+    fn flip_winding_order(&mut self) {
+        for triangle in &mut self.triangles {
+            triangle.swap(0, 2);
+        }
+    }
+}
 
-        // Accumulate face normals for each vertex
-        for triangle in &self.triangles {
-            let v0 = self.positions[triangle[0] as usize];
-            let v1 = self.positions[triangle[1] as usize];
-            let v2 = self.positions[triangle[2] as usize];
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Unit cube sharing its 8 corners between faces, without normals. Also
+    /// used by the tests of the mesh operations.
+    pub(crate) fn welded_cube() -> Mesh {
+        let positions = (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32))
+            .collect();
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let triangles = quads
+            .iter()
+            .flat_map(|[a, b, c, d]| [[*a, *b, *c], [*a, *c, *d]])
+            .collect();
+        Mesh::new(positions, Vec::new(), Vec::new(), triangles)
+    }
 
-            let face_normal = (v1 - v0).cross(v2 - v0);
-            let face_normal = if face_normal.length() > 0.0 {
-                face_normal.normalize()
-            } else {
-                Vec3::Y // fallback
-            };
+    #[test]
+    fn crease_angle_splits_cube_corners() {
+        let mut cube = welded_cube();
+        cube.set_shading(Shading::Auto { crease_angle: 30.0 });
+        assert_eq!(cube.positions().len(), 24);
+        assert_eq!(cube.normals.len(), 24);
+        for t in cube.triangles() {
+            let [a, b, c] = t.map(|i| cube.positions()[i as usize]);
+            let face = (b - a).cross(c - a).normalize();
+            for i in t {
+                assert!(cube.normals[*i as usize].abs_diff_eq(face, 1e-5));
+            }
+        }
+    }
 
-            self.normals[triangle[0] as usize] += face_normal;
-            self.normals[triangle[1] as usize] += face_normal;
-            self.normals[triangle[2] as usize] += face_normal;
+    #[test]
+    fn smooth_shading_keeps_cube_corners() {
+        let mut cube = welded_cube();
+        cube.set_shading(Shading::Smooth);
+        assert_eq!(cube.positions().len(), 8);
+        let center = Vec3::splat(0.5);
+        for (p, n) in cube.positions().iter().zip(&cube.normals) {
+            assert!(n.abs_diff_eq((*p - center).normalize(), 1e-5));
         }
+    }
 
-        // Normalize all accumulated normals
-        for normal in &mut self.normals {
-            *normal = if normal.length() > 0.0 {
-                normal.normalize()
-            } else {
-                Vec3::Y // fallback for degenerate cases
-            };
+    #[test]
+    fn shading_survives_operations() {
+        let mut cube = welded_cube();
+        cube.set_shading(Shading::Flat);
+        cube.subdivide();
+        assert_eq!(cube.shading(), Some(Shading::Flat));
+        assert_eq!(cube.normals.len(), cube.positions().len());
+    }
+
+    #[test]
+    fn fit_follows_a_line() {
+        let points: PointSet = (0..40)
+            .map(|i| Vec3::new(i as f32 * 0.1, 1.0, 2.0))
+            .collect();
+        let ctrl = fit_bspline_control_points(&points, 6, 0.0);
+        assert_eq!(ctrl.len(), 6);
+        for p in &ctrl {
+            assert!((p.y - 1.0).abs() < 1e-4 && (p.z - 2.0).abs() < 1e-4);
         }
+        let max_t = (ctrl.len() - DEGREE) as f32;
+        assert!(eval_bspline_fixed(&ctrl, 0.0).abs_diff_eq(points[0], 1e-3));
+        assert!(eval_bspline_fixed(&ctrl, max_t).abs_diff_eq(points[39], 1e-3));
     }
 
-    /// Flip the winding order of all triangles (used after reflection)
-    fn flip_winding_order(&mut self) {
-        for triangle in &mut self.triangles {
-            triangle.swap(0, 2);
+    #[test]
+    fn fit_of_repeated_points_does_not_fail() {
+        let tap = vec![Vec3::new(1.0, 2.0, 3.0); 12];
+        let ctrl = fit_bspline_control_points(&tap, 4, 0.0);
+        assert!(!ctrl.is_empty());
+        for p in ctrl {
+            assert!(p.abs_diff_eq(tap[0], 1e-3));
+        }
+    }
+
+    #[test]
+    fn cholesky_solves_banded_system() {
+        // Tridiagonal [2 -1 0; -1 2 -1; 0 -1 2] with x = (1, 2, 3)
+        let mut a = BandedMatrix::new(3);
+        for i in 0..3 {
+            a.add(i, i, 2.0);
+        }
+        a.add(1, 0, -1.0);
+        a.add(2, 1, -1.0);
+        let b = [DVec3::splat(0.0), DVec3::splat(0.0), DVec3::splat(4.0)];
+        let x = a.cholesky_solve(&b).unwrap();
+        for (x, expected) in x.iter().zip([1.0, 2.0, 3.0]) {
+            assert!((x.x - expected).abs() < 1e-9);
+        }
+        assert!(BandedMatrix::new(3).cholesky_solve(&b).is_none());
+    }
+
+    /// Two turns of a helix around Z
+    fn helix() -> Vec<Vec3> {
+        (0..200)
+            .map(|i| {
+                let a = i as f32 * 0.02 * std::f32::consts::TAU;
+                Vec3::new(a.cos(), a.sin(), i as f32 * 0.01)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_are_perpendicular_to_the_curve() {
+        let mut curve = helix();
+        // A finger resting in place repeats points
+        curve.insert(50, curve[50]);
+        let tangents = curve_tangents(&curve);
+        for mode in [FrameMode::ParallelTransport, FrameMode::CameraFacing] {
+            let frames = tube_frames(&curve, &tangents, Vec3::new(0.0, -5.0, 1.0), mode);
+            assert_eq!(frames.len(), curve.len());
+            for ((right, up), t) in frames.iter().zip(&tangents) {
+                assert!(right.is_normalized() && up.is_normalized());
+                assert!(right.dot(*t).abs() < 1e-4 && up.dot(*t).abs() < 1e-4);
+                assert!(right.dot(*up).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_transport_does_not_twist() {
+        let curve = helix();
+        let tangents = curve_tangents(&curve);
+        let frames = tube_frames(
+            &curve,
+            &tangents,
+            Vec3::new(0.0, -5.0, 1.0),
+            FrameMode::ParallelTransport,
+        );
+        // Rotation minimizing frames turn no more than the tangent does
+        for i in 1..curve.len() {
+            let frame_turn = frames[i - 1].1.angle_between(frames[i].1);
+            let tangent_turn = tangents[i - 1].angle_between(tangents[i]);
+            assert!(frame_turn <= tangent_turn + 1e-3);
         }
     }
 }
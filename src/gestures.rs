@@ -0,0 +1,208 @@
+//! Two-finger gestures for the orbit camera: pinch to zoom, drag to pan the
+//! orbit center and twist to turn around it.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::scene3d::{MyPluginConfig, OrbitCamera, TouchInput};
+
+/// What a two-finger twist does.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TwistMode {
+    /// Turn around the vertical axis, like a one-finger horizontal drag
+    #[default]
+    Azimuth,
+    /// Roll the camera around its view direction
+    Roll,
+    None,
+}
+
+#[derive(Resource, Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GestureSettings {
+    /// Exponent applied to the pinch ratio, 0 disables zooming
+    pub pinch_sensitivity: f32,
+    /// 1 keeps the orbit center under the fingers, 0 disables panning
+    pub pan_sensitivity: f32,
+    pub twist_sensitivity: f32,
+    pub twist: TwistMode,
+    pub min_radius: f32,
+    pub max_radius: f32,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        Self {
+            pinch_sensitivity: 1.0,
+            pan_sensitivity: 1.0,
+            twist_sensitivity: 1.0,
+            twist: TwistMode::Azimuth,
+            min_radius: 0.5,
+            max_radius: 200.0,
+        }
+    }
+}
+
+/// The two pointers as seen on the previous frame.
+#[derive(Resource, Default)]
+pub struct GestureState {
+    last: Option<[Vec2; 2]>,
+}
+
+pub(crate) fn handle_gestures(
+    input: Res<TouchInput>,
+    settings: Res<GestureSettings>,
+    mut state: ResMut<GestureState>,
+    mut orbit: ResMut<OrbitCamera>,
+    mut config: ResMut<MyPluginConfig>,
+    mut cameras: Query<(&mut Transform, &Projection), With<Camera3d>>,
+    windows: Query<&Window>,
+) {
+    let [(_, a), (_, b), ..] = input.pointers[..] else {
+        state.last = None;
+        return;
+    };
+    let Some([la, lb]) = state.last.replace([a, b]) else {
+        return;
+    };
+    let Ok((mut transform, projection)) = cameras.single_mut() else {
+        return;
+    };
+
+    // Pinch: spreading the fingers moves the camera closer
+    let (d0, d1) = (la.distance(lb), a.distance(b));
+    if d0 > 1.0 && d1 > 1.0 {
+        let ratio = (d0 / d1).powf(settings.pinch_sensitivity);
+        orbit.radius = (orbit.radius * ratio).clamp(settings.min_radius, settings.max_radius);
+    }
+
+    // Pan: move the orbit center with the midpoint of the fingers
+    let delta = (a + b) / 2.0 - (la + lb) / 2.0;
+    let world_per_pixel = match (projection, windows.single()) {
+        (Projection::Perspective(p), Ok(window)) => {
+            2.0 * orbit.radius * (p.fov / 2.0).tan() / window.physical_height().max(1) as f32
+        }
+        _ => config.move_strength,
+    };
+    let pan = (transform.right() * -delta.x + transform.up() * delta.y)
+        * world_per_pixel
+        * settings.pan_sensitivity;
+    config.camera_pos += pan;
+
+    // Twist
+    let angle = (lb - la).angle_to(b - a) * settings.twist_sensitivity;
+    match settings.twist {
+        TwistMode::Azimuth => orbit.azimuth -= angle,
+        TwistMode::Roll => orbit.roll += angle,
+        TwistMode::None => {}
+    }
+
+    *transform = orbit.transform(config.camera_pos);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::MaterialConfig;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn world(settings: GestureSettings) -> World {
+        let mut world = World::new();
+        world.insert_resource(settings);
+        world.init_resource::<GestureState>();
+        world.init_resource::<TouchInput>();
+        world.init_resource::<OrbitCamera>();
+        world.insert_resource(MyPluginConfig {
+            env_lightcolor: Color::WHITE,
+            move_strength: 0.01,
+            meshes: Vec::new(),
+            lights: Vec::new(),
+            camera_pos: Vec3::ZERO,
+            mode: default(),
+            sketch_history: Vec::new(),
+            last_stroke: None,
+            sketch_material: MaterialConfig::default(),
+        });
+        world.spawn((
+            Camera3d::default(),
+            Transform::default(),
+            Projection::default(),
+        ));
+        world
+    }
+
+    /// Put two fingers at `a` and `b` for one frame
+    fn touch(world: &mut World, a: Vec2, b: Vec2) {
+        world.resource_mut::<TouchInput>().pointers = vec![(1, a), (2, b)];
+        world.run_system_once(handle_gestures).unwrap();
+    }
+
+    /// Move two fingers from `from` to `to` pixels apart, then lift them
+    fn pinch(world: &mut World, from: f32, to: f32) {
+        touch(
+            world,
+            Vec2::new(-from / 2.0, 0.0),
+            Vec2::new(from / 2.0, 0.0),
+        );
+        touch(world, Vec2::new(-to / 2.0, 0.0), Vec2::new(to / 2.0, 0.0));
+        world.resource_mut::<TouchInput>().pointers.clear();
+        world.run_system_once(handle_gestures).unwrap();
+    }
+
+    #[test]
+    fn pinch_zoom_stays_within_limits() {
+        let mut world = world(GestureSettings::default());
+        pinch(&mut world, 100.0, 200.0);
+        assert_eq!(world.resource::<OrbitCamera>().radius, 5.0);
+        for _ in 0..5 {
+            pinch(&mut world, 10.0, 1000.0);
+        }
+        assert_eq!(world.resource::<OrbitCamera>().radius, 0.5);
+        for _ in 0..5 {
+            pinch(&mut world, 1000.0, 10.0);
+        }
+        assert_eq!(world.resource::<OrbitCamera>().radius, 200.0);
+    }
+
+    #[test]
+    fn twist_follows_the_mode() {
+        for (twist, azimuth, roll) in [
+            (TwistMode::Azimuth, -std::f32::consts::FRAC_PI_2, 0.0),
+            (TwistMode::Roll, 0.0, std::f32::consts::FRAC_PI_2),
+            (TwistMode::None, 0.0, 0.0),
+        ] {
+            let mut world = world(GestureSettings {
+                twist,
+                ..Default::default()
+            });
+            touch(&mut world, Vec2::new(-50.0, 0.0), Vec2::new(50.0, 0.0));
+            touch(&mut world, Vec2::new(0.0, -50.0), Vec2::new(0.0, 50.0));
+            let orbit = world.resource::<OrbitCamera>();
+            assert!((orbit.azimuth - azimuth).abs() < 1e-5);
+            assert!((orbit.roll - roll).abs() < 1e-5);
+            assert_eq!(orbit.radius, 10.0);
+        }
+    }
+
+    #[test]
+    fn pan_moves_the_center_with_the_fingers() {
+        let mut world = world(GestureSettings::default());
+        touch(&mut world, Vec2::new(0.0, 0.0), Vec2::new(100.0, 0.0));
+        touch(&mut world, Vec2::new(10.0, 0.0), Vec2::new(110.0, 0.0));
+        // Without a window the pan uses `move_strength` per pixel
+        let center = world.resource::<MyPluginConfig>().camera_pos;
+        assert!(center.abs_diff_eq(Vec3::new(-0.1, 0.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn lifting_a_finger_ends_the_gesture() {
+        let mut world = world(GestureSettings::default());
+        touch(&mut world, Vec2::new(0.0, 0.0), Vec2::new(100.0, 0.0));
+        world.resource_mut::<TouchInput>().pointers.pop();
+        world.run_system_once(handle_gestures).unwrap();
+        // The next two-finger frame starts a new gesture instead of zooming
+        touch(&mut world, Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0));
+        assert_eq!(world.resource::<OrbitCamera>().radius, 10.0);
+    }
+}
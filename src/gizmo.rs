@@ -0,0 +1,348 @@
+//! Translate/rotate/scale handles drawn on the selected object.
+//!
+//! In select mode, a touch that starts on a handle drags it instead of picking
+//! a new object. Translation and rotation work along world axes, scaling along
+//! the object's own axes.
+
+use bevy::prelude::*;
+
+use crate::history::{Command, History};
+use crate::scene3d::{InteractionMode, LastTouchInput, MyPluginConfig, Selection, TouchInput};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GizmoDrag {
+    /// 0, 1, 2 for X, Y, Z
+    axis: usize,
+    start: Transform,
+    /// Accumulated drag along the handle: world units for translation,
+    /// radians for rotation and a factor offset for scaling
+    amount: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct TransformGizmo {
+    pub mode: GizmoMode,
+    /// Step for translation and scale factors, if set
+    pub grid_snap: Option<f32>,
+    /// Step for rotation in radians, if set
+    pub angle_snap: Option<f32>,
+    drag: Option<GizmoDrag>,
+}
+
+const AXIS_COLORS: [Color; 3] = [
+    Color::srgb(0.9, 0.2, 0.2),
+    Color::srgb(0.2, 0.8, 0.2),
+    Color::srgb(0.2, 0.4, 0.9),
+];
+const ACTIVE_COLOR: Color = Color::srgb(1.0, 0.8, 0.0);
+/// How close a touch has to be to a handle, in logical pixels
+const HANDLE_TOUCH_RADIUS: f32 = 24.0;
+const RING_SEGMENTS: usize = 32;
+
+fn snap(value: f32, step: Option<f32>) -> f32 {
+    match step {
+        Some(step) if step > 0.0 => (value / step).round() * step,
+        _ => value,
+    }
+}
+
+/// Handles keep the same size on screen regardless of zoom.
+fn handle_length(camera: &GlobalTransform, origin: Vec3) -> f32 {
+    camera.translation().distance(origin) * 0.15
+}
+
+fn handle_dir(mode: GizmoMode, axis: usize, transform: &Transform) -> Vec3 {
+    let dir = Vec3::AXES[axis];
+    match mode {
+        GizmoMode::Scale => transform.rotation * dir,
+        GizmoMode::Translate | GizmoMode::Rotate => dir,
+    }
+}
+
+/// World space points along a handle, used both to draw and to hit test it.
+fn handle_points(mode: GizmoMode, axis: usize, transform: &Transform, len: f32) -> Vec<Vec3> {
+    let origin = transform.translation;
+    let dir = handle_dir(mode, axis, transform);
+    match mode {
+        GizmoMode::Translate | GizmoMode::Scale => vec![origin, origin + dir * len],
+        GizmoMode::Rotate => {
+            let rot = Quat::from_rotation_arc(Vec3::Z, dir);
+            (0..=RING_SEGMENTS)
+                .map(|i| {
+                    let a = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                    origin + rot * Vec3::new(a.cos(), a.sin(), 0.0) * len
+                })
+                .collect()
+        }
+    }
+}
+
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+/// The axis whose handle is under `pos` (logical pixels), if any.
+fn hit_handle(
+    mode: GizmoMode,
+    transform: &Transform,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    pos: Vec2,
+) -> Option<usize> {
+    let len = handle_length(camera_transform, transform.translation);
+    let mut best: Option<(usize, f32)> = None;
+    for axis in 0..3 {
+        let points: Option<Vec<Vec2>> = handle_points(mode, axis, transform, len)
+            .into_iter()
+            .map(|p| camera.world_to_viewport(camera_transform, p).ok())
+            .collect();
+        let Some(points) = points else {
+            continue;
+        };
+        let d = points
+            .windows(2)
+            .map(|w| distance_to_segment(pos, w[0], w[1]))
+            .fold(f32::INFINITY, f32::min);
+        if d < HANDLE_TOUCH_RADIUS && best.is_none_or(|(_, bd)| d < bd) {
+            best = Some((axis, d));
+        }
+    }
+    best.map(|(axis, _)| axis)
+}
+
+/// Start dragging if `pos` (physical pixels) is on a handle of the selection.
+pub fn try_begin_drag(
+    gizmo: &mut TransformGizmo,
+    selection: &Selection,
+    objects: &Query<&Transform, Without<Camera3d>>,
+    camera: (&Camera, &GlobalTransform),
+    scale_factor: f32,
+    pos: Vec2,
+) -> bool {
+    let Some(transform) = selection.entity.and_then(|e| objects.get(e).ok()) else {
+        return false;
+    };
+    let (camera, camera_transform) = camera;
+    let hit = hit_handle(
+        gizmo.mode,
+        transform,
+        camera,
+        camera_transform,
+        pos / scale_factor,
+    );
+    if let Some(axis) = hit {
+        gizmo.drag = Some(GizmoDrag {
+            axis,
+            start: *transform,
+            amount: 0.0,
+        });
+    }
+    hit.is_some()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn drag_gizmo(
+    config: Res<MyPluginConfig>,
+    input: Res<TouchInput>,
+    mut lastinput: ResMut<LastTouchInput>,
+    mut gizmo: ResMut<TransformGizmo>,
+    mut history: ResMut<History>,
+    meshes: Res<Assets<Mesh>>,
+    selection: Res<Selection>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut objects: Query<&mut Transform, Without<Camera3d>>,
+) {
+    if config.mode != InteractionMode::Select {
+        gizmo.drag = None;
+        return;
+    }
+    let Some(cpos) = input.touch else {
+        // The drag is over, record it as a single step
+        if let Some(drag) = gizmo.drag.take() {
+            record_drag(&mut history, &meshes, &selection, &objects, drag);
+        }
+        return;
+    };
+    if input.is_multi_touch() {
+        // A second finger pauses the drag
+        lastinput.touch = None;
+        return;
+    }
+    let lastpos = lastinput.touch.replace(cpos);
+    let (Some(lastpos), Some(mut drag)) = (lastpos, gizmo.drag) else {
+        return;
+    };
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.single(), cameras.single()) else {
+        return;
+    };
+    let Some(mut transform) = selection.entity.and_then(|e| objects.get_mut(e).ok()) else {
+        gizmo.drag = None;
+        return;
+    };
+
+    let scale_factor = window.scale_factor();
+    let (cur, last) = (cpos / scale_factor, lastpos / scale_factor);
+    let origin = drag.start.translation;
+    let dir = handle_dir(gizmo.mode, drag.axis, &drag.start);
+    let len = handle_length(camera_transform, origin);
+    let (Ok(o_screen), Ok(tip_screen)) = (
+        camera.world_to_viewport(camera_transform, origin),
+        camera.world_to_viewport(camera_transform, origin + dir * len),
+    ) else {
+        return;
+    };
+
+    match gizmo.mode {
+        GizmoMode::Translate | GizmoMode::Scale => {
+            // Project the drag onto the handle as seen on screen
+            let axis_screen = tip_screen - o_screen;
+            if axis_screen.length_squared() < 1.0 {
+                // Handle points at the camera, no meaningful direction
+                return;
+            }
+            let along = (cur - last).dot(axis_screen) / axis_screen.length_squared();
+            drag.amount += along * len;
+        }
+        GizmoMode::Rotate => {
+            let (v0, v1) = (last - o_screen, cur - o_screen);
+            if v0.length_squared() < 1.0 || v1.length_squared() < 1.0 {
+                return;
+            }
+            // Viewport y points down, so a positive angle turns clockwise on
+            // screen, which is a negative turn about an axis facing the camera
+            let screen_angle = v0.perp_dot(v1).atan2(v0.dot(v1));
+            let facing_camera = dir.dot(*camera_transform.forward()) < 0.0;
+            drag.amount += if facing_camera {
+                -screen_angle
+            } else {
+                screen_angle
+            };
+        }
+    }
+
+    let start = drag.start;
+    *transform = match gizmo.mode {
+        GizmoMode::Translate => {
+            start.with_translation(start.translation + dir * snap(drag.amount, gizmo.grid_snap))
+        }
+        GizmoMode::Rotate => {
+            let angle = snap(drag.amount, gizmo.angle_snap);
+            start.with_rotation(Quat::from_axis_angle(dir, angle) * start.rotation)
+        }
+        GizmoMode::Scale => {
+            // Dragging one handle length doubles the size
+            let factor = snap(1.0 + drag.amount / len, gizmo.grid_snap).max(0.01);
+            let mut scale = start.scale;
+            scale[drag.axis] *= factor;
+            start.with_scale(scale)
+        }
+    };
+    gizmo.drag = Some(drag);
+}
+
+fn record_drag(
+    history: &mut History,
+    meshes: &Assets<Mesh>,
+    selection: &Selection,
+    objects: &Query<&mut Transform, Without<Camera3d>>,
+    drag: GizmoDrag,
+) {
+    let (Some(id), Some(entity)) = (selection.id, selection.entity) else {
+        return;
+    };
+    let Ok(after) = objects.get(entity) else {
+        return;
+    };
+    if *after != drag.start {
+        history.push(
+            Command::Transform {
+                id,
+                before: drag.start,
+                after: *after,
+            },
+            meshes,
+        );
+    }
+}
+
+pub(crate) fn draw_gizmo(
+    mut gizmos: Gizmos,
+    config: Res<MyPluginConfig>,
+    gizmo: Res<TransformGizmo>,
+    selection: Res<Selection>,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    objects: Query<&Transform, Without<Camera3d>>,
+) {
+    if config.mode != InteractionMode::Select {
+        return;
+    }
+    let (Some(transform), Ok(camera_transform)) = (
+        selection.entity.and_then(|e| objects.get(e).ok()),
+        cameras.single(),
+    ) else {
+        return;
+    };
+    let len = handle_length(camera_transform, transform.translation);
+    for (axis, &axis_color) in AXIS_COLORS.iter().enumerate() {
+        let color = match gizmo.drag {
+            Some(drag) if drag.axis == axis => ACTIVE_COLOR,
+            _ => axis_color,
+        };
+        let points = handle_points(gizmo.mode, axis, transform, len);
+        match gizmo.mode {
+            GizmoMode::Translate => {
+                gizmos.arrow(points[0], points[1], color);
+            }
+            GizmoMode::Rotate => gizmos.linestrip(points, color),
+            GizmoMode::Scale => {
+                gizmos.line(points[0], points[1], color);
+                let tip = Transform::from_translation(points[1])
+                    .with_rotation(transform.rotation)
+                    .with_scale(Vec3::splat(len * 0.1));
+                gizmos.cuboid(tip, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_snap_to_the_nearest_step() {
+        assert_eq!(snap(0.26, Some(0.25)), 0.25);
+        assert_eq!(snap(0.38, Some(0.25)), 0.5);
+        assert_eq!(snap(-0.38, Some(0.25)), -0.5);
+        let angle = 15f32.to_radians();
+        assert!((snap(20f32.to_radians(), Some(angle)) - angle).abs() < 1e-6);
+    }
+
+    #[test]
+    fn values_pass_through_without_a_step() {
+        assert_eq!(snap(0.26, None), 0.26);
+        assert_eq!(snap(0.26, Some(0.0)), 0.26);
+        assert_eq!(snap(0.26, Some(-1.0)), 0.26);
+    }
+
+    #[test]
+    fn scale_handles_follow_the_object_rotation() {
+        let transform =
+            Transform::from_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        let scale = handle_dir(GizmoMode::Scale, 0, &transform);
+        assert!(scale.abs_diff_eq(Vec3::Y, 1e-6));
+        assert_eq!(handle_dir(GizmoMode::Translate, 0, &transform), Vec3::X);
+        assert_eq!(handle_dir(GizmoMode::Rotate, 0, &transform), Vec3::X);
+    }
+}
@@ -0,0 +1,788 @@
+//! Half-edge adjacency for [`Mesh`], and Loop and Catmull-Clark subdivision
+//! on top of it.
+//!
+//! A [`Mesh`] splits a vertex wherever its normal or UV changes, so a cube
+//! has 24 vertices. Subdividing that directly would tear the surface apart
+//! along every seam. [`HalfEdgeMesh`] welds vertices by position and keeps
+//! UVs per face corner instead, so the surface is connected while a seam
+//! can still have a different UV on either side.
+//!
+//! Edges where the input normals are split, like the rim of a stroke cap,
+//! are creases and stay sharp when subdividing. Boundary edges follow the
+//! same rules. UVs are interpolated linearly within each face, which keeps
+//! seams where they are.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{geometry::Mesh, validation::Validator};
+
+/// Marks a missing twin or UV.
+const NONE: u32 = u32::MAX;
+
+/// Vertices closer than this fraction of the mesh size are welded.
+const WELD_TOLERANCE: f32 = 1e-6;
+
+/// Subdivision steps allowed on a scene object, each one multiplies the
+/// face count by 4.
+const MAX_LEVELS: u32 = 4;
+
+/// Merge positions closer than [`WELD_TOLERANCE`] of the mesh size. Returns
+/// the merged positions and the merged index of each input position.
+///
+/// Generated meshes rarely close their seams exactly, e.g. a sphere computes
+/// its first and last column with different angles.
+pub(crate) fn weld(positions: &[Vec3]) -> (Vec<Vec3>, Vec<u32>) {
+    let (min, max) = positions
+        .iter()
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        });
+    let tolerance = (WELD_TOLERANCE * (max - min).length()).max(f32::MIN_POSITIVE);
+    let mut welded = Vec::new();
+    let mut weld = HashMap::new();
+    let index = positions
+        .iter()
+        .map(|p| {
+            let key = p.to_array().map(|c| (c / tolerance).round() as i64);
+            *weld.entry(key).or_insert_with(|| {
+                welded.push(*p);
+                welded.len() as u32 - 1
+            })
+        })
+        .collect();
+    (welded, index)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HalfEdge {
+    /// Vertex the half-edge starts at
+    origin: u32,
+    /// UV of the face corner at `origin`, [`NONE`] without UVs
+    uv: u32,
+    /// Half-edge running the other way along the same edge, [`NONE`] on a
+    /// boundary
+    twin: u32,
+    face: u32,
+    /// Kept sharp by subdivision, set on both halves
+    crease: bool,
+}
+
+/// Neighbourhood of a vertex, as far as the subdivision rules need it.
+#[derive(Debug, Clone, Copy, Default)]
+struct Star {
+    /// Sum of the neighbouring vertices
+    sum: Vec3,
+    valence: u32,
+    /// Sum of the neighbours across crease or boundary edges
+    sharp_sum: Vec3,
+    sharp: u32,
+}
+
+impl Star {
+    /// Position of a vertex on two sharp edges, which moves along them, or
+    /// on more, which is a corner and stays put. `None` when the smooth rule
+    /// applies.
+    fn sharp_position(&self, p: Vec3) -> Option<Vec3> {
+        match self.sharp {
+            0 | 1 => None,
+            2 => Some(0.75 * p + 0.125 * self.sharp_sum),
+            _ => Some(p),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HalfEdgeMesh {
+    /// Welded vertex positions
+    pub positions: Vec<Vec3>,
+    /// Indexed by the face corners
+    pub uvs: Vec<Vec2>,
+    /// The half-edges of a face are stored together, in order
+    halfedges: Vec<HalfEdge>,
+    /// Start of the half-edges of each face, and the end of the last one
+    face_start: Vec<u32>,
+}
+
+impl HalfEdgeMesh {
+    /// Weld a triangle mesh. Edges where its normals are split become
+    /// creases.
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        let faces: Vec<Vec<u32>> = mesh.triangles().iter().map(|t| t.to_vec()).collect();
+        Self::from_polygons(mesh.positions(), &mesh.normals, &mesh.uvs, &faces)
+    }
+
+    /// Weld polygons over split vertices as in [`Mesh`]. `normals` and
+    /// `uvs` are either empty or have an entry per vertex. Vertices are
+    /// welded when they are within a millionth of the mesh size.
+    pub fn from_polygons(
+        positions: &[Vec3],
+        normals: &[Vec3],
+        uvs: &[Vec2],
+        faces: &[Vec<u32>],
+    ) -> Self {
+        let has_normals = normals.len() == positions.len();
+        let has_uvs = uvs.len() == positions.len();
+
+        let (welded, vertex) = weld(positions);
+
+        let mut corner_uvs = Vec::new();
+        let mut uv_of = HashMap::new();
+        let mut corners = Vec::with_capacity(faces.len());
+        // Split vertices at both ends of each directed edge
+        let mut directed = HashMap::new();
+        for face in faces {
+            if face.iter().any(|&i| i as usize >= positions.len()) {
+                continue;
+            }
+            let corner: Vec<[u32; 2]> = face
+                .iter()
+                .map(|&i| {
+                    let uv = if has_uvs {
+                        let key = (
+                            vertex[i as usize],
+                            uvs[i as usize].to_array().map(f32::to_bits),
+                        );
+                        *uv_of.entry(key).or_insert_with(|| {
+                            corner_uvs.push(uvs[i as usize]);
+                            corner_uvs.len() as u32 - 1
+                        })
+                    } else {
+                        NONE
+                    };
+                    [vertex[i as usize], uv]
+                })
+                .collect();
+            for k in 0..face.len() {
+                let (i, j) = (face[k], face[(k + 1) % face.len()]);
+                directed.insert((vertex[i as usize], vertex[j as usize]), (i, j));
+            }
+            corners.push(corner);
+        }
+
+        let mut creases = HashSet::new();
+        if has_normals {
+            let same = |a: u32, b: u32| {
+                let (a, b) = (normals[a as usize], normals[b as usize]);
+                a == b || a.normalize_or_zero().dot(b.normalize_or_zero()) > 0.9999
+            };
+            for (&(a, b), &(ia, ib)) in &directed {
+                if let Some(&(jb, ja)) = directed.get(&(b, a)) {
+                    if a < b && !(same(ia, ja) && same(ib, jb)) {
+                        creases.insert([a, b]);
+                    }
+                }
+            }
+        }
+        Self::build(welded, corner_uvs, &corners, &creases)
+    }
+
+    /// Link up faces given as `[vertex, uv]` corners. `creases` holds edges
+    /// as `[lower, higher]` vertex pairs.
+    ///
+    /// An edge is only shared by two faces when they run along it in
+    /// opposite directions and no other face uses it. Anything else, like
+    /// three faces on one edge, is treated as separate boundary edges.
+    fn build(
+        positions: Vec<Vec3>,
+        uvs: Vec<Vec2>,
+        faces: &[Vec<[u32; 2]>],
+        creases: &HashSet<[u32; 2]>,
+    ) -> Self {
+        let mut halfedges = Vec::new();
+        let mut face_start = vec![0];
+        for face in faces {
+            let n = face.len();
+            let degenerate = (0..n).any(|k| face[k][0] == face[(k + 1) % n][0]);
+            if n < 3 || degenerate {
+                continue;
+            }
+            let f = face_start.len() as u32 - 1;
+            for &[origin, uv] in face {
+                halfedges.push(HalfEdge {
+                    origin,
+                    uv,
+                    twin: NONE,
+                    face: f,
+                    crease: false,
+                });
+            }
+            face_start.push(halfedges.len() as u32);
+        }
+
+        let mut mesh = Self {
+            positions,
+            uvs,
+            halfedges,
+            face_start,
+        };
+        let mut by_edge: HashMap<(u32, u32), Vec<u32>> = HashMap::new();
+        for h in 0..mesh.halfedges.len() as u32 {
+            by_edge
+                .entry((mesh.origin(h), mesh.dest(h)))
+                .or_default()
+                .push(h);
+        }
+        for h in 0..mesh.halfedges.len() as u32 {
+            let (a, b) = (mesh.origin(h), mesh.dest(h));
+            if let (Some([_]), Some(&[t])) = (
+                by_edge.get(&(a, b)).map(Vec::as_slice),
+                by_edge.get(&(b, a)).map(Vec::as_slice),
+            ) {
+                mesh.halfedges[h as usize].twin = t;
+                mesh.halfedges[h as usize].crease = creases.contains(&edge_key(a, b));
+            }
+        }
+        mesh
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.face_start.len() - 1
+    }
+
+    fn face(&self, f: u32) -> Range<u32> {
+        self.face_start[f as usize]..self.face_start[f as usize + 1]
+    }
+
+    fn next(&self, h: u32) -> u32 {
+        let face = self.face(self.halfedges[h as usize].face);
+        if h + 1 == face.end { face.start } else { h + 1 }
+    }
+
+    fn prev(&self, h: u32) -> u32 {
+        let face = self.face(self.halfedges[h as usize].face);
+        if h == face.start { face.end - 1 } else { h - 1 }
+    }
+
+    fn origin(&self, h: u32) -> u32 {
+        self.halfedges[h as usize].origin
+    }
+
+    fn dest(&self, h: u32) -> u32 {
+        self.origin(self.next(h))
+    }
+
+    fn twin(&self, h: u32) -> Option<u32> {
+        let twin = self.halfedges[h as usize].twin;
+        (twin != NONE).then_some(twin)
+    }
+
+    /// Boundary edges are as sharp as creases.
+    fn is_sharp(&self, h: u32) -> bool {
+        let he = &self.halfedges[h as usize];
+        he.twin == NONE || he.crease
+    }
+
+    fn has_uvs(&self) -> bool {
+        !self.uvs.is_empty()
+    }
+
+    fn is_triangles(&self) -> bool {
+        (0..self.face_count() as u32).all(|f| self.face(f).len() == 3)
+    }
+
+    /// One half-edge per edge, and the edge each half-edge belongs to.
+    fn edges(&self) -> (Vec<u32>, Vec<u32>) {
+        let mut edges = Vec::new();
+        let mut edge_of = vec![NONE; self.halfedges.len()];
+        for h in 0..self.halfedges.len() as u32 {
+            if edge_of[h as usize] != NONE {
+                continue;
+            }
+            edge_of[h as usize] = edges.len() as u32;
+            if let Some(t) = self.twin(h) {
+                edge_of[t as usize] = edges.len() as u32;
+            }
+            edges.push(h);
+        }
+        (edges, edge_of)
+    }
+
+    fn stars(&self, edges: &[u32]) -> Vec<Star> {
+        let mut stars = vec![Star::default(); self.positions.len()];
+        for &h in edges {
+            let (a, b) = (self.origin(h), self.dest(h));
+            let sharp = self.is_sharp(h);
+            for (v, other) in [(a, b), (b, a)] {
+                let star = &mut stars[v as usize];
+                let p = self.positions[other as usize];
+                star.sum += p;
+                star.valence += 1;
+                if sharp {
+                    star.sharp_sum += p;
+                    star.sharp += 1;
+                }
+            }
+        }
+        stars
+    }
+
+    /// Crease edges as `[lower, higher]` vertex pairs.
+    fn creases(&self) -> HashSet<[u32; 2]> {
+        (0..self.halfedges.len() as u32)
+            .filter(|&h| self.halfedges[h as usize].crease)
+            .map(|h| {
+                let (a, b) = (self.origin(h), self.dest(h));
+                [a.min(b), a.max(b)]
+            })
+            .collect()
+    }
+
+    fn face_corners(&self, f: u32) -> Vec<[u32; 2]> {
+        self.face(f)
+            .map(|h| [self.origin(h), self.halfedges[h as usize].uv])
+            .collect()
+    }
+
+    /// Split polygons into triangle fans. The new diagonals are smooth.
+    pub fn triangulated(&self) -> Self {
+        let mut faces = Vec::new();
+        for f in 0..self.face_count() as u32 {
+            let corners = self.face_corners(f);
+            for k in 1..corners.len() - 1 {
+                faces.push(vec![corners[0], corners[k], corners[k + 1]]);
+            }
+        }
+        Self::build(
+            self.positions.clone(),
+            self.uvs.clone(),
+            &faces,
+            &self.creases(),
+        )
+    }
+
+    /// One step of Loop subdivision, splitting every triangle into four.
+    /// Polygons are triangulated first.
+    pub fn loop_subdivide(&self) -> Self {
+        if !self.is_triangles() {
+            return self.triangulated().loop_subdivide();
+        }
+        let (edges, edge_of) = self.edges();
+        let stars = self.stars(&edges);
+        let nv = self.positions.len() as u32;
+
+        let mut positions = Vec::with_capacity(self.positions.len() + edges.len());
+        for (p, star) in self.positions.iter().zip(&stars) {
+            let n = star.valence as f32;
+            let smooth = if star.valence == 0 {
+                *p
+            } else {
+                let c = 0.375 + 0.25 * (std::f32::consts::TAU / n).cos();
+                let beta = (0.625 - c * c) / n;
+                (1.0 - n * beta) * p + beta * star.sum
+            };
+            positions.push(star.sharp_position(*p).unwrap_or(smooth));
+        }
+        for &h in &edges {
+            let a = self.positions[self.origin(h) as usize];
+            let b = self.positions[self.dest(h) as usize];
+            let p = match self.twin(h) {
+                Some(t) if !self.is_sharp(h) => {
+                    let c = self.positions[self.origin(self.prev(h)) as usize];
+                    let d = self.positions[self.origin(self.prev(t)) as usize];
+                    0.375 * (a + b) + 0.125 * (c + d)
+                }
+                _ => 0.5 * (a + b),
+            };
+            positions.push(p);
+        }
+
+        let mut uvs = UvPool::new(&self.uvs);
+        let mut faces = Vec::with_capacity(self.face_count() * 4);
+        let mut creases = HashSet::new();
+        for f in 0..self.face_count() as u32 {
+            let h: Vec<u32> = self.face(f).collect();
+            let v = h.iter().map(|&h| self.origin(h)).collect::<Vec<_>>();
+            let uv = h
+                .iter()
+                .map(|&h| self.halfedges[h as usize].uv)
+                .collect::<Vec<_>>();
+            let e = h
+                .iter()
+                .map(|&h| nv + edge_of[h as usize])
+                .collect::<Vec<_>>();
+            let m: Vec<u32> = (0..3)
+                .map(|k| uvs.midpoint(uv[k], uv[(k + 1) % 3]))
+                .collect();
+            for k in 0..3 {
+                let prev = (k + 2) % 3;
+                faces.push(vec![[v[k], uv[k]], [e[k], m[k]], [e[prev], m[prev]]]);
+                if self.halfedges[h[k] as usize].crease {
+                    creases.insert(edge_key(v[k], e[k]));
+                    creases.insert(edge_key(e[k], v[(k + 1) % 3]));
+                }
+            }
+            faces.push(vec![[e[0], m[0]], [e[1], m[1]], [e[2], m[2]]]);
+        }
+        Self::build(positions, uvs.uvs, &faces, &creases)
+    }
+
+    /// One step of Catmull-Clark subdivision, splitting every n-sided face
+    /// into n quads.
+    pub fn catmull_clark(&self) -> Self {
+        let (edges, edge_of) = self.edges();
+        let stars = self.stars(&edges);
+        let nv = self.positions.len() as u32;
+        let ne = edges.len() as u32;
+
+        let face_points: Vec<Vec3> = (0..self.face_count() as u32)
+            .map(|f| {
+                let face = self.face(f);
+                let n = face.len() as f32;
+                face.map(|h| self.positions[self.origin(h) as usize])
+                    .sum::<Vec3>()
+                    / n
+            })
+            .collect();
+        // Sum and count of the face points around each vertex
+        let mut around = vec![(Vec3::ZERO, 0u32); self.positions.len()];
+        for he in &self.halfedges {
+            let entry = &mut around[he.origin as usize];
+            entry.0 += face_points[he.face as usize];
+            entry.1 += 1;
+        }
+
+        let mut positions = Vec::with_capacity((nv + ne) as usize + face_points.len());
+        for (v, (p, star)) in self.positions.iter().zip(&stars).enumerate() {
+            let (face_sum, faces) = around[v];
+            let smooth = if star.valence < 3 || faces == 0 {
+                *p
+            } else {
+                let n = star.valence as f32;
+                let q = face_sum / faces as f32;
+                let r = 0.5 * (*p + star.sum / n);
+                (q + 2.0 * r + (n - 3.0) * p) / n
+            };
+            positions.push(star.sharp_position(*p).unwrap_or(smooth));
+        }
+        for &h in &edges {
+            let a = self.positions[self.origin(h) as usize];
+            let b = self.positions[self.dest(h) as usize];
+            let p = match self.twin(h) {
+                Some(t) if !self.is_sharp(h) => {
+                    let f1 = face_points[self.halfedges[h as usize].face as usize];
+                    let f2 = face_points[self.halfedges[t as usize].face as usize];
+                    0.25 * (a + b + f1 + f2)
+                }
+                _ => 0.5 * (a + b),
+            };
+            positions.push(p);
+        }
+        positions.extend_from_slice(&face_points);
+
+        let mut uvs = UvPool::new(&self.uvs);
+        let mut faces = Vec::with_capacity(self.halfedges.len());
+        let mut creases = HashSet::new();
+        for f in 0..self.face_count() as u32 {
+            let h: Vec<u32> = self.face(f).collect();
+            let n = h.len();
+            let v = h.iter().map(|&h| self.origin(h)).collect::<Vec<_>>();
+            let uv = h
+                .iter()
+                .map(|&h| self.halfedges[h as usize].uv)
+                .collect::<Vec<_>>();
+            let e = h
+                .iter()
+                .map(|&h| nv + edge_of[h as usize])
+                .collect::<Vec<_>>();
+            let m: Vec<u32> = (0..n)
+                .map(|k| uvs.midpoint(uv[k], uv[(k + 1) % n]))
+                .collect();
+            let center = uvs.average(&uv);
+            let fp = nv + ne + f;
+            for k in 0..n {
+                let prev = (k + n - 1) % n;
+                faces.push(vec![
+                    [v[k], uv[k]],
+                    [e[k], m[k]],
+                    [fp, center],
+                    [e[prev], m[prev]],
+                ]);
+                if self.halfedges[h[k] as usize].crease {
+                    creases.insert(edge_key(v[k], e[k]));
+                    creases.insert(edge_key(e[k], v[(k + 1) % n]));
+                }
+            }
+        }
+        Self::build(positions, uvs.uvs, &faces, &creases)
+    }
+
+    /// Triangulate back into a [`Mesh`]. Normals are smoothed around each
+    /// vertex up to the creases and boundaries, vertices are split where
+    /// the normal or the UV changes.
+    pub fn to_mesh(&self) -> Mesh {
+        // Corners on either side of a smooth edge share their normal
+        let mut sector: Vec<u32> = (0..self.halfedges.len() as u32).collect();
+        for h in 0..self.halfedges.len() as u32 {
+            let Some(t) = self.twin(h) else {
+                continue;
+            };
+            if h < t && !self.is_sharp(h) {
+                union(&mut sector, h, self.next(t));
+                union(&mut sector, self.next(h), t);
+            }
+        }
+
+        let mut sector_normals = vec![Vec3::ZERO; self.halfedges.len()];
+        for f in 0..self.face_count() as u32 {
+            // Twice the area along the normal, also for non-planar polygons
+            let normal: Vec3 = self
+                .face(f)
+                .map(|h| {
+                    let a = self.positions[self.origin(h) as usize];
+                    a.cross(self.positions[self.dest(h) as usize])
+                })
+                .sum();
+            for h in self.face(f) {
+                let root = find(&mut sector, h);
+                sector_normals[root as usize] += normal;
+            }
+        }
+
+        let mut mesh = Mesh::default();
+        let mut vertex_of = HashMap::new();
+        let mut corner = |mesh: &mut Mesh, h: u32| -> u32 {
+            let root = find(&mut sector, h);
+            let uv = self.halfedges[h as usize].uv;
+            *vertex_of.entry((root, uv)).or_insert_with(|| {
+                mesh.positions_mut()
+                    .push(self.positions[self.origin(h) as usize]);
+                mesh.normals
+                    .push(sector_normals[root as usize].normalize_or(Vec3::Y));
+                if uv != NONE {
+                    mesh.uvs.push(self.uvs[uv as usize]);
+                }
+                mesh.positions().len() as u32 - 1
+            })
+        };
+        for f in 0..self.face_count() as u32 {
+            let face = self.face(f);
+            let first = corner(&mut mesh, face.start);
+            for h in face.start + 1..face.end - 1 {
+                let b = corner(&mut mesh, h);
+                let c = corner(&mut mesh, h + 1);
+                mesh.triangles_mut().push([first, b, c]);
+            }
+        }
+        if !self.has_uvs() {
+            mesh.uvs.clear();
+        }
+        mesh
+    }
+}
+
+fn edge_key(a: u32, b: u32) -> [u32; 2] {
+    [a.min(b), a.max(b)]
+}
+
+/// Union-find root with path halving.
+fn find(parent: &mut [u32], mut x: u32) -> u32 {
+    while parent[x as usize] != x {
+        parent[x as usize] = parent[parent[x as usize] as usize];
+        x = parent[x as usize];
+    }
+    x
+}
+
+fn union(parent: &mut [u32], a: u32, b: u32) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    if a != b {
+        parent[a.max(b) as usize] = a.min(b);
+    }
+}
+
+/// UVs of a subdivided mesh: the old ones keep their index, and the
+/// midpoint of two UVs is shared by the faces on both sides of an edge
+/// unless it is a seam.
+struct UvPool {
+    uvs: Vec<Vec2>,
+    midpoints: HashMap<[u32; 2], u32>,
+}
+
+impl UvPool {
+    fn new(uvs: &[Vec2]) -> Self {
+        Self {
+            uvs: uvs.to_vec(),
+            midpoints: HashMap::new(),
+        }
+    }
+
+    fn midpoint(&mut self, a: u32, b: u32) -> u32 {
+        if a == NONE || b == NONE {
+            return NONE;
+        }
+        *self.midpoints.entry(edge_key(a, b)).or_insert_with(|| {
+            self.uvs
+                .push((self.uvs[a as usize] + self.uvs[b as usize]) * 0.5);
+            self.uvs.len() as u32 - 1
+        })
+    }
+
+    fn average(&mut self, corners: &[u32]) -> u32 {
+        if corners.contains(&NONE) {
+            return NONE;
+        }
+        let sum: Vec2 = corners.iter().map(|&i| self.uvs[i as usize]).sum();
+        self.uvs.push(sum / corners.len() as f32);
+        self.uvs.len() as u32 - 1
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SubdivisionScheme {
+    /// Smooths triangle meshes, polygons are triangulated first
+    #[default]
+    Loop,
+    /// Smooths quad meshes, any polygon becomes quads
+    CatmullClark,
+}
+
+/// Subdivision applied to a mesh object when it is built, keeping the
+/// original OBJ data.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Subdivision {
+    pub scheme: SubdivisionScheme,
+    pub levels: u32,
+}
+
+impl Default for Subdivision {
+    fn default() -> Self {
+        Self {
+            scheme: SubdivisionScheme::Loop,
+            levels: 1,
+        }
+    }
+}
+
+impl Subdivision {
+    pub fn validate(&self, v: &mut Validator) {
+        if self.levels > MAX_LEVELS {
+            v.field_error("levels", format!("must be at most {}", MAX_LEVELS));
+        }
+    }
+
+    pub fn apply(&self, mut mesh: HalfEdgeMesh) -> HalfEdgeMesh {
+        for _ in 0..self.levels {
+            mesh = match self.scheme {
+                SubdivisionScheme::Loop => mesh.loop_subdivide(),
+                SubdivisionScheme::CatmullClark => mesh.catmull_clark(),
+            };
+        }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit cube as an OBJ would load it, with each face on its own four
+    /// vertices carrying the face normal and the full UV square
+    fn split_cube() -> HalfEdgeMesh {
+        let corner =
+            |i: u32| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32);
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let square = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y];
+        let (mut positions, mut normals, mut uvs, mut faces) = (vec![], vec![], vec![], vec![]);
+        for quad in quads {
+            let [a, b, c, _] = quad.map(corner);
+            let normal = (b - a).cross(c - a).normalize();
+            let first = positions.len() as u32;
+            for (k, i) in quad.into_iter().enumerate() {
+                positions.push(corner(i));
+                normals.push(normal);
+                uvs.push(square[k]);
+            }
+            faces.push((first..first + 4).collect());
+        }
+        HalfEdgeMesh::from_polygons(&positions, &normals, &uvs, &faces)
+    }
+
+    fn on_cube(p: Vec3) -> bool {
+        let inside = p.cmpge(Vec3::splat(-1e-6)).all() && p.cmple(Vec3::splat(1.0 + 1e-6)).all();
+        let on_face = p
+            .to_array()
+            .iter()
+            .any(|c| c.abs() < 1e-6 || (c - 1.0).abs() < 1e-6);
+        inside && on_face
+    }
+
+    #[test]
+    fn cube_is_welded() {
+        let cube = split_cube();
+        assert_eq!(cube.positions.len(), 8);
+        assert_eq!(cube.face_count(), 6);
+    }
+
+    #[test]
+    fn loop_keeps_creased_cube_sharp() {
+        let cube = split_cube().loop_subdivide();
+        // 8 corners and a point on each of the 12 edges and 6 diagonals
+        assert_eq!(cube.positions.len(), 26);
+        assert_eq!(cube.face_count(), 48);
+        for i in 0..8u32 {
+            let corner = Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32);
+            assert!(cube.positions.contains(&corner));
+        }
+        assert!(cube.positions.iter().all(|p| on_cube(*p)));
+    }
+
+    #[test]
+    fn subdivision_keeps_uv_seams_split() {
+        let mesh = split_cube().loop_subdivide().to_mesh();
+        // Each corner is on three faces, which give it different UVs
+        let copies = mesh
+            .positions()
+            .iter()
+            .zip(&mesh.uvs)
+            .filter(|(p, _)| **p == Vec3::X)
+            .map(|(_, uv)| *uv)
+            .collect::<Vec<_>>();
+        assert_eq!(copies.len(), 3);
+        assert!(copies.iter().any(|uv| *uv != copies[0]));
+        // UVs stay within each face's square
+        assert!(
+            mesh.uvs
+                .iter()
+                .all(|uv| uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all())
+        );
+    }
+
+    #[test]
+    fn catmull_clark_rounds_a_smooth_cube() {
+        let corner =
+            |i: u32| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32);
+        let positions: Vec<Vec3> = (0..8).map(corner).collect();
+        let faces = vec![
+            vec![0, 2, 3, 1],
+            vec![4, 5, 7, 6],
+            vec![0, 1, 5, 4],
+            vec![2, 6, 7, 3],
+            vec![0, 4, 6, 2],
+            vec![1, 3, 7, 5],
+        ];
+        let cube = HalfEdgeMesh::from_polygons(&positions, &[], &[], &faces).catmull_clark();
+        assert_eq!(cube.face_count(), 24);
+        assert_eq!(cube.positions.len(), 8 + 12 + 6);
+        // Without creases the corners are pulled in
+        assert!(!cube.positions.contains(&Vec3::ZERO));
+        let center = Vec3::splat(0.5);
+        assert!(
+            cube.positions
+                .iter()
+                .all(|p| (*p - center).abs().max_element() <= 0.5)
+        );
+    }
+}
@@ -0,0 +1,536 @@
+//! Undo/redo for scene edits, sketch strokes and camera moves.
+//!
+//! Edits are recorded as commands holding both the old and the new state, so
+//! undoing and redoing are the same operation in opposite directions. Edits
+//! of existing objects are replayed through [`EditObjectEvent`] with recording
+//! turned off. Restored objects and the camera are applied right away, so
+//! edits replayed after them in the same frame find their target.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+
+use crate::{
+    material::MaterialConfig,
+    primitives::ObjKind,
+    scene3d::{
+        EditObjectEvent, MyPluginConfig, ObjectEdit, ObjectId, OrbitCamera, SceneObject,
+        ensure_tangents,
+    },
+};
+
+/// Everything needed to bring a removed object back.
+#[derive(Debug, Clone)]
+pub struct ObjectSnapshot {
+    pub id: ObjectId,
+    pub mesh: Mesh3d,
+    pub transform: Transform,
+    pub object: SceneObject,
+    pub visibility: Visibility,
+}
+
+impl ObjectSnapshot {
+    /// Rough number of bytes kept alive by this snapshot. The mesh handle
+    /// keeps the mesh asset loaded even after the entity is gone.
+    fn cost(&self, meshes: &Assets<Mesh>) -> usize {
+        let mesh = meshes.get(&self.mesh.0).map_or(0, |mesh| {
+            let indices = match mesh.indices() {
+                Some(Indices::U16(idx)) => idx.len() * 2,
+                Some(Indices::U32(idx)) => idx.len() * 4,
+                None => 0,
+            };
+            mesh.count_vertices() * mesh.get_vertex_size() as usize + indices
+        });
+        let data = match &self.object.kind {
+            ObjKind::Mesh(params) => params.data.len(),
+            ObjKind::Stroke(params) => {
+                params.data.len()
+                    + size_of_val(params.points.as_slice())
+                    + size_of_val(params.samples.as_slice())
+                    + size_of_val(params.curve.as_slice())
+            }
+            _ => 0,
+        };
+        size_of::<Self>() + mesh + data + self.object.label.len()
+    }
+
+    fn restore(&self, world: &mut World) {
+        // The material may have gained a normal map since the mesh was built.
+        // Only borrow the mesh mutably when it needs them, as that uploads it
+        // again.
+        if self.object.material.needs_tangents() {
+            if let Some(mesh) = world.resource_mut::<Assets<Mesh>>().get_mut(&self.mesh.0) {
+                ensure_tangents(mesh, &self.object.material);
+            }
+        }
+        let material = self
+            .object
+            .material
+            .to_standard_material(world.resource::<AssetServer>());
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(material);
+        world.spawn((
+            self.mesh.clone(),
+            MeshMaterial3d(material),
+            self.transform,
+            self.object.clone(),
+            self.visibility,
+            self.id,
+        ));
+    }
+}
+
+/// Where the camera orbits and from how far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraState {
+    pub orbit: OrbitCamera,
+    pub center: Vec3,
+}
+
+impl CameraState {
+    pub fn capture(world: &World) -> Self {
+        Self {
+            orbit: *world.resource::<OrbitCamera>(),
+            center: world.resource::<MyPluginConfig>().camera_pos,
+        }
+    }
+
+    pub fn apply(&self, world: &mut World) {
+        *world.resource_mut::<OrbitCamera>() = self.orbit;
+        world.resource_mut::<MyPluginConfig>().camera_pos = self.center;
+        let transform = self.orbit.transform(self.center);
+        let mut cameras = world.query_filtered::<&mut Transform, With<Camera3d>>();
+        for mut camera in cameras.iter_mut(world) {
+            *camera = transform;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Spawn(Box<ObjectSnapshot>),
+    Delete(Box<ObjectSnapshot>),
+    Transform {
+        id: ObjectId,
+        before: Transform,
+        after: Transform,
+    },
+    Material {
+        id: ObjectId,
+        before: MaterialConfig,
+        after: MaterialConfig,
+    },
+    Visible {
+        id: ObjectId,
+        before: bool,
+        after: bool,
+    },
+    Camera {
+        before: CameraState,
+        after: CameraState,
+        /// Whether a following camera change may be folded into this one,
+        /// so dragging a slider is a single step
+        merge: bool,
+    },
+    /// Steps done and undone as one, in order
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    fn cost(&self, meshes: &Assets<Mesh>) -> usize {
+        match self {
+            Command::Spawn(snapshot) | Command::Delete(snapshot) => snapshot.cost(meshes),
+            Command::Batch(commands) => commands.iter().map(|c| c.cost(meshes)).sum(),
+            _ => size_of::<Self>(),
+        }
+    }
+
+    /// Bring the scene to the state before (`undo`) or after the command.
+    fn apply(&self, world: &mut World, undo: bool) {
+        let (id, edit) = match self {
+            Command::Spawn(snapshot) | Command::Delete(snapshot) => {
+                if matches!(self, Command::Spawn(_)) != undo {
+                    snapshot.restore(world);
+                    return;
+                }
+                (snapshot.id, ObjectEdit::Delete)
+            }
+            Command::Transform { id, before, after } => {
+                (*id, ObjectEdit::Transform(*pick(undo, before, after)))
+            }
+            Command::Material { id, before, after } => {
+                (*id, ObjectEdit::Material(pick(undo, before, after).clone()))
+            }
+            Command::Visible { id, before, after } => {
+                (*id, ObjectEdit::Visible(*pick(undo, before, after)))
+            }
+            Command::Camera { before, after, .. } => {
+                pick(undo, before, after).apply(world);
+                return;
+            }
+            Command::Batch(commands) => {
+                if undo {
+                    commands.iter().rev().for_each(|c| c.apply(world, true));
+                } else {
+                    commands.iter().for_each(|c| c.apply(world, false));
+                }
+                return;
+            }
+        };
+        world.send_event(EditObjectEvent {
+            id,
+            edit,
+            record: false,
+        });
+    }
+}
+
+fn pick<T>(undo: bool, before: T, after: T) -> T {
+    if undo { before } else { after }
+}
+
+struct Entry {
+    command: Command,
+    cost: usize,
+}
+
+#[derive(Resource)]
+pub struct History {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+    /// Bytes held by both stacks
+    used: usize,
+    /// Oldest steps are dropped once `used` exceeds this
+    pub budget: usize,
+    pub max_steps: usize,
+    /// Camera state when the current touch began
+    camera_start: Option<CameraState>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            used: 0,
+            budget: 64 * 1024 * 1024,
+            max_steps: 256,
+            camera_start: None,
+        }
+    }
+}
+
+impl History {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Record a new step. This forgets everything that could be redone.
+    pub fn push(&mut self, command: Command, meshes: &Assets<Mesh>) {
+        for entry in self.redo.drain(..) {
+            self.used -= entry.cost;
+        }
+        if let (
+            Command::Camera {
+                after, merge: true, ..
+            },
+            Some(Entry {
+                command:
+                    Command::Camera {
+                        after: last,
+                        merge: true,
+                        ..
+                    },
+                ..
+            }),
+        ) = (&command, self.undo.back_mut())
+        {
+            *last = *after;
+            return;
+        }
+        let cost = command.cost(meshes);
+        self.used += cost;
+        self.undo.push_back(Entry { command, cost });
+        self.evict();
+    }
+
+    /// Drop the oldest steps until the history fits `budget` and
+    /// `max_steps`. The newest step is always kept.
+    fn evict(&mut self) {
+        while self.undo.len() > 1 && (self.used > self.budget || self.undo.len() > self.max_steps) {
+            if let Some(entry) = self.undo.pop_front() {
+                self.used -= entry.cost;
+            }
+        }
+    }
+
+    /// Remember the camera at the start of a touch, see [`Self::end_camera_move`].
+    pub fn begin_camera_move(&mut self, state: CameraState) {
+        self.camera_start = Some(state);
+    }
+
+    /// Record the camera move since [`Self::begin_camera_move`], if there was one.
+    pub fn end_camera_move(&mut self, state: CameraState, meshes: &Assets<Mesh>) {
+        if let Some(before) = self.camera_start.take() {
+            if before != state {
+                self.push(
+                    Command::Camera {
+                        before,
+                        after: state,
+                        merge: false,
+                    },
+                    meshes,
+                );
+            }
+        }
+    }
+}
+
+/// Record a step from outside the ECS schedule.
+pub fn record(world: &mut World, command: Command) {
+    world.resource_scope(|world, mut history: Mut<History>| {
+        history.push(command, world.resource::<Assets<Mesh>>());
+    });
+}
+
+/// Do a step from outside the ECS schedule and record it.
+pub fn perform(world: &mut World, command: Command) {
+    command.apply(world, false);
+    record(world, command);
+}
+
+/// Undo the last step. Returns false if there was nothing to undo.
+pub fn undo(world: &mut World) -> bool {
+    let Some(mut history) = world.get_resource_mut::<History>() else {
+        return false;
+    };
+    let Some(entry) = history.undo.pop_back() else {
+        return false;
+    };
+    entry.command.apply(world, true);
+    world.resource_mut::<History>().redo.push(entry);
+    true
+}
+
+/// Redo the last undone step. Returns false if there was nothing to redo.
+pub fn redo(world: &mut World) -> bool {
+    let Some(mut history) = world.get_resource_mut::<History>() else {
+        return false;
+    };
+    let Some(entry) = history.redo.pop() else {
+        return false;
+    };
+    entry.command.apply(world, false);
+    world.resource_mut::<History>().undo.push_back(entry);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::SphereParams;
+
+    fn world(history: History) -> World {
+        let mut world = World::new();
+        world.insert_resource(history);
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<Events<EditObjectEvent>>();
+        world.init_resource::<OrbitCamera>();
+        world.insert_resource(MyPluginConfig {
+            env_lightcolor: Color::WHITE,
+            move_strength: 0.01,
+            meshes: Vec::new(),
+            lights: Vec::new(),
+            camera_pos: Vec3::ZERO,
+            mode: default(),
+            sketch_history: Vec::new(),
+            last_stroke: None,
+            sketch_material: MaterialConfig::default(),
+        });
+        world
+    }
+
+    /// The edits sent since the last call
+    fn edits(world: &mut World) -> Vec<(u32, ObjectEdit)> {
+        world
+            .resource_mut::<Events<EditObjectEvent>>()
+            .drain()
+            .map(|e| (e.id.0, e.edit))
+            .collect()
+    }
+
+    fn moved(id: u32, x: f32) -> Command {
+        Command::Transform {
+            id: ObjectId(id),
+            before: Transform::default(),
+            after: Transform::from_xyz(x, 0.0, 0.0),
+        }
+    }
+
+    fn zoomed(from: f32, to: f32, merge: bool) -> Command {
+        let state = |radius| CameraState {
+            orbit: OrbitCamera {
+                radius,
+                ..default()
+            },
+            center: Vec3::ZERO,
+        };
+        Command::Camera {
+            before: state(from),
+            after: state(to),
+            merge,
+        }
+    }
+
+    fn spawned(id: u32) -> Command {
+        Command::Spawn(Box::new(ObjectSnapshot {
+            id: ObjectId(id),
+            mesh: Mesh3d::default(),
+            transform: Transform::default(),
+            object: SceneObject {
+                label: String::new(),
+                kind: ObjKind::Sphere(SphereParams::default()),
+                material: default(),
+            },
+            visibility: Visibility::Inherited,
+        }))
+    }
+
+    fn steps(world: &World) -> usize {
+        world.resource::<History>().undo.len()
+    }
+
+    #[test]
+    fn undo_and_redo_replay_the_step() {
+        let mut world = world(History::default());
+        assert!(!undo(&mut world));
+        record(&mut world, moved(1, 2.0));
+        assert!(undo(&mut world));
+        assert!(matches!(
+            edits(&mut world)[..],
+            [(1, ObjectEdit::Transform(t))] if t == Transform::default()
+        ));
+        assert!(!undo(&mut world));
+        assert!(redo(&mut world));
+        assert!(matches!(
+            edits(&mut world)[..],
+            [(1, ObjectEdit::Transform(t))] if t.translation.x == 2.0
+        ));
+        assert!(!redo(&mut world));
+    }
+
+    #[test]
+    fn a_new_step_forgets_the_redo_steps() {
+        let mut world = world(History::default());
+        record(&mut world, moved(1, 1.0));
+        record(&mut world, moved(1, 2.0));
+        undo(&mut world);
+        record(&mut world, moved(2, 3.0));
+        let history = world.resource::<History>();
+        assert!(!history.can_redo());
+        assert_eq!(history.used, 2 * size_of::<Command>());
+    }
+
+    #[test]
+    fn camera_drags_merge_into_one_step() {
+        let mut world = world(History::default());
+        record(&mut world, zoomed(10.0, 8.0, true));
+        record(&mut world, zoomed(8.0, 6.0, true));
+        assert_eq!(steps(&world), 1);
+        undo(&mut world);
+        assert_eq!(world.resource::<OrbitCamera>().radius, 10.0);
+        redo(&mut world);
+        assert_eq!(world.resource::<OrbitCamera>().radius, 6.0);
+        // A pinch is a step of its own, even after a drag
+        record(&mut world, zoomed(6.0, 4.0, false));
+        record(&mut world, zoomed(4.0, 2.0, true));
+        assert_eq!(steps(&world), 3);
+    }
+
+    #[test]
+    fn oldest_steps_are_evicted_over_budget() {
+        let step = size_of::<Command>();
+        let mut world = world(History {
+            budget: 2 * step,
+            ..default()
+        });
+        for x in 0..3 {
+            record(&mut world, moved(1, x as f32));
+        }
+        assert_eq!(steps(&world), 2);
+        assert_eq!(world.resource::<History>().used, 2 * step);
+    }
+
+    #[test]
+    fn step_count_is_limited() {
+        let mut world = world(History {
+            max_steps: 2,
+            ..default()
+        });
+        for x in 0..3 {
+            record(&mut world, moved(1, x as f32));
+        }
+        assert_eq!(steps(&world), 2);
+        undo(&mut world);
+        undo(&mut world);
+        assert!(matches!(
+            edits(&mut world)[..],
+            [(1, ObjectEdit::Transform(_)), (1, ObjectEdit::Transform(_))]
+        ));
+    }
+
+    #[test]
+    fn merged_steps_stay_within_the_budget() {
+        let step = size_of::<Command>();
+        let mut world = world(History {
+            budget: 2 * step,
+            ..default()
+        });
+        record(&mut world, moved(1, 1.0));
+        for radius in [8.0, 6.0, 4.0] {
+            record(&mut world, zoomed(radius + 2.0, radius, true));
+        }
+        record(&mut world, moved(1, 2.0));
+        assert_eq!(steps(&world), 2);
+        assert_eq!(world.resource::<History>().used, 2 * step);
+    }
+
+    #[test]
+    fn newest_step_is_kept_over_budget() {
+        let mut world = world(History {
+            budget: 0,
+            ..default()
+        });
+        record(&mut world, moved(1, 1.0));
+        record(&mut world, moved(2, 2.0));
+        assert_eq!(steps(&world), 1);
+        undo(&mut world);
+        assert!(matches!(edits(&mut world)[..], [(2, _)]));
+    }
+
+    #[test]
+    fn batches_are_undone_in_one_step() {
+        // A stroke and its symmetric copies
+        let mut world = world(History::default());
+        record(
+            &mut world,
+            Command::Batch(vec![spawned(1), spawned(2), spawned(3)]),
+        );
+        assert!(undo(&mut world));
+        assert!(!world.resource::<History>().can_undo());
+        let deleted: Vec<u32> = edits(&mut world)
+            .into_iter()
+            .map(|(id, edit)| {
+                assert!(matches!(edit, ObjectEdit::Delete));
+                id
+            })
+            .collect();
+        assert_eq!(deleted, [3, 2, 1]);
+    }
+}
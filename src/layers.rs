@@ -0,0 +1,441 @@
+//! Sketch layers: strokes are grouped into ordered layers that can be hidden
+//! or locked.
+//!
+//! Strokes stay ordinary scene objects, so picking, edits and undo work on
+//! them as on anything else. The layer a stroke is on is saved in its
+//! [`StrokeParams`], and its entity is parented to an entity for the layer,
+//! so hiding the layer hides all of its strokes.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    file_io, geometry,
+    primitives::{ObjKind, StrokeParams},
+    scene3d::{ObjectId, SceneObject},
+    stroke::StrokeStyle,
+    validation::Validator,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SketchLayer {
+    pub id: u32,
+    pub name: String,
+    pub visible: bool,
+    /// Locked layers take no new strokes, and their strokes can neither be
+    /// selected nor edited
+    pub locked: bool,
+}
+
+impl Default for SketchLayer {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            name: "Layer 1".to_string(),
+            visible: true,
+            locked: false,
+        }
+    }
+}
+
+impl SketchLayer {
+    pub fn validate(&self, v: &mut Validator) {
+        if self.id == u32::MAX {
+            v.field_error("id", format!("must be less than {}", u32::MAX));
+        }
+        if self.name.trim().is_empty() {
+            v.field_error("name", "must not be empty");
+        }
+    }
+
+    fn visibility(&self) -> Visibility {
+        if self.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        }
+    }
+}
+
+/// The layer the strokes of layer `.0` are parented to.
+#[derive(Component)]
+pub struct LayerRoot(pub u32);
+
+#[derive(Resource)]
+pub struct SketchLayers {
+    /// In drawing order
+    layers: Vec<SketchLayer>,
+    /// Layer new strokes go on
+    active: u32,
+    /// [`LayerRoot`] entity of each layer, spawned once it has strokes
+    roots: HashMap<u32, Entity>,
+}
+
+impl Default for SketchLayers {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl SketchLayers {
+    /// Start from saved layers, or a single empty one. The last layer is
+    /// active.
+    pub fn new(mut layers: Vec<SketchLayer>) -> Self {
+        if layers.is_empty() {
+            layers.push(SketchLayer::default());
+        }
+        let active = layers[layers.len() - 1].id;
+        Self {
+            layers,
+            active,
+            roots: HashMap::new(),
+        }
+    }
+
+    pub fn layers(&self) -> &[SketchLayer] {
+        &self.layers
+    }
+
+    pub fn get(&self, id: u32) -> Option<&SketchLayer> {
+        self.layers.iter().find(|l| l.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Result<&mut SketchLayer, String> {
+        self.layers
+            .iter_mut()
+            .find(|l| l.id == id)
+            .ok_or_else(|| format!("no layer with id {}", id))
+    }
+
+    pub fn active(&self) -> &SketchLayer {
+        // `new` and `add` keep the active layer in the list
+        self.get(self.active).unwrap_or(&self.layers[0])
+    }
+
+    pub fn set_active(&mut self, id: u32) -> Result<(), String> {
+        self.get_mut(id)?;
+        self.active = id;
+        Ok(())
+    }
+
+    /// Add a layer on top of the others and make it active.
+    pub fn add(&mut self, name: Option<String>) -> u32 {
+        // Layers and strokes from a scene cannot use `u32::MAX`, see
+        // `SketchLayer::validate`
+        let id = self
+            .layers
+            .iter()
+            .filter_map(|l| l.id.checked_add(1))
+            .max()
+            .unwrap_or(0);
+        self.layers.push(SketchLayer {
+            id,
+            name: name.unwrap_or_else(|| format!("Layer {}", self.layers.len() + 1)),
+            ..default()
+        });
+        self.active = id;
+        id
+    }
+
+    /// Whether `object` is a stroke on a locked layer.
+    pub fn locks(&self, object: &SceneObject) -> bool {
+        layer_of(object)
+            .and_then(|id| self.get(id))
+            .is_some_and(|l| l.locked)
+    }
+
+    /// The [`LayerRoot`] of layer `id`, spawning it first if needed. Strokes
+    /// saved on a layer that no longer exists bring it back.
+    fn root(&mut self, id: u32, commands: &mut Commands) -> Entity {
+        if self.get(id).is_none() {
+            self.layers.push(SketchLayer {
+                id,
+                name: format!("Layer {}", id + 1),
+                ..default()
+            });
+        }
+        let visibility = self
+            .get(id)
+            .map_or(Visibility::Inherited, SketchLayer::visibility);
+        *self.roots.entry(id).or_insert_with(|| {
+            commands
+                .spawn((LayerRoot(id), Transform::default(), visibility))
+                .id()
+        })
+    }
+}
+
+/// The layer of a stroke, `None` for objects that are not strokes.
+pub fn layer_of(object: &SceneObject) -> Option<u32> {
+    match &object.kind {
+        ObjKind::Stroke(params) => Some(params.layer),
+        _ => None,
+    }
+}
+
+/// Keep strokes parented to their layer, and layer roots hidden or shown
+/// with their layer.
+pub fn sync_layers(
+    mut commands: Commands,
+    mut layers: ResMut<SketchLayers>,
+    strokes: Query<(Entity, &SceneObject, Option<&ChildOf>), Changed<SceneObject>>,
+    mut roots: Query<&mut Visibility, With<LayerRoot>>,
+) {
+    for (entity, object, parent) in &strokes {
+        let Some(id) = layer_of(object) else {
+            continue;
+        };
+        let root = layers.root(id, &mut commands);
+        if parent.map(ChildOf::parent) != Some(root) {
+            commands.entity(root).add_child(entity);
+        }
+    }
+    if layers.is_changed() {
+        for layer in &layers.layers {
+            let root = layers.roots.get(&layer.id);
+            if let Some(mut visibility) = root.and_then(|e| roots.get_mut(*e).ok()) {
+                visibility.set_if_neq(layer.visibility());
+            }
+        }
+    }
+}
+
+/// A stroke as exported to the host, in world space.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StrokeExport {
+    pub id: u32,
+    pub style: StrokeStyle,
+    pub color: [f32; 3],
+    pub radius: f32,
+    pub visible: bool,
+    pub samples: Vec<[f32; 3]>,
+    pub curve: Vec<[f32; 3]>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LayerExport {
+    #[serde(flatten)]
+    pub layer: SketchLayer,
+    /// In drawing order
+    pub strokes: Vec<StrokeExport>,
+}
+
+/// A stroke object as found in the world.
+struct Stroke {
+    id: ObjectId,
+    params: StrokeParams,
+    color: [f32; 3],
+    transform: Transform,
+    visible: bool,
+}
+
+impl Stroke {
+    fn export(&self) -> StrokeExport {
+        let to_world = |points: &[[f32; 3]]| {
+            points
+                .iter()
+                .map(|p| {
+                    self.transform
+                        .transform_point(Vec3::from_array(*p))
+                        .to_array()
+                })
+                .collect()
+        };
+        StrokeExport {
+            id: self.id.0,
+            style: self.params.style,
+            color: self.color,
+            radius: self.params.radius,
+            visible: self.visible,
+            samples: to_world(&self.params.samples),
+            curve: to_world(&self.params.curve),
+        }
+    }
+
+    /// The stroke surface in world space, `None` for lines.
+    fn mesh(&self) -> Result<Option<geometry::Mesh>, String> {
+        if self.params.data.is_empty() {
+            return Ok(None);
+        }
+        let mut mesh = file_io::load_obj(self.params.data.clone())?;
+        let matrix = self.transform.compute_affine().to_cols_array();
+        mesh.transform(glam::Affine3A::from_cols_array(&matrix));
+        Ok(Some(mesh))
+    }
+}
+
+/// Every stroke in drawing order.
+fn strokes(world: &mut World) -> Vec<Stroke> {
+    let mut q = world.query::<(&ObjectId, &SceneObject, &Transform, &Visibility)>();
+    let mut strokes: Vec<_> = q
+        .iter(world)
+        .filter_map(|(id, object, transform, visibility)| match &object.kind {
+            ObjKind::Stroke(params) => Some(Stroke {
+                id: *id,
+                params: params.clone(),
+                color: object.material.base_color,
+                transform: *transform,
+                visible: *visibility != Visibility::Hidden,
+            }),
+            _ => None,
+        })
+        .collect();
+    // IDs are handed out in spawn order
+    strokes.sort_by_key(|s| s.id);
+    strokes
+}
+
+/// The layer with `id`, or every layer.
+fn select(world: &World, layer: Option<u32>) -> Result<Vec<SketchLayer>, String> {
+    let layers = world.resource::<SketchLayers>();
+    match layer {
+        Some(id) => layers
+            .get(id)
+            .map(|l| vec![l.clone()])
+            .ok_or_else(|| format!("no layer with id {}", id)),
+        None => Ok(layers.layers.clone()),
+    }
+}
+
+/// The strokes of one layer, or of every layer.
+pub fn export(world: &mut World, layer: Option<u32>) -> Result<Vec<LayerExport>, String> {
+    let layers = select(world, layer)?;
+    let strokes = strokes(world);
+    Ok(layers
+        .into_iter()
+        .map(|layer| LayerExport {
+            strokes: strokes
+                .iter()
+                .filter(|s| s.params.layer == layer.id)
+                .map(Stroke::export)
+                .collect(),
+            layer,
+        })
+        .collect())
+}
+
+/// The surfaces of the strokes of one layer, or of every layer, merged into
+/// one mesh in world space. Lines have no surface and are left out.
+pub fn merged_mesh(world: &mut World, layer: Option<u32>) -> Result<geometry::Mesh, String> {
+    let ids: Vec<u32> = select(world, layer)?.iter().map(|l| l.id).collect();
+    let mut merged = geometry::Mesh::default();
+    for stroke in strokes(world) {
+        if !ids.contains(&stroke.params.layer) {
+            continue;
+        }
+        if let Some(mesh) = stroke.mesh()? {
+            merged.append(&mesh);
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::SphereParams;
+
+    fn object(kind: ObjKind) -> SceneObject {
+        SceneObject {
+            label: String::new(),
+            kind,
+            material: default(),
+        }
+    }
+
+    fn stroke(layer: u32, data: &str) -> SceneObject {
+        object(ObjKind::Stroke(StrokeParams {
+            layer,
+            radius: 0.1,
+            samples: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]],
+            curve: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]],
+            data: data.to_string(),
+            ..default()
+        }))
+    }
+
+    fn world(layers: SketchLayers) -> World {
+        let triangle = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+        let mut world = World::new();
+        world.insert_resource(layers);
+        for (id, object) in [
+            (2, stroke(1, triangle)),
+            (0, stroke(0, triangle)),
+            (1, stroke(1, triangle)),
+            (3, object(ObjKind::Sphere(SphereParams::default()))),
+        ] {
+            world.spawn((
+                ObjectId(id),
+                object,
+                Transform::from_xyz(0.0, 0.0, id as f32),
+                Visibility::Inherited,
+            ));
+        }
+        world
+    }
+
+    #[test]
+    fn added_layers_become_active() {
+        let mut layers = SketchLayers::default();
+        assert_eq!(layers.active().id, 0);
+        let id = layers.add(None);
+        assert_eq!(id, 1);
+        assert_eq!(layers.active().name, "Layer 2");
+        layers.set_active(0).unwrap();
+        assert_eq!(layers.active().id, 0);
+        assert!(layers.set_active(7).is_err());
+    }
+
+    #[test]
+    fn locked_layers_lock_their_strokes_only() {
+        let mut layers = SketchLayers::new(vec![
+            SketchLayer::default(),
+            SketchLayer {
+                id: 1,
+                name: "Locked".to_string(),
+                locked: true,
+                ..default()
+            },
+        ]);
+        assert!(layers.locks(&stroke(1, "")));
+        assert!(!layers.locks(&stroke(0, "")));
+        assert!(!layers.locks(&object(ObjKind::Sphere(SphereParams::default()))));
+        layers.get_mut(1).unwrap().locked = false;
+        assert!(!layers.locks(&stroke(1, "")));
+    }
+
+    #[test]
+    fn strokes_are_exported_per_layer_in_drawing_order() {
+        let mut layers = SketchLayers::default();
+        layers.add(None);
+        let mut world = world(layers);
+        let exported = export(&mut world, None).unwrap();
+        let ids: Vec<Vec<u32>> = exported
+            .iter()
+            .map(|l| l.strokes.iter().map(|s| s.id).collect())
+            .collect();
+        assert_eq!(ids, [vec![0], vec![1, 2]]);
+        // Points are moved into world space
+        assert_eq!(exported[1].strokes[1].samples[1], [1.0, 0.0, 2.0]);
+
+        let exported = export(&mut world, Some(1)).unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].layer.id, 1);
+        assert!(export(&mut world, Some(5)).is_err());
+    }
+
+    #[test]
+    fn merged_mesh_holds_the_layer_strokes() {
+        let mut layers = SketchLayers::default();
+        layers.add(None);
+        let mut world = world(layers);
+        let mesh = merged_mesh(&mut world, Some(1)).unwrap();
+        assert_eq!(mesh.triangles().len(), 2);
+        assert_eq!(merged_mesh(&mut world, None).unwrap().triangles().len(), 3);
+        assert!(merged_mesh(&mut world, Some(5)).is_err());
+    }
+}
@@ -21,66 +21,290 @@ mod ffi;
 pub use ffi::*;
 
 use crate::{
-    file_io::{export_obj_to_string, to_bevy_mesh},
-    geometry::meshify,
-    scene3d::{LastTouchInput, MeshConfig, OrbitCamera, SpawnMeshEvent, TouchInput},
+    csg::BooleanOp,
+    decimate::DecimateOptions,
+    file_io::{ImportError, MeshFormat, export_obj_to_string, to_bevy_mesh},
+    geometry::Shading,
+    history::{CameraState, History},
+    material::MaterialConfig,
+    primitives::{MeshParams, ObjKind},
+    repair::{MeshReport, RepairOptions},
+    scene3d::{
+        EditObjectEvent, InteractionMode, LastTouchInput, MeshConfig, ObjectEdit, ObjectId,
+        ObjectIds, OrbitCamera, PickEvent, SceneObject, Selection, SpawnMeshEvent, TouchInput,
+    },
 };
 
 #[cfg(target_os = "android")]
 mod android_asset_io;
 
 mod breakout_game;
+mod bvh;
+mod csg;
 mod lighting_demo;
+mod line_material;
+mod material;
+mod picking;
+mod primitives;
+mod projection;
+mod repair;
 mod scene3d;
 mod shapes_demo;
 mod stepping;
+mod stroke;
+mod symmetry;
+mod validation;
 
+mod decimate;
 mod file_io;
 mod geometry;
+mod gestures;
+mod gizmo;
+mod halfedge;
+mod history;
+mod layers;
+
+/// What the benchmarks in `benches/` need, with the `bench` feature. Not an
+/// API.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::bvh::{Aabb, Bvh};
+    pub use crate::file_io::{export_obj_to_string, from_bevy_mesh, load_obj};
+    pub use crate::geometry::{Mesh, TubeOptions, meshify};
+}
+
+fn default_color() -> [f32; 3] {
+    [0.8, 0.8, 0.8]
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_rotation() -> [f32; 4] {
+    [0.0, 0.0, 0.0, 1.0]
+}
+
+fn default_visible() -> bool {
+    true
+}
 
 #[derive(Deserialize, Serialize, Debug)]
 struct ObjConfig {
-    data: String,
-    #[serde(rename = "type")]
-    objtype: String,
-    label: String,
+    #[serde(flatten)]
+    kind: ObjKind,
+    /// Assigned on spawn when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<u32>,
     #[serde(default)]
-    color: Vec<f32>,
+    label: String,
+    #[serde(default = "default_color")]
+    color: [f32; 3],
     #[serde(default)]
-    pos: Vec<f32>,
+    pos: [f32; 3],
+    #[serde(default = "default_scale")]
     scale: f32,
+    /// Per-axis scale, multiplied with `scale`
+    #[serde(default, rename = "scaleAxes", skip_serializing_if = "Option::is_none")]
+    scale_axes: Option<[f32; 3]>,
+    /// Quaternion as `[x, y, z, w]`
+    #[serde(default = "default_rotation")]
+    rotation: [f32; 4],
+    /// Overrides `color` when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    material: Option<MaterialConfig>,
+    #[serde(default = "default_visible")]
+    visible: bool,
+}
+
+impl ObjConfig {
+    fn material(&self) -> MaterialConfig {
+        self.material
+            .clone()
+            .unwrap_or_else(|| MaterialConfig::from_color(self.color))
+    }
+
+    fn transform(&self) -> Transform {
+        let axes = Vec3::from_array(self.scale_axes.unwrap_or([1.0; 3]));
+        Transform::from_translation(Vec3::from_array(self.pos))
+            .with_rotation(Quat::from_array(self.rotation).normalize())
+            .with_scale(axes * self.scale)
+    }
+
+    fn visibility(&self) -> Visibility {
+        if self.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        }
+    }
+
+    fn from_entity(
+        id: ObjectId,
+        object: &SceneObject,
+        transform: &Transform,
+        visibility: &Visibility,
+    ) -> Self {
+        let s = transform.scale;
+        let (scale, scale_axes) = if s.x == s.y && s.y == s.z {
+            (s.x, None)
+        } else {
+            (1.0, Some(s.to_array()))
+        };
+        Self {
+            kind: object.kind.clone(),
+            id: Some(id.0),
+            label: object.label.clone(),
+            color: object.material.base_color,
+            pos: transform.translation.to_array(),
+            scale,
+            scale_axes,
+            rotation: transform.rotation.to_array(),
+            material: Some(object.material.clone()),
+            visible: *visibility != Visibility::Hidden,
+        }
+    }
+}
+
+/// What `list_objects` reports per object, without the mesh data.
+#[derive(Serialize, Debug)]
+pub(crate) struct ObjectSummary {
+    id: u32,
+    label: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    pos: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    visible: bool,
+    material: MaterialConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum LightKind {
+    Point,
+    Directional,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct LightConfig {
+    #[serde(rename = "type")]
+    kind: LightKind,
+    #[serde(default = "default_light_color")]
+    color: [f32; 3],
+    /// Lumens for point lights, lux for directional lights
+    intensity: f32,
+    #[serde(default)]
+    pos: [f32; 3],
+    /// Quaternion as `[x, y, z, w]`, directional lights shine along -Z
+    #[serde(default = "default_rotation")]
+    rotation: [f32; 4],
+    #[serde(default)]
+    shadows: bool,
 }
 
-#[derive(Deserialize, Debug)]
+fn default_light_color() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+impl LightConfig {
+    fn transform(&self) -> Transform {
+        Transform::from_translation(Vec3::from_array(self.pos))
+            .with_rotation(Quat::from_array(self.rotation).normalize())
+    }
+
+    fn spawn(&self, commands: &mut Commands) {
+        let color = Color::srgb_from_array(self.color);
+        match self.kind {
+            LightKind::Point => commands.spawn((
+                PointLight {
+                    color,
+                    intensity: self.intensity,
+                    shadows_enabled: self.shadows,
+                    ..default()
+                },
+                self.transform(),
+            )),
+            LightKind::Directional => commands.spawn((
+                DirectionalLight {
+                    color,
+                    illuminance: self.intensity,
+                    shadows_enabled: self.shadows,
+                    ..default()
+                },
+                self.transform(),
+            )),
+        };
+    }
+}
+
+fn color_to_array(color: Color) -> [f32; 3] {
+    let c = color.to_srgba();
+    [c.red, c.green, c.blue]
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
 struct SceneConfig {
     #[serde(default)]
     objects: Vec<ObjConfig>,
 
     #[serde(default, rename = "cameraPos")]
-    camera_pos: Vec<f32>,
+    camera_pos: [f32; 3],
+
+    #[serde(default)]
+    camera: OrbitCamera,
+
+    /// A single point light with `environmentLightColor` is used when empty
+    #[serde(default)]
+    lights: Vec<LightConfig>,
+
+    /// Sketch layers in drawing order, a single empty one when empty
+    #[serde(default)]
+    layers: Vec<layers::SketchLayer>,
 }
 
-#[derive(Deserialize, Debug)]
-struct AppInitOpts {
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct AppInitOpts {
     #[serde(rename = "backgroundColor")]
-    background_color: Vec<f32>,
+    background_color: [f32; 3],
 
     #[serde(rename = "environmentLightColor")]
-    light_color: Vec<f32>,
+    light_color: [f32; 3],
 
     #[serde(rename = "moveStrength")]
     move_strength: f32,
 
+    /// Two-finger camera gestures
+    gestures: gestures::GestureSettings,
+
+    /// How sketch strokes are smoothed
+    stroke: stroke::StrokeSettings,
+
     scene: SceneConfig,
 }
 
+impl Default for AppInitOpts {
+    fn default() -> Self {
+        Self {
+            background_color: [0.1, 0.1, 0.1],
+            light_color: [1.0, 1.0, 1.0],
+            move_strength: 0.01,
+            gestures: gestures::GestureSettings::default(),
+            stroke: stroke::StrokeSettings::default(),
+            scene: SceneConfig::default(),
+        }
+    }
+}
+
 #[allow(unused_variables)]
 pub fn create_breakout_app(
     #[cfg(target_os = "android")] android_asset_manager: android_asset_io::AndroidAssetManager,
     opts: AppInitOpts,
 ) -> App {
-    let bg = &opts.background_color;
-    let bg_color = Color::srgb(bg[0], bg[1], bg[2]);
+    let bg_color = Color::srgb_from_array(opts.background_color);
     #[allow(unused_imports)]
     use bevy::winit::WinitPlugin;
 
@@ -157,160 +381,611 @@ pub fn create_breakout_app(
 pub(crate) fn update_camera(app: &mut App, pos: Vec3) {
     let center = Vec3::new(pos[0], pos[1], pos[2]);
 
-    let mut config = app.world_mut().resource_mut::<scene3d::MyPluginConfig>();
-    config.camera_pos = vec![center.x, center.y, center.z];
-
-    let orbit = app.world().resource::<OrbitCamera>();
-    let x = orbit.radius * orbit.elevation.cos() * orbit.azimuth.sin();
-    let y = orbit.radius * orbit.elevation.sin();
-    let z = orbit.radius * orbit.elevation.cos() * orbit.azimuth.cos();
-
-    let mut camq = app
-        .world_mut()
-        .query_filtered::<&mut Transform, With<Camera3d>>();
-    for mut transform in camq.iter_mut(app.world_mut()) {
-        log::info!("Camera position: {:?}", transform.translation);
+    let before = CameraState::capture(app.world());
+    let after = CameraState { center, ..before };
+    after.apply(app.world_mut());
+    log::info!("Camera position: {:?}", after.orbit.eye(center));
 
-        // Convert spherical coordinates to cartesian
-        let position = Vec3::new(x, y, z) + center;
-        transform.translation = position;
-        transform.look_at(center, Vec3::Y);
-    }
+    // Consecutive offset changes, e.g. from dragging a slider, are one step
+    history::record(
+        app.world_mut(),
+        history::Command::Camera {
+            before,
+            after,
+            merge: true,
+        },
+    );
 }
 
 pub(crate) fn to_plugin_opts(opts: AppInitOpts) -> scene3d::Scene3DPlugin {
     let mut meshes = vec![];
     for mesh_config in opts.scene.objects {
-        if mesh_config.objtype == "mesh" {
-            let mesh_data = mesh_config.data;
-            // log::info!("Importing mesh data: {}", mesh_data);
-            let res = file_io::load_obj(mesh_data).unwrap();
-            let mymesh = file_io::to_bevy_mesh(&res);
-            let mm = MeshConfig {
-                mesh: mymesh,
-                transform: Transform::from_xyz(
-                    mesh_config.pos[0],
-                    mesh_config.pos[1],
-                    mesh_config.pos[2],
-                )
-                .with_scale(Vec3::splat(mesh_config.scale)),
-                color: Color::srgb(
-                    mesh_config.color[0],
-                    mesh_config.color[1],
-                    mesh_config.color[2],
-                ),
-            };
-            meshes.push(mm);
-        }
-        if mesh_config.objtype == "sphere" {
-            let mut mshp = Sphere::default();
-            mshp.radius = 1.0;
-            let mymesh = mshp.mesh().ico(5).unwrap();
-            let mm = MeshConfig {
-                mesh: mymesh,
-                transform: Transform::from_xyz(
-                    mesh_config.pos[0],
-                    mesh_config.pos[1],
-                    mesh_config.pos[2],
-                )
-                .with_scale(Vec3::splat(mesh_config.scale)),
-                color: Color::srgb(
-                    mesh_config.color[0],
-                    mesh_config.color[1],
-                    mesh_config.color[2],
-                ),
-            };
-            meshes.push(mm);
-        }
-        if mesh_config.objtype == "plane" {
-            let mshp = Plane3d::default().mesh().size(100.0, 100.0);
-            let mm = MeshConfig {
-                mesh: mshp.into(),
-                transform: Transform::from_xyz(
-                    mesh_config.pos[0],
-                    mesh_config.pos[1],
-                    mesh_config.pos[2],
-                )
-                .with_scale(Vec3::splat(mesh_config.scale)),
-                color: Color::srgb(
-                    mesh_config.color[0],
-                    mesh_config.color[1],
-                    mesh_config.color[2],
-                ),
-            };
-            meshes.push(mm);
-        }
-        if mesh_config.objtype == "cube" {
-            let mshp = Cuboid::new(1.0, 1.0, 1.0).mesh();
-            let mm = MeshConfig {
-                mesh: mshp.into(),
-                transform: Transform::from_xyz(
-                    mesh_config.pos[0],
-                    mesh_config.pos[1],
-                    mesh_config.pos[2],
-                )
-                .with_scale(Vec3::splat(mesh_config.scale)),
-                color: Color::srgb(
-                    mesh_config.color[0],
-                    mesh_config.color[1],
-                    mesh_config.color[2],
-                ),
-            };
-            meshes.push(mm);
-        }
+        let mymesh = match mesh_config.kind.build() {
+            Ok(mesh) => mesh,
+            // `parse_app_opts` reports bad mesh data under its path, so this
+            // is only reached by options that were not parsed from JSON
+            Err(e) => {
+                log::error!("Failed to build object `{}`: {}", mesh_config.label, e);
+                continue;
+            }
+        };
+        let mm = MeshConfig {
+            mesh: mymesh,
+            transform: mesh_config.transform(),
+            id: mesh_config.id,
+            visibility: mesh_config.visibility(),
+            object: SceneObject {
+                label: mesh_config.label.clone(),
+                material: mesh_config.material(),
+                kind: mesh_config.kind,
+            },
+        };
+        meshes.push(mm);
     }
 
-    let light = opts.light_color;
-    let env_lightcolor = Color::srgb(light[0], light[1], light[2]);
+    let env_lightcolor = Color::srgb_from_array(opts.light_color);
     let move_strength = opts.move_strength;
 
+    let mut lights = opts.scene.lights;
+    if lights.is_empty() {
+        lights.push(LightConfig {
+            kind: LightKind::Point,
+            color: opts.light_color,
+            intensity: PointLight::default().intensity,
+            pos: [0.0, 8.0, 0.0],
+            rotation: default_rotation(),
+            shadows: true,
+        });
+    }
+
     scene3d::Scene3DPlugin {
         env_lightcolor: env_lightcolor,
         move_strength: move_strength,
         meshes: meshes,
-        camera_pos: opts.scene.camera_pos,
+        lights,
+        camera_pos: Vec3::from_array(opts.scene.camera_pos),
+        orbit: opts.scene.camera,
+        gestures: opts.gestures,
+        stroke: opts.stroke,
+        layers: opts.scene.layers,
     }
 }
 
 pub(crate) fn switch_mode(app: &mut App, mode: u32) {
     let mut config = app.world_mut().resource_mut::<scene3d::MyPluginConfig>();
-    config.sketch = mode == 1;
-    log::info!("Switching to mode: {}", mode);
-    if config.sketch {
-        log::info!("Sketch mode enabled");
-    } else {
-        log::info!("Sketch mode disabled");
-    }
+    config.mode = match mode {
+        1 => InteractionMode::Sketch,
+        2 => InteractionMode::Select,
+        _ => InteractionMode::Orbit,
+    };
+    log::info!("Switching to mode: {} ({:?})", mode, config.mode);
+}
+
+pub(crate) fn set_gizmo_mode(app: &mut App, mode: u32) {
+    let mut gizmo = app.world_mut().resource_mut::<gizmo::TransformGizmo>();
+    gizmo.mode = match mode {
+        1 => gizmo::GizmoMode::Rotate,
+        2 => gizmo::GizmoMode::Scale,
+        _ => gizmo::GizmoMode::Translate,
+    };
+}
+
+/// Snapping steps for gizmo drags. Non-positive values turn snapping off.
+pub(crate) fn set_gizmo_snap(app: &mut App, grid: f32, angle_degrees: f32) {
+    let mut gizmo = app.world_mut().resource_mut::<gizmo::TransformGizmo>();
+    gizmo.grid_snap = (grid > 0.0).then_some(grid);
+    gizmo.angle_snap = (angle_degrees > 0.0).then_some(angle_degrees.to_radians());
 }
 
 pub(crate) fn touch_enter(app: &mut App, pos: Vec2) {
     let mut config = app.world_mut().resource_mut::<scene3d::MyPluginConfig>();
-    if config.sketch {
-        config.sketch_history.clear();
+    match config.mode {
+        InteractionMode::Sketch => config.sketch_history.clear(),
+        InteractionMode::Select => {
+            app.world_mut().send_event(PickEvent { pos });
+        }
+        InteractionMode::Orbit => {}
     }
     change_touch(app, Some(pos));
     change_last_touch(app, None);
 }
 
+/// The stroke drawn last, if it is still in the scene. Its symmetric copies
+/// are left out.
 pub(crate) fn get_current_mesh(app: &mut App) -> String {
-    let config = app.world_mut().resource_mut::<scene3d::MyPluginConfig>();
-    let mm = &config.current_mesh;
+    let world = app.world_mut();
+    let last = world.resource::<scene3d::MyPluginConfig>().last_stroke;
+    let mut q = world.query::<(&ObjectId, &SceneObject)>();
+    let mm = last
+        .and_then(|last| q.iter(world).find(|(id, _)| **id == last))
+        .map(|(_, object)| object.kind.clone());
+    let config = world.resource::<scene3d::MyPluginConfig>();
     let mut bobj = ObjConfig {
-        data: String::new(),
-        objtype: "mesh".to_string(),
+        kind: ObjKind::Mesh(MeshParams {
+            data: String::new(),
+            subdivision: None,
+            shading: None,
+        }),
+        id: None,
         label: "none".to_string(),
-        color: vec![0.0, 0.0, 1.0],
-        pos: vec![0.0, 0.0, 0.0],
+        color: config.sketch_material.base_color,
+        pos: [0.0, 0.0, 0.0],
         scale: 1.0,
+        scale_axes: None,
+        rotation: default_rotation(),
+        material: Some(config.sketch_material.clone()),
+        visible: true,
     };
-    if let Some(mesh) = mm {
-        bobj.data = export_obj_to_string(mesh);
+    if let Some(kind) = mm {
+        bobj.kind = kind;
     }
     let json_str = serde_json::to_string(&bobj).unwrap();
 
     return json_str;
 }
 
+/// Export the live scene in the same schema `create_breakout_app` reads.
+pub(crate) fn get_scene(app: &mut App) -> String {
+    let world = app.world_mut();
+
+    let mut objq = world.query::<(&ObjectId, &SceneObject, &Transform, &Visibility)>();
+    let mut objs: Vec<_> = objq.iter(world).collect();
+    // IDs are handed out in spawn order
+    objs.sort_by_key(|(id, ..)| **id);
+    let objects = objs
+        .into_iter()
+        .map(|(id, object, transform, visibility)| {
+            ObjConfig::from_entity(*id, object, transform, visibility)
+        })
+        .collect();
+
+    let mut lights = vec![];
+    let mut pointq = world.query::<(Entity, &PointLight, &Transform)>();
+    for (entity, light, transform) in pointq.iter(world) {
+        lights.push((
+            entity,
+            LightConfig {
+                kind: LightKind::Point,
+                color: color_to_array(light.color),
+                intensity: light.intensity,
+                pos: transform.translation.to_array(),
+                rotation: transform.rotation.to_array(),
+                shadows: light.shadows_enabled,
+            },
+        ));
+    }
+    let mut dirq = world.query::<(Entity, &DirectionalLight, &Transform)>();
+    for (entity, light, transform) in dirq.iter(world) {
+        lights.push((
+            entity,
+            LightConfig {
+                kind: LightKind::Directional,
+                color: color_to_array(light.color),
+                intensity: light.illuminance,
+                pos: transform.translation.to_array(),
+                rotation: transform.rotation.to_array(),
+                shadows: light.shadows_enabled,
+            },
+        ));
+    }
+    lights.sort_by_key(|(entity, _)| *entity);
+
+    let config = world.resource::<scene3d::MyPluginConfig>();
+    let opts = AppInitOpts {
+        background_color: color_to_array(world.resource::<ClearColor>().0),
+        light_color: color_to_array(config.env_lightcolor),
+        move_strength: config.move_strength,
+        gestures: *world.resource::<gestures::GestureSettings>(),
+        stroke: world.resource::<stroke::StrokeSettings>().clone(),
+        scene: SceneConfig {
+            objects,
+            camera_pos: config.camera_pos.to_array(),
+            camera: *world.resource::<OrbitCamera>(),
+            lights: lights.into_iter().map(|(_, light)| light).collect(),
+            layers: world.resource::<layers::SketchLayers>().layers().to_vec(),
+        },
+    };
+    serde_json::to_string(&opts).unwrap()
+}
+
+pub(crate) fn list_objects(app: &mut App) -> Vec<ObjectSummary> {
+    let world = app.world_mut();
+    let mut q = world.query::<(&ObjectId, &SceneObject, &Transform, &Visibility)>();
+    let mut objects: Vec<_> = q
+        .iter(world)
+        .map(|(id, object, transform, visibility)| ObjectSummary {
+            id: id.0,
+            label: object.label.clone(),
+            kind: object.kind.name(),
+            pos: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+            scale: transform.scale.to_array(),
+            visible: *visibility != Visibility::Hidden,
+            material: object.material.clone(),
+        })
+        .collect();
+    objects.sort_by_key(|o| o.id);
+    objects
+}
+
+/// Queue an edit for the object with `id`, applied by the scene on the next frame.
+pub(crate) fn edit_object(app: &mut App, id: u32, edit: ObjectEdit) -> Result<(), String> {
+    let id = ObjectId(id);
+    if let ObjectEdit::Transform(t) = &edit {
+        if !t.is_finite() {
+            return Err("transform must contain only finite numbers".to_string());
+        }
+    }
+    check_editable(app.world_mut(), id)?;
+    app.world_mut().send_event(EditObjectEvent {
+        id,
+        edit,
+        record: true,
+    });
+    Ok(())
+}
+
+/// Fail unless the object with `id` exists and is not on a locked layer.
+fn check_editable(world: &mut World, id: ObjectId) -> Result<(), String> {
+    let mut q = world.query::<(&ObjectId, &SceneObject)>();
+    let Some((_, object)) = q.iter(world).find(|(other, _)| **other == id) else {
+        return Err(format!("no object with id {}", id.0));
+    };
+    if world.resource::<layers::SketchLayers>().locks(object) {
+        return Err(format!("object {} is on a locked layer", id.0));
+    }
+    Ok(())
+}
+
+/// Duplicate an object, returning the ID of the copy.
+pub(crate) fn duplicate_object(app: &mut App, id: u32) -> Result<u32, String> {
+    // Failed calls must not use up an ID
+    check_editable(app.world_mut(), ObjectId(id))?;
+    let new_id = app.world_mut().resource_mut::<ObjectIds>().reserve(None);
+    edit_object(app, id, ObjectEdit::Duplicate(new_id))?;
+    Ok(new_id.0)
+}
+
+/// An object as a closed mesh in world space, with what is needed to bring
+/// it back after it is replaced.
+fn solid(world: &mut World, id: u32) -> Result<(history::ObjectSnapshot, geometry::Mesh), String> {
+    let mut q = world.query::<(
+        &ObjectId,
+        &Mesh3d,
+        &Transform,
+        &GlobalTransform,
+        &SceneObject,
+        &Visibility,
+    )>();
+    let Some((id, mesh, transform, global, object, visibility)) =
+        q.iter(world).find(|(other, ..)| other.0 == id)
+    else {
+        return Err(format!("no object with id {}", id));
+    };
+    if world.resource::<layers::SketchLayers>().locks(object) {
+        return Err(format!("object {} is on a locked layer", id.0));
+    }
+    let mut solid = world
+        .resource::<Assets<Mesh>>()
+        .get(&mesh.0)
+        .and_then(file_io::from_bevy_mesh)
+        .ok_or_else(|| format!("object {} has no surface", id.0))?;
+    let matrix = global.affine().to_cols_array();
+    solid.transform(glam::Affine3A::from_cols_array(&matrix));
+    let snapshot = history::ObjectSnapshot {
+        id: *id,
+        mesh: mesh.clone(),
+        transform: *transform,
+        object: object.clone(),
+        visibility: *visibility,
+    };
+    Ok((snapshot, solid))
+}
+
+/// Replace two objects by a mesh combining them, undone as one step. `op`
+/// is 0 for their union, 1 to carve `tool` out of `target` and 2 for their
+/// intersection. The new object takes the material of `target`, and its ID
+/// is returned. Objects with more than [`csg::MAX_TRIANGLES`] between them
+/// are refused.
+pub(crate) fn boolean_objects(
+    app: &mut App,
+    target: u32,
+    tool: u32,
+    op: u32,
+) -> Result<u32, String> {
+    let op = match op {
+        0 => BooleanOp::Union,
+        1 => BooleanOp::Difference,
+        2 => BooleanOp::Intersection,
+        _ => return Err(format!("unknown boolean op {}", op)),
+    };
+    if target == tool {
+        return Err("cannot combine an object with itself".to_string());
+    }
+    let world = app.world_mut();
+    let (target, mut mesh) = solid(world, target)?;
+    let (tool, tool_mesh) = solid(world, tool)?;
+    mesh.boolean(&tool_mesh, op)?;
+    if mesh.triangles().is_empty() {
+        return Err(format!(
+            "the {:?} of objects {} and {} is empty",
+            op, target.id.0, tool.id.0
+        ));
+    }
+    log::info!("{:?} of objects {} and {}", op, target.id.0, tool.id.0);
+    // A mesh object that chose its shading keeps it
+    let shading = match &target.object.kind {
+        ObjKind::Mesh(params) => params.shading,
+        _ => None,
+    };
+    if let Some(shading) = shading {
+        mesh.set_shading(shading);
+    }
+
+    let mut bevy_mesh = to_bevy_mesh(&mesh);
+    scene3d::ensure_tangents(&mut bevy_mesh, &target.object.material);
+    let combined = history::ObjectSnapshot {
+        id: world.resource_mut::<ObjectIds>().reserve(None),
+        mesh: Mesh3d(world.resource_mut::<Assets<Mesh>>().add(bevy_mesh)),
+        transform: Transform::IDENTITY,
+        object: SceneObject {
+            label: target.object.label.clone(),
+            kind: ObjKind::Mesh(MeshParams {
+                data: export_obj_to_string(&mesh),
+                subdivision: None,
+                shading,
+            }),
+            material: target.object.material.clone(),
+        },
+        visibility: Visibility::Inherited,
+    };
+    let id = combined.id.0;
+    history::perform(
+        world,
+        history::Command::Batch(vec![
+            history::Command::Delete(Box::new(target)),
+            history::Command::Delete(Box::new(tool)),
+            history::Command::Spawn(Box::new(combined)),
+        ]),
+    );
+    Ok(id)
+}
+
+/// Undo the last edit. Returns false if there was nothing to undo.
+pub(crate) fn undo(app: &mut App) -> bool {
+    history::undo(app.world_mut())
+}
+
+/// Redo the last undone edit. Returns false if there was nothing to redo.
+pub(crate) fn redo(app: &mut App) -> bool {
+    history::redo(app.world_mut())
+}
+
+pub(crate) fn can_undo(app: &App) -> bool {
+    app.world()
+        .get_resource::<History>()
+        .is_some_and(History::can_undo)
+}
+
+pub(crate) fn can_redo(app: &App) -> bool {
+    app.world()
+        .get_resource::<History>()
+        .is_some_and(History::can_redo)
+}
+
+/// Replace the stroke smoothing and style settings, used by strokes drawn
+/// from now on.
+pub(crate) fn set_stroke_settings(
+    app: &mut App,
+    json: &str,
+) -> Result<(), Vec<validation::ValidationError>> {
+    let settings = validation::parse_with(json, |s: &stroke::StrokeSettings, v| s.validate(v))?;
+    app.world_mut().insert_resource(settings);
+    Ok(())
+}
+
+/// Move the work plane strokes can be projected on.
+pub(crate) fn set_work_plane(app: &mut App, origin: Vec3, normal: Vec3) -> Result<(), String> {
+    if !(origin.is_finite() && normal.is_finite() && normal.length_squared() > 0.0) {
+        return Err("expected a finite origin and a non-zero normal".to_string());
+    }
+    let mut settings = app.world_mut().resource_mut::<stroke::StrokeSettings>();
+    settings.projection.work_plane = projection::WorkPlane {
+        origin: origin.to_array(),
+        normal: normal.normalize().to_array(),
+    };
+    Ok(())
+}
+
+pub(crate) fn list_layers(app: &App) -> Vec<layers::SketchLayer> {
+    app.world()
+        .resource::<layers::SketchLayers>()
+        .layers()
+        .to_vec()
+}
+
+/// Add a layer on top of the others and make it the active one, returning
+/// its ID.
+pub(crate) fn add_layer(app: &mut App, name: Option<String>) -> u32 {
+    app.world_mut()
+        .resource_mut::<layers::SketchLayers>()
+        .add(name)
+}
+
+/// Choose the layer new strokes go on.
+pub(crate) fn set_active_layer(app: &mut App, id: u32) -> Result<(), String> {
+    app.world_mut()
+        .resource_mut::<layers::SketchLayers>()
+        .set_active(id)
+}
+
+pub(crate) fn set_layer_visible(app: &mut App, id: u32, visible: bool) -> Result<(), String> {
+    let mut layers = app.world_mut().resource_mut::<layers::SketchLayers>();
+    layers.get_mut(id)?.visible = visible;
+    Ok(())
+}
+
+/// Lock or unlock a layer. Locking drops a selected stroke on the layer.
+pub(crate) fn set_layer_locked(app: &mut App, id: u32, locked: bool) -> Result<(), String> {
+    let world = app.world_mut();
+    world
+        .resource_mut::<layers::SketchLayers>()
+        .get_mut(id)?
+        .locked = locked;
+    let selected = world.resource::<Selection>().entity;
+    let selected_layer = selected
+        .and_then(|entity| world.get::<SceneObject>(entity))
+        .and_then(layers::layer_of);
+    if locked && selected_layer == Some(id) {
+        world.resource_mut::<Selection>().clear();
+    }
+    Ok(())
+}
+
+/// Strokes of layer `id`, or of every layer when `None`, with their points
+/// in world space.
+pub(crate) fn export_strokes(
+    app: &mut App,
+    id: Option<u32>,
+) -> Result<Vec<layers::LayerExport>, String> {
+    layers::export(app.world_mut(), id)
+}
+
+/// Surfaces of the strokes of layer `id`, or of every layer when `None`,
+/// merged into a single OBJ. It is repaired first if `repair` is given, then
+/// decimated to fit `decimate`.
+pub(crate) fn export_sketch_mesh(
+    app: &mut App,
+    id: Option<u32>,
+    repair: Option<&RepairOptions>,
+    decimate: &DecimateOptions,
+) -> Result<String, String> {
+    let mut mesh = layers::merged_mesh(app.world_mut(), id)?;
+    if let Some(repair) = repair {
+        mesh.repair(repair);
+    }
+    mesh.decimate(decimate);
+    Ok(export_obj_to_string(&mesh))
+}
+
+/// What repairing the mesh [`export_sketch_mesh`] would export with
+/// `repair` finds and fixes, e.g. to show before 3D printing.
+pub(crate) fn check_sketch_mesh(
+    app: &mut App,
+    id: Option<u32>,
+    repair: &RepairOptions,
+) -> Result<MeshReport, String> {
+    let mut mesh = layers::merged_mesh(app.world_mut(), id)?;
+    Ok(mesh.repair(repair))
+}
+
+/// Parse mesh repair options from the host, reporting errors with their
+/// paths.
+pub(crate) fn parse_repair(json: &str) -> Result<RepairOptions, Vec<validation::ValidationError>> {
+    validation::parse_with(json, |repair: &RepairOptions, v| repair.validate(v))
+}
+
+/// Parse a mesh shading mode from the host, reporting errors with their
+/// paths.
+pub(crate) fn parse_shading(json: &str) -> Result<Shading, Vec<validation::ValidationError>> {
+    validation::parse_with(json, |shading: &Shading, v| shading.validate(v))
+}
+
+/// Parse a material JSON from the host, reporting errors with their paths.
+pub(crate) fn parse_material(
+    json: &str,
+) -> Result<MaterialConfig, Vec<validation::ValidationError>> {
+    validation::parse_with(json, |material: &MaterialConfig, v| material.validate(v))
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct SelectionInfo {
+    id: u32,
+    point: [f32; 3],
+}
+
+/// The object picked by the last tap in select mode, if any.
+pub(crate) fn get_selection(app: &mut App) -> Option<SelectionInfo> {
+    let selection = app.world().resource::<Selection>();
+    let (entity, id, point) = (selection.entity?, selection.id?, selection.point?);
+    // The entity may have been despawned since it was picked
+    app.world().get_entity(entity).ok()?;
+    Some(SelectionInfo {
+        id: id.0,
+        point: point.to_array(),
+    })
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ImportSummary {
+    format: MeshFormat,
+    vertices: usize,
+    triangles: usize,
+    /// Triangles in the file, when decimation removed some
+    #[serde(rename = "originalTriangles", skip_serializing_if = "Option::is_none")]
+    original_triangles: Option<usize>,
+    /// Problems found in the file, which is imported as it is
+    report: MeshReport,
+}
+
+/// Parse a mesh file of any supported format, decimate it to fit `decimate`
+/// and spawn it into the scene. With `shading` its normals are generated
+/// again, otherwise those in the file are kept.
+pub(crate) fn import_mesh(
+    app: &mut App,
+    bytes: &[u8],
+    decimate: &DecimateOptions,
+    shading: Option<Shading>,
+) -> Result<ImportSummary, ImportError> {
+    let (format, mut mesh) = file_io::load_mesh_bytes(bytes)?;
+    let report = mesh.repair(&RepairOptions::CHECK);
+    let original = mesh.triangles().len();
+    mesh.decimate(decimate);
+    if let Some(shading) = shading {
+        mesh.set_shading(shading);
+    }
+    log::info!(
+        "Imported {:?} mesh with {} vertices",
+        format,
+        mesh.positions().len()
+    );
+    let summary = ImportSummary {
+        format,
+        vertices: mesh.positions().len(),
+        triangles: mesh.triangles().len(),
+        original_triangles: (mesh.triangles().len() < original).then_some(original),
+        report,
+    };
+    let material = app
+        .world()
+        .resource::<scene3d::MyPluginConfig>()
+        .sketch_material
+        .clone();
+    app.world_mut().send_event(SpawnMeshEvent {
+        mesh: to_bevy_mesh(&mesh),
+        object: SceneObject {
+            label: "import".to_string(),
+            kind: ObjKind::Mesh(MeshParams {
+                data: export_obj_to_string(&mesh),
+                subdivision: None,
+                shading,
+            }),
+            material,
+        },
+        copies: Vec::new(),
+    });
+    Ok(summary)
+}
+
+/// Encode a result as the `{"ok": ..}` JSON envelope returned over FFI.
+pub(crate) fn ffi_result_json<T: Serialize, E: Serialize>(res: &Result<T, E>) -> String {
+    let value = match res {
+        Ok(v) => serde_json::json!({ "ok": true, "value": v }),
+        Err(e) => serde_json::json!({ "ok": false, "error": e }),
+    };
+    value.to_string()
+}
+
 pub(crate) fn touch_exit(app: &mut App) {
     let mut camq = app
         .world_mut()
@@ -319,22 +994,111 @@ pub(crate) fn touch_exit(app: &mut App) {
     for transform in camq.iter(app.world_mut()) {
         rtm = transform.translation;
     }
-    let mut config = app.world_mut().resource_mut::<scene3d::MyPluginConfig>();
-    if config.sketch {
-        // Draw the sketch
-        let mm = meshify(
-            &config.sketch_history,
-            glam::Vec3::new(rtm.x, rtm.y, rtm.z),
-            0.1,
-        );
-        config.current_mesh = Some(mm.clone());
-        let rmm = to_bevy_mesh(&mm);
-        app.world_mut().send_event(SpawnMeshEvent { mesh: rmm });
+    let settings = app.world().resource::<stroke::StrokeSettings>().clone();
+    let layer = app
+        .world()
+        .resource::<layers::SketchLayers>()
+        .active()
+        .clone();
+    let config = app.world().resource::<scene3d::MyPluginConfig>();
+    if config.mode == InteractionMode::Sketch && layer.locked {
+        log::warn!("Layer `{}` is locked, dropping the stroke", layer.name);
+    } else if config.mode == InteractionMode::Sketch {
+        // Draw the sketch, then its symmetric copies as the same undo step
+        let camera = glam::Vec3::new(rtm.x, rtm.y, rtm.z);
+        let mut strokes = vec![(config.sketch_history.clone(), camera)];
+        for copy in settings.symmetry.copies() {
+            strokes.push((
+                symmetry::map_samples(&copy, &config.sketch_history),
+                symmetry::map_point(&copy, camera),
+            ));
+        }
+        let material = config.sketch_material.clone();
+        let mut objects: Vec<_> = strokes
+            .into_iter()
+            .filter_map(|(samples, camera)| {
+                let (rmm, mut params) = stroke::build(&samples, camera, &settings);
+                // A tap, or a stroke that missed every surface, has no curve
+                // to show and must not leave an empty object or undo step
+                if params.curve.len() < 2 {
+                    return None;
+                }
+                params.layer = layer.id;
+                let object = SceneObject {
+                    label: "sketch".to_string(),
+                    kind: ObjKind::Stroke(params),
+                    material: material.clone(),
+                };
+                Some((rmm, object))
+            })
+            .collect();
+        if !objects.is_empty() {
+            let (mesh, object) = objects.remove(0);
+            app.world_mut().send_event(SpawnMeshEvent {
+                mesh,
+                object,
+                copies: objects,
+            });
+        }
     }
     change_touch(app, None);
     change_last_touch(app, None);
 }
 
+/// A pointer went down. The first one drives the one-finger interactions.
+pub(crate) fn pointer_down(app: &mut App, id: u64, pos: Vec2, pressure: f32) {
+    let mut touch_input = app.world_mut().resource_mut::<TouchInput>();
+    touch_input.pointers.retain(|(other, _)| *other != id);
+    touch_input.pointers.push((id, pos));
+    if touch_input.pointers.len() == 1 {
+        touch_input.pressure = pressure;
+        let camera = CameraState::capture(app.world());
+        app.world_mut()
+            .resource_mut::<History>()
+            .begin_camera_move(camera);
+        touch_enter(app, pos);
+    }
+}
+
+pub(crate) fn pointer_move(app: &mut App, id: u64, pos: Vec2, pressure: f32) {
+    let mut touch_input = app.world_mut().resource_mut::<TouchInput>();
+    let is_primary = touch_input
+        .pointers
+        .first()
+        .is_some_and(|(first, _)| *first == id);
+    if let Some(p) = touch_input
+        .pointers
+        .iter_mut()
+        .find(|(other, _)| *other == id)
+    {
+        p.1 = pos;
+    }
+    if is_primary {
+        touch_input.touch = Some(pos);
+        touch_input.pressure = pressure;
+    }
+}
+
+pub(crate) fn pointer_up(app: &mut App, id: u64) {
+    let mut touch_input = app.world_mut().resource_mut::<TouchInput>();
+    touch_input.pointers.retain(|(other, _)| *other != id);
+    match touch_input.pointers.first() {
+        None => {
+            touch_exit(app);
+            let camera = CameraState::capture(app.world());
+            app.world_mut()
+                .resource_scope(|world, mut history: Mut<History>| {
+                    history.end_camera_move(camera, world.resource::<Assets<Mesh>>());
+                });
+        }
+        Some(&(_, pos)) => {
+            // Hand the one-finger interactions over to the next pointer
+            touch_input.touch = Some(pos);
+            change_last_touch(app, None);
+        }
+    }
+}
+
 pub(crate) fn change_touch(app: &mut App, pos: Option<Vec2>) {
     let mut touch_input = app.world_mut().resource_mut::<TouchInput>();
     touch_input.touch = pos;
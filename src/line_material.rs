@@ -0,0 +1,161 @@
+//! Lines that keep the same width on screen however far away they are, used
+//! for line style strokes.
+//!
+//! The mesh holds every centerline point twice, once for each side of the
+//! line. The vertex shader projects the point and its neighbours and pushes
+//! the two copies apart across the projected line.
+
+use bevy::{
+    asset::{RenderAssetUsages, weak_handle},
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    render::{
+        mesh::{Indices, MeshVertexAttribute, MeshVertexBufferLayoutRef, PrimitiveTopology},
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+            VertexFormat,
+        },
+        view::NoFrustumCulling,
+    },
+};
+
+use crate::{material::MaterialConfig, scene3d::SceneObject};
+
+const LINE_SHADER_HANDLE: Handle<Shader> = weak_handle!("6f1e2c3a-8d47-4b9e-a2f5-3c81d07e94b6");
+
+/// Centerline point before this one, the point itself at the start.
+pub const ATTRIBUTE_LINE_PREV: MeshVertexAttribute =
+    MeshVertexAttribute::new("LinePrev", 917_402_611, VertexFormat::Float32x3);
+/// Centerline point after this one, the point itself at the end.
+pub const ATTRIBUTE_LINE_NEXT: MeshVertexAttribute =
+    MeshVertexAttribute::new("LineNext", 917_402_612, VertexFormat::Float32x3);
+/// Width in physical pixels, negative for the vertex on the right side.
+pub const ATTRIBUTE_LINE_WIDTH: MeshVertexAttribute =
+    MeshVertexAttribute::new("LineWidth", 917_402_613, VertexFormat::Float32);
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct LineMaterial {
+    #[uniform(0)]
+    pub color: LinearRgba,
+    pub alpha_mode: AlphaMode,
+}
+
+impl LineMaterial {
+    /// Lines are not lit, so only the color and alpha carry over.
+    pub fn from_config(config: &MaterialConfig) -> Self {
+        let [r, g, b] = config.base_color;
+        Self {
+            color: Color::srgba(r, g, b, config.alpha).into(),
+            alpha_mode: config.alpha_mode(),
+        }
+    }
+}
+
+impl Material for LineMaterial {
+    fn vertex_shader() -> ShaderRef {
+        LINE_SHADER_HANDLE.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        LINE_SHADER_HANDLE.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.0.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            ATTRIBUTE_LINE_PREV.at_shader_location(1),
+            ATTRIBUTE_LINE_NEXT.at_shader_location(2),
+            ATTRIBUTE_LINE_WIDTH.at_shader_location(3),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        // Which way the quads wind depends on the view
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+pub struct LineMaterialPlugin;
+
+impl Plugin for LineMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        bevy::asset::load_internal_asset!(
+            app,
+            LINE_SHADER_HANDLE,
+            "line_material.wgsl",
+            Shader::from_wgsl
+        );
+        // The prepass and shadow shaders would not widen the line
+        app.add_plugins(MaterialPlugin::<LineMaterial> {
+            prepass_enabled: false,
+            shadows_enabled: false,
+            ..default()
+        })
+        .add_systems(PostUpdate, use_line_materials);
+    }
+}
+
+/// Mesh for a line through `points`, given as `[x, y, z, width]` with the
+/// width in physical pixels.
+pub fn line_mesh(points: &[[f32; 4]]) -> Mesh {
+    let n = points.len();
+    let position = |i: usize| [points[i][0], points[i][1], points[i][2]];
+    let mut positions = Vec::with_capacity(n * 2);
+    let mut prev = Vec::with_capacity(n * 2);
+    let mut next = Vec::with_capacity(n * 2);
+    let mut widths = Vec::with_capacity(n * 2);
+    for (i, point) in points.iter().enumerate() {
+        for side in [1.0, -1.0] {
+            positions.push(position(i));
+            prev.push(position(i.saturating_sub(1)));
+            next.push(position((i + 1).min(n - 1)));
+            widths.push(point[3] * side);
+        }
+    }
+    let mut indices = Vec::with_capacity(n.saturating_sub(1) * 6);
+    for i in 0..n.saturating_sub(1) as u32 {
+        let (a, b) = (2 * i, 2 * i + 1);
+        indices.extend_from_slice(&[a, b, a + 2, b, b + 2, a + 2]);
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(ATTRIBUTE_LINE_PREV, prev)
+    .with_inserted_attribute(ATTRIBUTE_LINE_NEXT, next)
+    .with_inserted_attribute(ATTRIBUTE_LINE_WIDTH, widths)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+/// Objects are spawned with a standard material wherever they come from.
+/// Give line strokes a [`LineMaterial`] instead, following their material
+/// whenever the object changes.
+fn use_line_materials(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<LineMaterial>>,
+    objects: Query<(Entity, &SceneObject), Changed<SceneObject>>,
+) {
+    for (entity, object) in &objects {
+        if !object.kind.is_line() {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .remove::<MeshMaterial3d<StandardMaterial>>()
+            .insert((
+                MeshMaterial3d(materials.add(LineMaterial::from_config(&object.material))),
+                // The mesh bounds do not include the line width
+                NoFrustumCulling,
+            ));
+    }
+}
@@ -0,0 +1,59 @@
+#import bevy_pbr::{
+    mesh_functions::{get_world_from_local, mesh_position_local_to_world},
+    mesh_view_bindings::view,
+    view_transformations::position_world_to_clip,
+}
+
+struct LineMaterial {
+    color: vec4<f32>,
+};
+
+@group(2) @binding(0) var<uniform> material: LineMaterial;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) prev: vec3<f32>,
+    @location(2) next: vec3<f32>,
+    // Physical pixels, negative on the right side
+    @location(3) width: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+fn to_clip(world_from_local: mat4x4<f32>, position: vec3<f32>) -> vec4<f32> {
+    let world = mesh_position_local_to_world(world_from_local, vec4<f32>(position, 1.0));
+    return position_world_to_clip(world.xyz);
+}
+
+// Clip space to pixels from the center of the viewport
+fn to_screen(clip: vec4<f32>) -> vec2<f32> {
+    return clip.xy / clip.w * view.viewport.zw * 0.5;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_from_local = get_world_from_local(vertex.instance_index);
+    let clip = to_clip(world_from_local, vertex.position);
+    let prev = to_screen(to_clip(world_from_local, vertex.prev));
+    let next = to_screen(to_clip(world_from_local, vertex.next));
+
+    var direction = next - prev;
+    if (length(direction) < 1e-6) {
+        direction = vec2<f32>(1.0, 0.0);
+    }
+    direction = normalize(direction);
+    let across = vec2<f32>(-direction.y, direction.x);
+    let offset = across * vertex.width * 0.5 / (view.viewport.zw * 0.5);
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(clip.xy + offset * clip.w, clip.zw);
+    return out;
+}
+
+@fragment
+fn fragment() -> @location(0) vec4<f32> {
+    return material.color;
+}
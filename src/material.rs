@@ -0,0 +1,125 @@
+//! PBR material description used by the scene JSON.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::validation::Validator;
+
+/// How the alpha channel of the base color is used.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum AlphaModeConfig {
+    #[default]
+    Opaque,
+    /// Fully transparent below `alphaCutoff`, opaque above it
+    Mask,
+    Blend,
+    Premultiplied,
+    Add,
+    Multiply,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MaterialConfig {
+    pub base_color: [f32; 3],
+    pub alpha: f32,
+    pub alpha_mode: AlphaModeConfig,
+    pub alpha_cutoff: f32,
+    pub metallic: f32,
+    pub perceptual_roughness: f32,
+    /// Linear RGB, may exceed 1 for bright emitters
+    pub emissive: [f32; 3],
+    pub reflectance: f32,
+    pub double_sided: bool,
+    pub unlit: bool,
+    /// Asset paths of the optional textures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_color_texture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normal_map_texture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metallic_roughness_texture: Option<String>,
+}
+
+impl Default for MaterialConfig {
+    fn default() -> Self {
+        // Same defaults as `StandardMaterial`
+        Self {
+            base_color: [1.0, 1.0, 1.0],
+            alpha: 1.0,
+            alpha_mode: AlphaModeConfig::Opaque,
+            alpha_cutoff: 0.5,
+            metallic: 0.0,
+            perceptual_roughness: 0.5,
+            emissive: [0.0, 0.0, 0.0],
+            reflectance: 0.5,
+            double_sided: false,
+            unlit: false,
+            base_color_texture: None,
+            normal_map_texture: None,
+            metallic_roughness_texture: None,
+        }
+    }
+}
+
+impl MaterialConfig {
+    pub fn from_color(rgb: [f32; 3]) -> Self {
+        Self {
+            base_color: rgb,
+            ..default()
+        }
+    }
+
+    pub fn alpha_mode(&self) -> AlphaMode {
+        match self.alpha_mode {
+            AlphaModeConfig::Opaque => AlphaMode::Opaque,
+            AlphaModeConfig::Mask => AlphaMode::Mask(self.alpha_cutoff),
+            AlphaModeConfig::Blend => AlphaMode::Blend,
+            AlphaModeConfig::Premultiplied => AlphaMode::Premultiplied,
+            AlphaModeConfig::Add => AlphaMode::Add,
+            AlphaModeConfig::Multiply => AlphaMode::Multiply,
+        }
+    }
+
+    /// Whether meshes using this material need vertex tangents.
+    pub fn needs_tangents(&self) -> bool {
+        self.normal_map_texture.is_some()
+    }
+
+    pub fn to_standard_material(&self, asset_server: &AssetServer) -> StandardMaterial {
+        let [r, g, b] = self.base_color;
+        let load = |path: &Option<String>| path.as_ref().map(|p| asset_server.load(p.clone()));
+        StandardMaterial {
+            base_color: Color::srgba(r, g, b, self.alpha),
+            alpha_mode: self.alpha_mode(),
+            metallic: self.metallic,
+            perceptual_roughness: self.perceptual_roughness,
+            emissive: LinearRgba::rgb(self.emissive[0], self.emissive[1], self.emissive[2]),
+            reflectance: self.reflectance,
+            double_sided: self.double_sided,
+            cull_mode: if self.double_sided {
+                None
+            } else {
+                Some(bevy::render::render_resource::Face::Back)
+            },
+            unlit: self.unlit,
+            base_color_texture: load(&self.base_color_texture),
+            normal_map_texture: load(&self.normal_map_texture),
+            metallic_roughness_texture: load(&self.metallic_roughness_texture),
+            ..default()
+        }
+    }
+
+    pub fn validate(&self, v: &mut Validator) {
+        v.color("baseColor", &self.base_color);
+        v.in_range("alpha", self.alpha, 0.0, 1.0);
+        v.in_range("alphaCutoff", self.alpha_cutoff, 0.0, 1.0);
+        v.in_range("metallic", self.metallic, 0.0, 1.0);
+        v.in_range("perceptualRoughness", self.perceptual_roughness, 0.0, 1.0);
+        v.in_range("reflectance", self.reflectance, 0.0, 1.0);
+        if self.emissive.iter().any(|c| !c.is_finite() || *c < 0.0) {
+            v.field_error("emissive", "must contain only non-negative numbers");
+        }
+    }
+}
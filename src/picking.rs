@@ -0,0 +1,85 @@
+//! Ray casts against render meshes, used to select objects by tapping.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy::render::mesh::MeshAabb;
+
+use crate::{file_io::from_bevy_mesh, geometry::Mesh as GMesh};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    /// Distance along the ray, in world units
+    pub distance: f32,
+    pub point: Vec3,
+    /// Unit normal of the hit triangle, facing back along the ray
+    pub normal: Vec3,
+}
+
+/// Mesh space bounding box as `(min, max)`.
+pub fn mesh_bounds(mesh: &Mesh) -> Option<(Vec3, Vec3)> {
+    let aabb = mesh.compute_aabb()?;
+    Some((aabb.min().into(), aabb.max().into()))
+}
+
+/// Geometry copies of the render meshes rays were cast against, with their
+/// triangle hierarchies, so each is only built again after its mesh changes.
+/// See [`forget_changed_meshes`].
+#[derive(Resource, Default)]
+pub struct MeshBvhCache(HashMap<AssetId<Mesh>, Option<GMesh>>);
+
+impl MeshBvhCache {
+    /// The cached mesh of `handle`, or `None` when it is not loaded or is
+    /// not a triangle list
+    pub fn get(&mut self, handle: &Mesh3d, meshes: &Assets<Mesh>) -> Option<&GMesh> {
+        let mesh = meshes.get(&handle.0)?;
+        self.0
+            .entry(handle.id())
+            .or_insert_with(|| from_bevy_mesh(mesh))
+            .as_ref()
+    }
+}
+
+/// Drop the cached hierarchies of meshes that were edited or removed.
+pub fn forget_changed_meshes(
+    mut events: EventReader<AssetEvent<Mesh>>,
+    mut cache: ResMut<MeshBvhCache>,
+) {
+    for event in events.read() {
+        match event {
+            AssetEvent::Modified { id }
+            | AssetEvent::Removed { id }
+            | AssetEvent::Unused { id } => {
+                cache.0.remove(id);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Closest hit of a world space ray with a mesh placed at `transform`.
+pub fn ray_mesh(ray: Ray3d, mesh: &GMesh, transform: &GlobalTransform) -> Option<RayHit> {
+    // Work in mesh space. The direction is not renormalized, so distances
+    // along it are still world distances.
+    let to_local = transform.affine().inverse();
+    let origin = to_local.transform_point3(ray.origin);
+    let dir = to_local.transform_vector3(*ray.direction);
+    let hit = mesh.ray_cast(
+        glam::Vec3::new(origin.x, origin.y, origin.z),
+        glam::Vec3::new(dir.x, dir.y, dir.z),
+        f32::INFINITY,
+    )?;
+
+    // Normals go to world space with the inverse transpose
+    let normal = Vec3::new(hit.normal.x, hit.normal.y, hit.normal.z);
+    let normal = (to_local.matrix3.transpose() * normal).normalize_or_zero();
+    Some(RayHit {
+        distance: hit.distance,
+        point: ray.origin + *ray.direction * hit.distance,
+        normal: if normal.dot(*ray.direction) > 0.0 {
+            -normal
+        } else {
+            normal
+        },
+    })
+}
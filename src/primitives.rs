@@ -0,0 +1,611 @@
+//! Object kinds that can be placed in a scene, and the registry used to
+//! parse them from the scene JSON.
+//!
+//! To add a new primitive: define its parameter struct, implement
+//! [`Primitive`] for it and list it in the `registry!` below.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, de::Error as _};
+use serde_json::Value;
+
+use crate::{
+    file_io,
+    geometry::{Profile, Shading},
+    halfedge::Subdivision,
+    line_material,
+    stroke::StrokeStyle,
+    validation::Validator,
+};
+
+/// Something that can be turned into a render mesh.
+pub trait Primitive {
+    fn build(&self) -> Result<Mesh, String>;
+
+    /// Check the parameters, reporting errors relative to the object.
+    fn validate(&self, _v: &mut Validator) {}
+
+    /// Whether this is drawn with a [`line_material::LineMaterial`].
+    fn is_line(&self) -> bool {
+        false
+    }
+}
+
+/// Upper bound on vertex counts around and along the built-in shapes, so a
+/// scene cannot ask Bevy for more vertices than fit in memory
+pub const MAX_RESOLUTION: u32 = 1024;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct MeshParams {
+    /// OBJ file contents
+    pub data: String,
+    /// Smoothing applied on top of `data` when the mesh is built
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdivision: Option<Subdivision>,
+    /// Normals generated after loading and subdividing. Without it the
+    /// normals in `data` are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shading: Option<Shading>,
+}
+
+/// A sketched stroke, saved together with the style it was drawn in.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StrokeParams {
+    pub style: StrokeStyle,
+    /// Sketch layer the stroke is on, see [`crate::layers`]
+    pub layer: u32,
+    /// Radius at full width in world units. Lines keep their width per point.
+    pub radius: f32,
+    /// Touch samples as drawn, before smoothing
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<[f32; 3]>,
+    /// The smoothed curve the stroke was built along
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub curve: Vec<[f32; 3]>,
+    /// Cross-section of an extruded stroke
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    /// OBJ file contents, for every style but lines
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub data: String,
+    /// Centerline of a line as `[x, y, z, width]`, the width in pixels
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub points: Vec<[f32; 4]>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SphereParams {
+    pub radius: f32,
+    /// Icosphere subdivision level
+    pub subdivisions: u32,
+}
+
+impl Default for SphereParams {
+    fn default() -> Self {
+        Self {
+            radius: 1.0,
+            subdivisions: 5,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PlaneParams {
+    pub width: f32,
+    pub depth: f32,
+    pub subdivisions: u32,
+}
+
+impl Default for PlaneParams {
+    fn default() -> Self {
+        Self {
+            width: 100.0,
+            depth: 100.0,
+            subdivisions: 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CubeParams {
+    pub width: f32,
+    pub height: f32,
+    pub depth: f32,
+}
+
+impl Default for CubeParams {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            height: 1.0,
+            depth: 1.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CylinderParams {
+    pub radius: f32,
+    pub height: f32,
+    /// Number of vertices around each ring
+    pub resolution: u32,
+    /// Number of segments along the height
+    pub segments: u32,
+}
+
+impl Default for CylinderParams {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            height: 1.0,
+            resolution: 32,
+            segments: 1,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ConeParams {
+    pub radius: f32,
+    pub height: f32,
+    pub resolution: u32,
+}
+
+impl Default for ConeParams {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            height: 1.0,
+            resolution: 32,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TorusParams {
+    /// Radius of the tube
+    pub minor_radius: f32,
+    /// Distance from the center to the middle of the tube
+    pub major_radius: f32,
+    pub minor_resolution: u32,
+    pub major_resolution: u32,
+}
+
+impl Default for TorusParams {
+    fn default() -> Self {
+        Self {
+            minor_radius: 0.25,
+            major_radius: 0.75,
+            minor_resolution: 24,
+            major_resolution: 32,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CapsuleParams {
+    pub radius: f32,
+    /// Length of the cylindrical middle section
+    pub length: f32,
+    pub latitudes: u32,
+    pub longitudes: u32,
+}
+
+impl Default for CapsuleParams {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            length: 1.0,
+            latitudes: 16,
+            longitudes: 32,
+        }
+    }
+}
+
+impl Primitive for MeshParams {
+    fn build(&self) -> Result<Mesh, String> {
+        let mut mesh = match &self.subdivision {
+            Some(subdivision) => {
+                let polygons = file_io::load_obj_polygons(&self.data)?;
+                subdivision.apply(polygons).to_mesh()
+            }
+            None => file_io::load_obj(self.data.clone())?,
+        };
+        if let Some(shading) = self.shading {
+            mesh.set_shading(shading);
+        }
+        Ok(file_io::to_bevy_mesh(&mesh))
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        if let Some(subdivision) = &self.subdivision {
+            v.at("subdivision", |v| subdivision.validate(v));
+        }
+        if let Some(shading) = &self.shading {
+            v.at("shading", |v| shading.validate(v));
+        }
+        // Parsed again by `build`, but a bad file is reported here under its path
+        let loaded = match &self.subdivision {
+            Some(_) => file_io::load_obj_polygons(&self.data).map(drop),
+            None => file_io::load_obj(self.data.clone()).map(drop),
+        };
+        if let Err(e) = loaded {
+            v.field_error("data", e);
+        }
+    }
+}
+
+impl Primitive for StrokeParams {
+    fn build(&self) -> Result<Mesh, String> {
+        if self.style == StrokeStyle::Line {
+            return Ok(line_material::line_mesh(&self.points));
+        }
+        let mesh = file_io::load_obj(self.data.clone())?;
+        Ok(file_io::to_bevy_mesh(&mesh))
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        if self.layer == u32::MAX {
+            v.field_error("layer", format!("must be less than {}", u32::MAX));
+        }
+        if self.style == StrokeStyle::Line {
+            if self.points.len() < 2 {
+                v.field_error("points", "must have at least 2 points");
+            }
+            v.finite("points", self.points.as_flattened());
+            if self.points.iter().any(|p| p[3] <= 0.0) {
+                v.field_error("points", "widths must be positive");
+            }
+        } else if let Err(e) = file_io::load_obj(self.data.clone()) {
+            v.field_error("data", e);
+        }
+        if let Some(profile) = &self.profile {
+            v.at("profile", |v| profile.validate(v));
+        }
+    }
+
+    fn is_line(&self) -> bool {
+        self.style == StrokeStyle::Line
+    }
+}
+
+impl Primitive for SphereParams {
+    fn build(&self) -> Result<Mesh, String> {
+        Sphere::new(self.radius)
+            .mesh()
+            .ico(self.subdivisions)
+            .map_err(|e| e.to_string())
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        v.positive("radius", self.radius);
+        v.in_range("subdivisions", self.subdivisions, 0, 80);
+    }
+}
+
+impl Primitive for PlaneParams {
+    fn build(&self) -> Result<Mesh, String> {
+        Ok(Plane3d::default()
+            .mesh()
+            .size(self.width, self.depth)
+            .subdivisions(self.subdivisions)
+            .into())
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        v.positive("width", self.width);
+        v.positive("depth", self.depth);
+        v.in_range("subdivisions", self.subdivisions, 0, MAX_RESOLUTION);
+    }
+}
+
+impl Primitive for CubeParams {
+    fn build(&self) -> Result<Mesh, String> {
+        Ok(Cuboid::new(self.width, self.height, self.depth)
+            .mesh()
+            .into())
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        v.positive("width", self.width);
+        v.positive("height", self.height);
+        v.positive("depth", self.depth);
+    }
+}
+
+impl Primitive for CylinderParams {
+    fn build(&self) -> Result<Mesh, String> {
+        Ok(Cylinder::new(self.radius, self.height)
+            .mesh()
+            .resolution(self.resolution)
+            .segments(self.segments)
+            .into())
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        v.positive("radius", self.radius);
+        v.positive("height", self.height);
+        v.in_range("resolution", self.resolution, 3, MAX_RESOLUTION);
+        v.in_range("segments", self.segments, 1, MAX_RESOLUTION);
+    }
+}
+
+impl Primitive for ConeParams {
+    fn build(&self) -> Result<Mesh, String> {
+        Ok(Cone::new(self.radius, self.height)
+            .mesh()
+            .resolution(self.resolution)
+            .into())
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        v.positive("radius", self.radius);
+        v.positive("height", self.height);
+        v.in_range("resolution", self.resolution, 3, MAX_RESOLUTION);
+    }
+}
+
+impl Primitive for TorusParams {
+    fn build(&self) -> Result<Mesh, String> {
+        let inner = self.major_radius - self.minor_radius;
+        let outer = self.major_radius + self.minor_radius;
+        Ok(Torus::new(inner, outer)
+            .mesh()
+            .minor_resolution(self.minor_resolution as usize)
+            .major_resolution(self.major_resolution as usize)
+            .into())
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        v.positive("minorRadius", self.minor_radius);
+        if self.major_radius <= self.minor_radius {
+            v.field_error("majorRadius", "must be larger than minorRadius");
+        }
+        v.in_range("minorResolution", self.minor_resolution, 3, MAX_RESOLUTION);
+        v.in_range("majorResolution", self.major_resolution, 3, MAX_RESOLUTION);
+    }
+}
+
+impl Primitive for CapsuleParams {
+    fn build(&self) -> Result<Mesh, String> {
+        Ok(Capsule3d::new(self.radius, self.length)
+            .mesh()
+            .latitudes(self.latitudes)
+            .longitudes(self.longitudes)
+            .into())
+    }
+
+    fn validate(&self, v: &mut Validator) {
+        v.positive("radius", self.radius);
+        if !self.length.is_finite() || self.length < 0.0 {
+            v.field_error("length", "must not be negative");
+        }
+        v.in_range("latitudes", self.latitudes, 2, MAX_RESOLUTION);
+        v.in_range("longitudes", self.longitudes, 3, MAX_RESOLUTION);
+    }
+}
+
+/// Declares [`ObjKind`] and [`PRIMITIVES`] from one list, so each kind's
+/// variant, parameters and `type` name are written once.
+macro_rules! registry {
+    ($($variant:ident($params:ty) = $name:literal,)*) => {
+        /// The kind of an object in the scene, tagged by its `type` field.
+        #[derive(Serialize, Debug, Clone, PartialEq)]
+        #[serde(tag = "type")]
+        pub enum ObjKind {
+            $(
+                #[serde(rename = $name)]
+                $variant($params),
+            )*
+        }
+
+        impl ObjKind {
+            pub fn as_primitive(&self) -> &dyn Primitive {
+                match self {
+                    $(ObjKind::$variant(p) => p,)*
+                }
+            }
+
+            /// The `type` name this kind is registered under.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(ObjKind::$variant(_) => $name,)*
+                }
+            }
+        }
+
+        /// Every object kind the scene JSON accepts.
+        pub const PRIMITIVES: &[PrimitiveEntry] = &[
+            $(PrimitiveEntry {
+                name: $name,
+                parse: |v| serde_path_to_error::deserialize(v).map(ObjKind::$variant),
+            },)*
+        ];
+    };
+}
+
+registry! {
+    Mesh(MeshParams) = "mesh",
+    Stroke(StrokeParams) = "stroke",
+    Sphere(SphereParams) = "sphere",
+    Plane(PlaneParams) = "plane",
+    Cube(CubeParams) = "cube",
+    Cylinder(CylinderParams) = "cylinder",
+    Cone(ConeParams) = "cone",
+    Torus(TorusParams) = "torus",
+    Capsule(CapsuleParams) = "capsule",
+}
+
+impl ObjKind {
+    pub fn build(&self) -> Result<Mesh, String> {
+        self.as_primitive().build()
+    }
+
+    /// Whether this is drawn with a [`line_material::LineMaterial`].
+    pub fn is_line(&self) -> bool {
+        self.as_primitive().is_line()
+    }
+}
+
+/// A registered object kind: its `type` name and how to parse its parameters.
+pub struct PrimitiveEntry {
+    pub name: &'static str,
+    parse: fn(Value) -> Result<ObjKind, serde_path_to_error::Error<serde_json::Error>>,
+}
+
+impl PrimitiveEntry {
+    /// Parse an object of this kind, keeping the path of any error. Fields
+    /// that are not parameters, like `type` and `pos`, are ignored.
+    pub fn parse(
+        &self,
+        object: Value,
+    ) -> Result<ObjKind, serde_path_to_error::Error<serde_json::Error>> {
+        (self.parse)(object)
+    }
+}
+
+pub fn supported_kinds() -> Vec<&'static str> {
+    PRIMITIVES.iter().map(|e| e.name).collect()
+}
+
+/// The registry entry for a `type` name.
+pub fn find(name: &str) -> Option<&'static PrimitiveEntry> {
+    PRIMITIVES.iter().find(|e| e.name == name)
+}
+
+impl<'de> Deserialize<'de> for ObjKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let object = Value::deserialize(deserializer)?;
+        let name = match object.get("type") {
+            Some(Value::String(name)) => name,
+            Some(_) => return Err(D::Error::custom("`type` must be a string")),
+            None => return Err(D::Error::missing_field("type")),
+        };
+        let entry = find(name).ok_or_else(|| {
+            D::Error::custom(format!(
+                "unknown object type `{}`, expected one of: {}",
+                name,
+                supported_kinds().join(", ")
+            ))
+        })?;
+        entry.parse(object).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn every_kind_parses_under_its_name() {
+        for entry in PRIMITIVES {
+            let kind = entry
+                .parse(json!({ "type": entry.name, "data": "" }))
+                .unwrap();
+            assert_eq!(kind.name(), entry.name);
+            assert_eq!(serde_json::to_value(&kind).unwrap()["type"], entry.name);
+        }
+    }
+
+    #[test]
+    fn kinds_are_tagged_by_type() {
+        let kind: ObjKind =
+            serde_json::from_value(json!({ "type": "cone", "radius": 2.0 })).unwrap();
+        assert_eq!(
+            kind,
+            ObjKind::Cone(ConeParams {
+                radius: 2.0,
+                ..Default::default()
+            })
+        );
+        assert!(!kind.is_line());
+
+        let error = serde_json::from_value::<ObjKind>(json!({ "type": "teapot" })).unwrap_err();
+        assert!(error.to_string().contains("unknown object type `teapot`"));
+        assert!(serde_json::from_value::<ObjKind>(json!({ "radius": 2.0 })).is_err());
+    }
+
+    #[test]
+    fn lines_are_the_only_line_kind() {
+        let line = ObjKind::Stroke(StrokeParams {
+            style: StrokeStyle::Line,
+            ..Default::default()
+        });
+        assert!(line.is_line());
+        assert!(!ObjKind::Stroke(StrokeParams::default()).is_line());
+    }
+
+    #[test]
+    fn parameter_errors_keep_their_path() {
+        let error = find("sphere")
+            .unwrap()
+            .parse(json!({ "type": "sphere", "radius": "large" }))
+            .unwrap_err();
+        assert_eq!(error.path().to_string(), "radius");
+
+        let scene = r#"{"scene": {"objects": [{"type": "sphere", "radius": "large"}]}}"#;
+        let errors = crate::validation::parse_app_opts(scene).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "scene.objects[0].radius");
+    }
+
+    #[test]
+    fn oversized_counts_are_rejected() {
+        let huge = 4_000_000_000;
+        let kinds: [(&dyn Primitive, &str); 5] = [
+            (
+                &CylinderParams {
+                    resolution: huge,
+                    ..Default::default()
+                },
+                "resolution",
+            ),
+            (
+                &ConeParams {
+                    resolution: huge,
+                    ..Default::default()
+                },
+                "resolution",
+            ),
+            (
+                &TorusParams {
+                    major_resolution: huge,
+                    ..Default::default()
+                },
+                "majorResolution",
+            ),
+            (
+                &PlaneParams {
+                    subdivisions: huge,
+                    ..Default::default()
+                },
+                "subdivisions",
+            ),
+            (
+                &CapsuleParams {
+                    longitudes: huge,
+                    ..Default::default()
+                },
+                "longitudes",
+            ),
+        ];
+        for (params, field) in kinds {
+            let mut v = Validator::default();
+            params.validate(&mut v);
+            let errors = v.into_errors();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].path, field);
+        }
+        let mut v = Validator::default();
+        CylinderParams::default().validate(&mut v);
+        assert!(v.into_errors().is_empty());
+    }
+}
@@ -0,0 +1,279 @@
+//! Where sketch touches land in the scene: on a plane, or on the surface of
+//! the objects under the finger.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    picking::{self, MeshBvhCache, RayHit},
+    scene3d::{InteractionMode, MyPluginConfig, ObjectId},
+    stroke::StrokeSettings,
+    validation::Validator,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ProjectionTarget {
+    /// Plane facing the camera, `depth` in front of it
+    #[default]
+    Depth,
+    /// Plane perpendicular to a world axis
+    AxisPlane,
+    /// Plane placed by the user, see [`WorkPlane`]
+    WorkPlane,
+    /// Surface of the scene objects. Touches that miss every object are
+    /// dropped from the stroke.
+    Surface,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WorldAxis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+impl WorldAxis {
+    pub fn direction(self) -> Vec3 {
+        match self {
+            WorldAxis::X => Vec3::X,
+            WorldAxis::Y => Vec3::Y,
+            WorldAxis::Z => Vec3::Z,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WorkPlane {
+    pub origin: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+impl Default for WorkPlane {
+    fn default() -> Self {
+        Self {
+            origin: [0.0, 0.0, 0.0],
+            normal: [0.0, 1.0, 0.0],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProjectionSettings {
+    pub target: ProjectionTarget,
+    /// Distance of the camera facing plane from the camera
+    pub depth: f32,
+    pub axis: WorldAxis,
+    /// Where the axis plane crosses its axis
+    pub axis_offset: f32,
+    pub work_plane: WorkPlane,
+    /// Lift off the surface along its normal, so a stroke drawn on an object
+    /// is not buried in it
+    pub surface_offset: f32,
+}
+
+impl Default for ProjectionSettings {
+    fn default() -> Self {
+        Self {
+            target: ProjectionTarget::Depth,
+            depth: 10.0,
+            axis: WorldAxis::Y,
+            axis_offset: 0.0,
+            work_plane: WorkPlane::default(),
+            surface_offset: 0.1,
+        }
+    }
+}
+
+impl ProjectionSettings {
+    pub fn validate(&self, v: &mut Validator) {
+        v.positive("depth", self.depth);
+        v.finite("axisOffset", &[self.axis_offset]);
+        v.at("workPlane", |v| {
+            v.finite("origin", &self.work_plane.origin);
+            let normal = Vec3::from_array(self.work_plane.normal);
+            if !(normal.is_finite() && normal.length_squared() > 0.0) {
+                v.field_error("normal", "must be a non-zero vector");
+            }
+        });
+        if !(self.surface_offset.is_finite() && self.surface_offset >= 0.0) {
+            v.field_error("surfaceOffset", "must be a non-negative number");
+        }
+    }
+
+    /// The plane touches are projected on as `(point, normal)`, or `None`
+    /// when projecting on surfaces.
+    pub fn plane(&self, camera: &GlobalTransform) -> Option<(Vec3, Vec3)> {
+        match self.target {
+            ProjectionTarget::Depth => {
+                let forward = camera.forward();
+                Some((camera.translation() + forward * self.depth, *forward))
+            }
+            ProjectionTarget::AxisPlane => {
+                let normal = self.axis.direction();
+                Some((normal * self.axis_offset, normal))
+            }
+            ProjectionTarget::WorkPlane => Some((
+                Vec3::from_array(self.work_plane.origin),
+                Vec3::from_array(self.work_plane.normal).normalize_or(Vec3::Y),
+            )),
+            ProjectionTarget::Surface => None,
+        }
+    }
+
+    /// Where a touch ray lands, if anywhere. `cast` finds the closest
+    /// surface hit for [`ProjectionTarget::Surface`].
+    pub fn project(
+        &self,
+        ray: Ray3d,
+        camera: &GlobalTransform,
+        cast: impl FnOnce(Ray3d) -> Option<RayHit>,
+    ) -> Option<Vec3> {
+        match self.plane(camera) {
+            Some((point, normal)) => {
+                let plane = InfinitePlane3d::new(normal);
+                let distance = ray.intersect_plane(point, plane)?;
+                Some(ray.get_point(distance))
+            }
+            None => cast(ray).map(|hit| hit.point + hit.normal * self.surface_offset),
+        }
+    }
+}
+
+/// Closest hit of a ray with the visible scene objects.
+pub fn cast_scene(
+    ray: Ray3d,
+    objects: &Query<(&Mesh3d, &GlobalTransform, &InheritedVisibility), With<ObjectId>>,
+    meshes: &Assets<Mesh>,
+    cache: &mut MeshBvhCache,
+) -> Option<RayHit> {
+    let mut best: Option<RayHit> = None;
+    for (mesh, transform, visibility) in objects {
+        if !visibility.get() {
+            continue;
+        }
+        let Some(mesh) = cache.get(mesh, meshes) else {
+            continue;
+        };
+        if let Some(hit) = picking::ray_mesh(ray, mesh, transform) {
+            if best.is_none_or(|b| hit.distance < b.distance) {
+                best = Some(hit);
+            }
+        }
+    }
+    best
+}
+
+/// Show the plane strokes are drawn on while sketching, unless it is the
+/// camera facing one.
+pub fn draw_projection_plane(
+    mut gizmos: Gizmos,
+    config: Res<MyPluginConfig>,
+    settings: Res<StrokeSettings>,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+) {
+    let projection = &settings.projection;
+    if config.mode != InteractionMode::Sketch
+        || matches!(
+            projection.target,
+            ProjectionTarget::Depth | ProjectionTarget::Surface
+        )
+    {
+        return;
+    }
+    let Ok(camera) = cameras.single() else {
+        return;
+    };
+    let Some((point, normal)) = projection.plane(camera) else {
+        return;
+    };
+    let rotation = Quat::from_rotation_arc(Vec3::Z, normal);
+    gizmos.grid(
+        Isometry3d::new(point, rotation),
+        UVec2::splat(20),
+        Vec2::splat(1.0),
+        Color::srgba(0.5, 0.7, 1.0, 0.4),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> GlobalTransform {
+        GlobalTransform::from(Transform::from_xyz(0.0, 0.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y))
+    }
+
+    fn down_ray() -> Ray3d {
+        Ray3d::new(Vec3::new(1.0, 5.0, 2.0), Dir3::NEG_Y)
+    }
+
+    fn no_surface(_: Ray3d) -> Option<RayHit> {
+        None
+    }
+
+    #[test]
+    fn depth_plane_faces_the_camera() {
+        let settings = ProjectionSettings {
+            depth: 4.0,
+            ..default()
+        };
+        let ray = Ray3d::new(Vec3::new(0.5, 0.5, 10.0), Dir3::NEG_Z);
+        let p = settings.project(ray, &camera(), no_surface).unwrap();
+        assert!(p.abs_diff_eq(Vec3::new(0.5, 0.5, 6.0), 1e-5));
+    }
+
+    #[test]
+    fn axis_plane_is_offset_along_its_axis() {
+        let settings = ProjectionSettings {
+            target: ProjectionTarget::AxisPlane,
+            axis: WorldAxis::Y,
+            axis_offset: 2.0,
+            ..default()
+        };
+        let p = settings.project(down_ray(), &camera(), no_surface).unwrap();
+        assert!(p.abs_diff_eq(Vec3::new(1.0, 2.0, 2.0), 1e-5));
+        // Parallel to the plane, never reaching it
+        let ray = Ray3d::new(Vec3::ZERO, Dir3::X);
+        assert_eq!(settings.project(ray, &camera(), no_surface), None);
+    }
+
+    #[test]
+    fn work_plane_normal_is_normalized() {
+        let settings = ProjectionSettings {
+            target: ProjectionTarget::WorkPlane,
+            work_plane: WorkPlane {
+                origin: [0.0, 1.0, 0.0],
+                normal: [0.0, 3.0, 0.0],
+            },
+            ..default()
+        };
+        let (_, normal) = settings.plane(&camera()).unwrap();
+        assert!(normal.abs_diff_eq(Vec3::Y, 1e-6));
+        let p = settings.project(down_ray(), &camera(), no_surface).unwrap();
+        assert!(p.abs_diff_eq(Vec3::new(1.0, 1.0, 2.0), 1e-5));
+    }
+
+    #[test]
+    fn surface_hits_are_lifted_along_the_normal() {
+        let settings = ProjectionSettings {
+            target: ProjectionTarget::Surface,
+            surface_offset: 0.5,
+            ..default()
+        };
+        assert_eq!(settings.plane(&camera()), None);
+        let hit = RayHit {
+            distance: 5.0,
+            point: Vec3::new(1.0, 0.0, 2.0),
+            normal: Vec3::Y,
+        };
+        let p = settings.project(down_ray(), &camera(), |_| Some(hit));
+        assert_eq!(p, Some(Vec3::new(1.0, 0.5, 2.0)));
+        // Touches that miss every object are dropped
+        assert_eq!(settings.project(down_ray(), &camera(), no_surface), None);
+    }
+}
@@ -0,0 +1,613 @@
+//! Checks and repairs for meshes that are about to be exported or 3D
+//! printed: duplicate vertices, degenerate and duplicate triangles,
+//! inconsistent winding, non-manifold edges and holes.
+//!
+//! Topology is judged on positions only, so a vertex split for its normal
+//! or UV still joins its neighbours. Welding keeps those splits and only
+//! merges vertices whose attributes match as well.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{geometry::Mesh, validation::Validator};
+
+/// Triangles with less area than this fraction of the squared mesh size are
+/// degenerate.
+const DEGENERATE_AREA: f32 = 1e-10;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RepairOptions {
+    /// Merge vertices within `weld_distance` that have the same normal and
+    /// UV
+    pub weld: bool,
+    /// In mesh units. Also used to tell which triangles share an edge.
+    pub weld_distance: f32,
+    pub remove_degenerate: bool,
+    /// Triangles over the same three vertices, in either winding
+    pub remove_duplicates: bool,
+    /// Turn triangles to agree with their neighbours, and closed shells to
+    /// face outwards
+    pub fix_winding: bool,
+    /// Holes with at most this many edges are closed. 0 leaves every hole
+    /// open.
+    pub max_hole_edges: u32,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self {
+            weld: true,
+            weld_distance: 1e-5,
+            remove_degenerate: true,
+            remove_duplicates: true,
+            fix_winding: true,
+            max_hole_edges: 16,
+        }
+    }
+}
+
+impl RepairOptions {
+    /// Only report, leaving the mesh as it is apart from triangles that
+    /// point past its vertices.
+    pub const CHECK: Self = Self {
+        weld: false,
+        weld_distance: 1e-5,
+        remove_degenerate: false,
+        remove_duplicates: false,
+        fix_winding: false,
+        max_hole_edges: 0,
+    };
+
+    pub fn validate(&self, v: &mut Validator) {
+        if !(self.weld_distance.is_finite() && self.weld_distance >= 0.0) {
+            v.field_error("weldDistance", "must be a non-negative number");
+        }
+    }
+}
+
+/// What [`repair`] found. The problem counts are for the input mesh, and
+/// were fixed where the options asked for it. `vertices`, `triangles`,
+/// `non_manifold_edges`, `holes` and `watertight` describe the result.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MeshReport {
+    pub vertices: usize,
+    pub triangles: usize,
+    /// Triangles pointing past the vertices, always removed
+    pub invalid_triangles: usize,
+    /// Vertices that are copies of another one
+    pub welded_vertices: usize,
+    pub degenerate_triangles: usize,
+    pub duplicate_triangles: usize,
+    /// Triangles wound against the rest of their shell, or in a closed
+    /// shell facing inwards
+    pub flipped_triangles: usize,
+    pub filled_holes: usize,
+    /// Edges shared by more than two triangles
+    pub non_manifold_edges: usize,
+    /// Edge count of each hole left open
+    pub holes: Vec<usize>,
+    /// Closed and manifold, so it has a well defined inside
+    pub watertight: bool,
+}
+
+/// Group positions within `distance` of each other. Returns the group of
+/// each position and the position of each group.
+fn cluster(positions: &[Vec3], distance: f32) -> (Vec<u32>, Vec<Vec3>) {
+    let cell = distance.max(f32::MIN_POSITIVE);
+    let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+    let mut centers: Vec<Vec3> = Vec::new();
+    let groups = positions
+        .iter()
+        .map(|p| {
+            let key = p.to_array().map(|c| (c / cell).floor() as i64);
+            let mut found = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let near = [key[0] + dx, key[1] + dy, key[2] + dz];
+                        for &g in grid.get(&near).into_iter().flatten() {
+                            if centers[g as usize].distance(*p) <= distance {
+                                found = Some(g);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+            found.unwrap_or_else(|| {
+                centers.push(*p);
+                let g = centers.len() as u32 - 1;
+                grid.entry(key).or_default().push(g);
+                g
+            })
+        })
+        .collect();
+    (groups, centers)
+}
+
+/// Triangles using an edge, with whether each runs along it from the lower
+/// to the higher vertex.
+fn edge_uses(triangles: &[[u32; 3]]) -> HashMap<(u32, u32), Vec<(usize, bool)>> {
+    let mut edges: HashMap<(u32, u32), Vec<(usize, bool)>> = HashMap::new();
+    for (i, t) in triangles.iter().enumerate() {
+        for k in 0..3 {
+            let (a, b) = (t[k], t[(k + 1) % 3]);
+            if a != b {
+                edges
+                    .entry((a.min(b), a.max(b)))
+                    .or_default()
+                    .push((i, a < b));
+            }
+        }
+    }
+    edges
+}
+
+/// Which triangles to turn so every shell is wound consistently, facing
+/// outwards when closed. Shells are joined across manifold edges.
+fn orient(triangles: &[[u32; 3]], positions: &[Vec3]) -> Vec<bool> {
+    let edges = edge_uses(triangles);
+    let mut neighbours = vec![Vec::new(); triangles.len()];
+    for uses in edges.values() {
+        if let [(a, forward_a), (b, forward_b)] = uses[..] {
+            // Neighbours agree when they run along the edge in opposite directions
+            let same = forward_a == forward_b;
+            neighbours[a].push((b, same));
+            neighbours[b].push((a, same));
+        }
+    }
+
+    let mut flip: Vec<Option<bool>> = vec![None; triangles.len()];
+    for start in 0..triangles.len() {
+        if flip[start].is_some() {
+            continue;
+        }
+        flip[start] = Some(false);
+        let mut shell = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(t) = queue.pop_front() {
+            for &(n, same) in &neighbours[t] {
+                if flip[n].is_none() {
+                    flip[n] = Some(flip[t].unwrap() ^ same);
+                    shell.push(n);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        let turned = |t: usize, flip: &[Option<bool>]| {
+            let [a, b, c] = triangles[t].map(|i| positions[i as usize]);
+            if flip[t] == Some(true) {
+                [a, c, b]
+            } else {
+                [a, b, c]
+            }
+        };
+        let closed = shell.iter().all(|&t| neighbours[t].len() == 3);
+        let invert = if closed {
+            // Signed volume, negative when the shell faces inwards
+            let volume: f32 = shell
+                .iter()
+                .map(|&t| {
+                    let [a, b, c] = turned(t, &flip);
+                    a.dot(b.cross(c))
+                })
+                .sum();
+            volume < 0.0
+        } else {
+            // Turn the smaller part
+            let flipped = shell.iter().filter(|&&t| flip[t] == Some(true)).count();
+            flipped * 2 > shell.len()
+        };
+        if invert {
+            for &t in &shell {
+                flip[t] = flip[t].map(|f| !f);
+            }
+        }
+    }
+    flip.into_iter().map(|f| f == Some(true)).collect()
+}
+
+/// A boundary edge of the mesh, in the direction a triangle closing it would
+/// run along it.
+#[derive(Debug, Clone, Copy)]
+struct HoleEdge {
+    from: u32,
+    to: u32,
+    /// Vertex of the mesh at `from`
+    source: u32,
+}
+
+/// Boundary loops, each as the edges a patch closing it runs along.
+fn holes(triangles: &[[u32; 3]], groups: &[u32]) -> Vec<Vec<HoleEdge>> {
+    let grouped: Vec<[u32; 3]> = triangles
+        .iter()
+        .map(|t| t.map(|i| groups[i as usize]))
+        .collect();
+    let mut outgoing: HashMap<u32, Vec<HoleEdge>> = HashMap::new();
+    for (&edge, uses) in &edge_uses(&grouped) {
+        let [(t, _)] = uses[..] else { continue };
+        for k in 0..3 {
+            let (a, b) = (grouped[t][k], grouped[t][(k + 1) % 3]);
+            if (a.min(b), a.max(b)) == edge {
+                // The hole runs the other way
+                outgoing.entry(b).or_default().push(HoleEdge {
+                    from: b,
+                    to: a,
+                    source: triangles[t][(k + 1) % 3],
+                });
+            }
+        }
+    }
+
+    let mut loops = Vec::new();
+    let mut starts: Vec<u32> = outgoing.keys().copied().collect();
+    starts.sort_unstable();
+    for start in starts {
+        while let Some(first) = outgoing.get_mut(&start).and_then(Vec::pop) {
+            let mut edges = vec![first];
+            let mut at = first.to;
+            while at != start {
+                let Some(next) = outgoing.get_mut(&at).and_then(Vec::pop) else {
+                    // Open chain around a non-manifold vertex
+                    break;
+                };
+                edges.push(next);
+                at = next.to;
+            }
+            loops.push(edges);
+        }
+    }
+    loops
+}
+
+/// Builds the repaired mesh, creating each output vertex on first use.
+struct Output<'a> {
+    mesh: &'a Mesh,
+    positions: &'a [Vec3],
+    /// Output index by source vertex and whether its normal is turned
+    index: HashMap<(u32, bool), u32>,
+    result: Mesh,
+}
+
+impl Output<'_> {
+    fn has_normals(&self) -> bool {
+        self.mesh.normals.len() == self.mesh.positions().len()
+    }
+
+    fn has_uvs(&self) -> bool {
+        self.mesh.uvs.len() == self.mesh.positions().len()
+    }
+
+    fn push(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> u32 {
+        self.result.positions_mut().push(position);
+        if self.has_normals() {
+            self.result.normals.push(normal);
+        }
+        if self.has_uvs() {
+            self.result.uvs.push(uv);
+        }
+        self.result.positions().len() as u32 - 1
+    }
+
+    fn normal(&self, source: u32) -> Vec3 {
+        self.mesh
+            .normals
+            .get(source as usize)
+            .copied()
+            .unwrap_or(Vec3::ZERO)
+    }
+
+    fn uv(&self, source: u32) -> Vec2 {
+        self.mesh
+            .uvs
+            .get(source as usize)
+            .copied()
+            .unwrap_or(Vec2::ZERO)
+    }
+
+    fn vertex(&mut self, source: u32, turned: bool) -> u32 {
+        if let Some(&i) = self.index.get(&(source, turned)) {
+            return i;
+        }
+        let normal = self.normal(source);
+        let i = self.push(
+            self.positions[source as usize],
+            if turned { -normal } else { normal },
+            self.uv(source),
+        );
+        self.index.insert((source, turned), i);
+        i
+    }
+}
+
+/// Check `mesh` and fix what `options` asks for, returning the repaired
+/// mesh and what was found.
+pub fn repair(mesh: &Mesh, options: &RepairOptions) -> (Mesh, MeshReport) {
+    let mut report = MeshReport::default();
+    let count = mesh.positions().len();
+    let has_normals = mesh.normals.len() == count;
+    let has_uvs = mesh.uvs.len() == count;
+
+    let mut triangles: Vec<[u32; 3]> = mesh
+        .triangles()
+        .iter()
+        .filter(|t| t.iter().all(|&i| (i as usize) < count))
+        .copied()
+        .collect();
+    report.invalid_triangles = mesh.triangles().len() - triangles.len();
+
+    let (groups, centers) = cluster(mesh.positions(), options.weld_distance);
+    // First vertex with the same position group and attributes
+    let mut first = HashMap::new();
+    let same: Vec<u32> = (0..count)
+        .map(|i| {
+            // Adding 0 turns -0 into 0, which compare equal but not by bits
+            let bits = |v: [f32; 3]| v.map(|c| (c + 0.0).to_bits());
+            let normal = has_normals.then(|| bits(mesh.normals[i].to_array()));
+            let uv = has_uvs.then(|| bits(mesh.uvs[i].extend(0.0).to_array()));
+            *first.entry((groups[i], normal, uv)).or_insert(i as u32)
+        })
+        .collect();
+    report.welded_vertices = count - first.len();
+    let positions: Vec<Vec3> = if options.weld {
+        groups.iter().map(|&g| centers[g as usize]).collect()
+    } else {
+        mesh.positions().to_vec()
+    };
+    if options.weld {
+        for t in &mut triangles {
+            *t = t.map(|i| same[i as usize]);
+        }
+    }
+
+    let (min, max) = mesh
+        .positions()
+        .iter()
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        });
+    let min_area = DEGENERATE_AREA * (max - min).length_squared();
+    let degenerate = |t: &[u32; 3]| {
+        let [a, b, c] = t.map(|i| groups[i as usize]);
+        let [pa, pb, pc] = t.map(|i| positions[i as usize]);
+        a == b || b == c || c == a || (pb - pa).cross(pc - pa).length() * 0.5 <= min_area
+    };
+    report.degenerate_triangles = triangles.iter().filter(|t| degenerate(t)).count();
+    if options.remove_degenerate {
+        triangles.retain(|t| !degenerate(t));
+    }
+
+    let mut seen = HashSet::new();
+    let unique: Vec<bool> = triangles
+        .iter()
+        .map(|t| {
+            let mut key = t.map(|i| groups[i as usize]);
+            key.sort_unstable();
+            seen.insert(key)
+        })
+        .collect();
+    report.duplicate_triangles = unique.iter().filter(|u| !**u).count();
+    if options.remove_duplicates {
+        let mut unique = unique.into_iter();
+        triangles.retain(|_| unique.next().unwrap());
+    }
+
+    let grouped: Vec<[u32; 3]> = triangles
+        .iter()
+        .map(|t| t.map(|i| groups[i as usize]))
+        .collect();
+    let flip = orient(&grouped, &centers);
+    report.flipped_triangles = flip.iter().filter(|f| **f).count();
+
+    let mut output = Output {
+        mesh,
+        positions: &positions,
+        index: HashMap::new(),
+        result: Mesh::default(),
+    };
+    let mut kept = Vec::with_capacity(triangles.len());
+    for (t, &flipped) in triangles.iter().zip(&flip) {
+        let [a, b, c] = *t;
+        let t = if flipped && options.fix_winding {
+            [a, c, b]
+        } else {
+            [a, b, c]
+        };
+        kept.push(t);
+        let [pa, pb, pc] = t.map(|i| positions[i as usize]);
+        let face = (pb - pa).cross(pc - pa);
+        // Normals written for the old winding are turned with it
+        let turned = flipped && options.fix_winding && has_normals;
+        let corners = t.map(|i| output.vertex(i, turned && output.normal(i).dot(face) < 0.0));
+        output.result.triangles_mut().push(corners);
+    }
+
+    for hole in holes(&kept, &groups) {
+        let closed = hole.first().map(|e| e.from) == hole.last().map(|e| e.to);
+        if !closed || hole.len() < 3 || hole.len() > options.max_hole_edges as usize {
+            report.holes.push(hole.len());
+            continue;
+        }
+        report.filled_holes += 1;
+        let corners: Vec<Vec3> = hole.iter().map(|e| centers[e.from as usize]).collect();
+        // Newell's normal, facing the way the patch is wound
+        let normal = corners
+            .iter()
+            .zip(corners.iter().cycle().skip(1))
+            .fold(Vec3::ZERO, |n, (a, b)| n + a.cross(*b))
+            .normalize_or_zero();
+        let ring: Vec<u32> = hole
+            .iter()
+            .map(|e| {
+                let uv = output.uv(e.source);
+                output.push(positions[e.source as usize], normal, uv)
+            })
+            .collect();
+        if ring.len() == 3 {
+            output
+                .result
+                .triangles_mut()
+                .push([ring[0], ring[1], ring[2]]);
+            continue;
+        }
+        let n = ring.len() as f32;
+        let center = corners.iter().sum::<Vec3>() / n;
+        let uv = hole.iter().map(|e| output.uv(e.source)).sum::<Vec2>() / n;
+        let middle = output.push(center, normal, uv);
+        for k in 0..ring.len() {
+            let next = ring[(k + 1) % ring.len()];
+            output.result.triangles_mut().push([ring[k], next, middle]);
+        }
+    }
+
+    let result = output.result;
+    let (groups, _) = cluster(result.positions(), options.weld_distance);
+    let grouped: Vec<[u32; 3]> = result
+        .triangles()
+        .iter()
+        .map(|t| t.map(|i| groups[i as usize]))
+        .collect();
+    report.non_manifold_edges = edge_uses(&grouped)
+        .values()
+        .filter(|uses| uses.len() > 2)
+        .count();
+    report.vertices = result.positions().len();
+    report.triangles = result.triangles().len();
+    report.watertight =
+        report.triangles > 0 && report.holes.is_empty() && report.non_manifold_edges == 0;
+    (result, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::tests::welded_cube;
+
+    /// Every triangle on its own three vertices, as STL stores them
+    fn soup(mesh: &Mesh) -> Mesh {
+        let positions = mesh
+            .triangles()
+            .iter()
+            .flat_map(|t| t.map(|i| mesh.positions()[i as usize]))
+            .collect();
+        let triangles = (0..mesh.triangles().len() as u32)
+            .map(|t| [3 * t, 3 * t + 1, 3 * t + 2])
+            .collect();
+        Mesh::new(positions, Vec::new(), Vec::new(), triangles)
+    }
+
+    fn volume(mesh: &Mesh) -> f32 {
+        let p = mesh.positions();
+        mesh.triangles()
+            .iter()
+            .map(|t| p[t[0] as usize].dot(p[t[1] as usize].cross(p[t[2] as usize])))
+            .sum::<f32>()
+            / 6.0
+    }
+
+    #[test]
+    fn closed_cube_is_watertight() {
+        let (mesh, report) = repair(&welded_cube(), &RepairOptions::CHECK);
+        assert!(report.watertight);
+        assert_eq!(report.vertices, 8);
+        assert_eq!(report.triangles, 12);
+        // Vertices may be renumbered, but the triangles are the same
+        let corners = |mesh: &Mesh| -> Vec<[Vec3; 3]> {
+            mesh.triangles()
+                .iter()
+                .map(|t| t.map(|i| mesh.positions()[i as usize]))
+                .collect()
+        };
+        assert_eq!(corners(&mesh), corners(&welded_cube()));
+    }
+
+    #[test]
+    fn triangle_soup_is_welded() {
+        let (mesh, report) = repair(&soup(&welded_cube()), &RepairOptions::default());
+        assert_eq!(report.welded_vertices, 36 - 8);
+        assert_eq!(mesh.positions().len(), 8);
+        assert!(report.watertight);
+    }
+
+    #[test]
+    fn split_vertices_still_join() {
+        // Checking alone does not weld, but the shell is still closed
+        let report = repair(&soup(&welded_cube()), &RepairOptions::CHECK).1;
+        assert_eq!(report.vertices, 36);
+        assert!(report.watertight);
+    }
+
+    #[test]
+    fn degenerate_duplicate_and_invalid_triangles_are_removed() {
+        let mut cube = welded_cube();
+        let first = cube.triangles()[0];
+        cube.triangles_mut().push([first[2], first[1], first[0]]);
+        cube.triangles_mut().push([0, 0, 1]);
+        cube.triangles_mut().push([0, 1, 99]);
+        let (mesh, report) = repair(&cube, &RepairOptions::default());
+        assert_eq!(report.duplicate_triangles, 1);
+        assert_eq!(report.degenerate_triangles, 1);
+        assert_eq!(report.invalid_triangles, 1);
+        assert_eq!(mesh.triangles().len(), 12);
+        assert!(report.watertight);
+    }
+
+    #[test]
+    fn winding_is_made_consistent_and_outwards() {
+        let mut cube = welded_cube();
+        cube.triangles_mut()[3].swap(0, 1);
+        let report = repair(&cube, &RepairOptions::CHECK).1;
+        assert_eq!(report.non_manifold_edges, 0);
+
+        let (mesh, report) = repair(&cube, &RepairOptions::default());
+        assert_eq!(report.flipped_triangles, 1);
+        assert!((volume(&mesh) - 1.0).abs() < 1e-5);
+
+        // A whole shell facing inwards is turned around
+        let mut inside_out = welded_cube();
+        for t in inside_out.triangles_mut() {
+            t.swap(0, 1);
+        }
+        let (mesh, report) = repair(&inside_out, &RepairOptions::default());
+        assert_eq!(report.flipped_triangles, 12);
+        assert!((volume(&mesh) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn small_holes_are_filled() {
+        let mut open = welded_cube();
+        open.triangles_mut().truncate(10);
+        let report = repair(&open, &RepairOptions::CHECK).1;
+        assert_eq!(report.holes, vec![4]);
+        assert!(!report.watertight);
+
+        let (mesh, report) = repair(&open, &RepairOptions::default());
+        assert_eq!(report.filled_holes, 1);
+        assert!(report.holes.is_empty());
+        assert!(report.watertight);
+        assert!((volume(&mesh) - 1.0).abs() < 1e-5);
+
+        let options = RepairOptions {
+            max_hole_edges: 3,
+            ..Default::default()
+        };
+        assert_eq!(repair(&open, &options).1.holes, vec![4]);
+    }
+
+    #[test]
+    fn non_manifold_edges_are_reported() {
+        // A fin on one of the cube's edges
+        let mut cube = welded_cube();
+        let fin = cube.positions().len() as u32;
+        cube.positions_mut().push(Vec3::new(-1.0, -1.0, 0.5));
+        cube.triangles_mut().push([0, 4, fin]);
+        let report = repair(&cube, &RepairOptions::CHECK).1;
+        assert_eq!(report.non_manifold_edges, 1);
+        assert!(!report.watertight);
+    }
+}
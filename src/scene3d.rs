@@ -1,17 +1,135 @@
 //! A simple 3D scene with light shining over a cube sitting on a plane.
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{file_io::to_bevy_mesh, geometry::meshify};
+use crate::{
+    LightConfig,
+    file_io::to_bevy_mesh,
+    geometry::meshify,
+    gestures::{self, GestureSettings, GestureState},
+    gizmo::{self, TransformGizmo},
+    history::{Command, History, ObjectSnapshot},
+    layers::{self, SketchLayer, SketchLayers},
+    line_material::LineMaterialPlugin,
+    material::MaterialConfig,
+    picking,
+    primitives::ObjKind,
+    projection,
+    stroke::{StrokeSample, StrokeSettings},
+    symmetry,
+};
 
 #[derive(Event)]
 pub struct SpawnMeshEvent {
     pub mesh: Mesh,
+    pub object: SceneObject,
+    /// Spawned along with the object and undone as the same step, e.g. the
+    /// symmetric copies of a stroke
+    pub copies: Vec<(Mesh, SceneObject)>,
 }
 
+/// Identifies a scene object across FFI calls and scene exports.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(pub u32);
+
+/// Hands out object IDs, never reusing one within a session.
 #[derive(Resource, Default)]
+pub struct ObjectIds {
+    next: u32,
+}
+
+impl ObjectIds {
+    /// Use `requested` if given (e.g. from a saved scene), otherwise a fresh ID.
+    pub fn reserve(&mut self, requested: Option<u32>) -> ObjectId {
+        let id = requested.unwrap_or(self.next);
+        // Scenes cannot ask for `u32::MAX`, see `ObjConfig::validate`
+        if let Some(after) = id.checked_add(1) {
+            self.next = self.next.max(after);
+        }
+        ObjectId(id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ObjectEdit {
+    Transform(Transform),
+    Material(MaterialConfig),
+    Visible(bool),
+    /// Copy the object, giving the copy the already reserved ID
+    Duplicate(ObjectId),
+    Delete,
+}
+
+#[derive(Event)]
+pub struct EditObjectEvent {
+    pub id: ObjectId,
+    pub edit: ObjectEdit,
+    /// Whether the edit goes into the undo history
+    pub record: bool,
+}
+
+/// What a touch does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InteractionMode {
+    #[default]
+    Orbit,
+    Sketch,
+    Select,
+}
+
+/// Select the object under a screen position, in physical pixels.
+#[derive(Event)]
+pub struct PickEvent {
+    pub pos: Vec2,
+}
+
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub entity: Option<Entity>,
+    pub id: Option<ObjectId>,
+    /// World space point where the pick ray hit the object
+    pub point: Option<Vec3>,
+}
+
+impl Selection {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// What an entity was spawned from, kept so the scene can be exported again.
+#[derive(Component, Debug, Clone)]
+pub struct SceneObject {
+    pub label: String,
+    pub kind: ObjKind,
+    pub material: MaterialConfig,
+}
+
+#[derive(Resource)]
 pub struct TouchInput {
+    /// The first pointer that went down, driving one-finger interactions
     pub touch: Option<Vec2>,
+    /// Every pointer currently down as `(id, pos)`, in the order they went down
+    pub pointers: Vec<(u64, Vec2)>,
+    /// Pressure of the first pointer, 1 if the screen does not report it
+    pub pressure: f32,
+}
+
+impl Default for TouchInput {
+    fn default() -> Self {
+        Self {
+            touch: None,
+            pointers: Vec::new(),
+            pressure: 1.0,
+        }
+    }
+}
+
+impl TouchInput {
+    pub fn is_multi_touch(&self) -> bool {
+        self.pointers.len() > 1
+    }
 }
 
 #[derive(Resource, Default)]
@@ -23,14 +141,48 @@ pub struct LastTouchInput {
 pub struct MeshConfig {
     pub mesh: Mesh,
     pub transform: Transform,
-    pub color: Color,
+    pub object: SceneObject,
+    pub id: Option<u32>,
+    pub visibility: Visibility,
 }
 
-#[derive(Resource)]
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct OrbitCamera {
     pub azimuth: f32,   // Horizontal angle
     pub elevation: f32, // Vertical angle
     pub radius: f32,    // Distance from the target
+    pub roll: f32,      // Rotation around the view direction
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self {
+            azimuth: 0.0,
+            elevation: 0.0,
+            radius: 10.0,
+            roll: 0.0,
+        }
+    }
+}
+
+impl OrbitCamera {
+    /// Camera position when orbiting around `center`.
+    pub fn eye(&self, center: Vec3) -> Vec3 {
+        // Convert spherical coordinates to cartesian
+        let x = self.radius * self.elevation.cos() * self.azimuth.sin();
+        let y = self.radius * self.elevation.sin();
+        let z = self.radius * self.elevation.cos() * self.azimuth.cos();
+        center + Vec3::new(x, y, z)
+    }
+
+    /// Camera transform when orbiting around `center`.
+    pub fn transform(&self, center: Vec3) -> Transform {
+        let mut transform =
+            Transform::from_translation(self.eye(center)).looking_at(center, Vec3::Y);
+        transform.rotate_local_z(self.roll);
+        transform
+    }
 }
 
 #[derive(Resource)]
@@ -38,66 +190,360 @@ pub struct MyPluginConfig {
     pub env_lightcolor: Color,
     pub move_strength: f32,
     pub meshes: Vec<MeshConfig>,
-    pub camera_pos: Vec<f32>,
-    pub sketch: bool, // Whether to use sketch mode
-    pub sketch_history: Vec<glam::Vec3>,
-    pub current_mesh: Option<crate::geometry::Mesh>,
+    pub lights: Vec<LightConfig>,
+    pub camera_pos: Vec3,
+    pub mode: InteractionMode,
+    pub sketch_history: Vec<StrokeSample>,
+    /// The stroke drawn last, not counting its symmetric copies
+    pub last_stroke: Option<ObjectId>,
+    /// Material used for sketched and imported meshes
+    pub sketch_material: MaterialConfig,
 }
 
 pub struct Scene3DPlugin {
     pub env_lightcolor: Color,
     pub move_strength: f32,
     pub meshes: Vec<MeshConfig>,
-    pub camera_pos: Vec<f32>,
+    pub lights: Vec<LightConfig>,
+    pub camera_pos: Vec3,
+    pub orbit: OrbitCamera,
+    pub gestures: GestureSettings,
+    pub stroke: StrokeSettings,
+    pub layers: Vec<SketchLayer>,
 }
 
 impl Plugin for Scene3DPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<SpawnMeshEvent>();
+        app.add_plugins(LineMaterialPlugin);
+        app.add_event::<SpawnMeshEvent>()
+            .add_event::<EditObjectEvent>()
+            .add_event::<PickEvent>();
         app.insert_resource(TouchInput::default())
+            .insert_resource(Selection::default())
+            .insert_resource(TransformGizmo::default())
+            .insert_resource(self.gestures)
+            .insert_resource(GestureState::default())
+            .insert_resource(self.stroke.clone())
+            .insert_resource(SketchLayers::new(self.layers.clone()))
+            .insert_resource(ObjectIds::default())
+            .insert_resource(History::default())
+            .insert_resource(picking::MeshBvhCache::default())
             .insert_resource(LastTouchInput::default())
             .insert_resource(MyPluginConfig {
                 env_lightcolor: self.env_lightcolor,
                 move_strength: self.move_strength,
                 meshes: self.meshes.clone(),
-                camera_pos: self.camera_pos.clone(),
-                sketch: false,
+                lights: self.lights.clone(),
+                camera_pos: self.camera_pos,
+                mode: InteractionMode::Orbit,
                 sketch_history: Vec::new(),
-                current_mesh: None,
-            })
-            .insert_resource(OrbitCamera {
-                azimuth: 0.0,
-                elevation: 0.0,
-                radius: 10.0,
+                last_stroke: None,
+                sketch_material: MaterialConfig::from_color([0.0, 0.0, 1.0]),
             })
+            .insert_resource(self.orbit)
             .add_systems(Startup, setup)
             .add_systems(Update, handle_spawn_event)
-            .add_systems(Update, move_camera);
+            .add_systems(Update, handle_edit_event)
+            .add_systems(
+                Update,
+                (
+                    handle_pick_event,
+                    gizmo::drag_gizmo,
+                    draw_selection,
+                    gizmo::draw_gizmo,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (projection::draw_projection_plane, symmetry::draw_symmetry),
+            )
+            .add_systems(PostUpdate, layers::sync_layers)
+            .add_systems(PostUpdate, picking::forget_changed_meshes)
+            .add_systems(Update, (gestures::handle_gestures, move_camera).chain());
     }
 }
 
+/// Build the render mesh and material handles for an object.
+fn add_mesh_and_material(
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    asset_server: &AssetServer,
+    mesh: &Mesh,
+    material: &MaterialConfig,
+) -> (Mesh3d, MeshMaterial3d<StandardMaterial>) {
+    let mut mesh = mesh.clone();
+    ensure_tangents(&mut mesh, material);
+    (
+        Mesh3d(meshes.add(mesh)),
+        MeshMaterial3d(materials.add(material.to_standard_material(asset_server))),
+    )
+}
+
+pub(crate) fn ensure_tangents(mesh: &mut Mesh, material: &MaterialConfig) {
+    if material.needs_tangents() && !mesh.contains_attribute(Mesh::ATTRIBUTE_TANGENT) {
+        // Normal maps need tangents, which in turn need UVs
+        if let Err(e) = mesh.generate_tangents() {
+            log::warn!("Cannot use normal map: {}", e);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_spawn_event(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    mut ids: ResMut<ObjectIds>,
+    mut history: ResMut<History>,
+    mut config: ResMut<MyPluginConfig>,
     mut reader: EventReader<SpawnMeshEvent>,
 ) {
     for e in reader.read() {
         // Spawn meshes from the event
-        let mesh_handle = meshes.add(e.mesh.clone());
-        commands.spawn((
-            Mesh3d(mesh_handle),
-            MeshMaterial3d(materials.add(Color::srgb_u8(0, 0, 255))),
-            Transform::from_xyz(0.0, 0.0, 0.0),
-        ));
+        let objects = std::iter::once((&e.mesh, &e.object))
+            .chain(e.copies.iter().map(|(mesh, object)| (mesh, object)));
+        let mut spawned = Vec::new();
+        for (mesh, object) in objects {
+            let (mesh, material) = add_mesh_and_material(
+                &mut meshes,
+                &mut materials,
+                &asset_server,
+                mesh,
+                &object.material,
+            );
+            let snapshot = ObjectSnapshot {
+                id: ids.reserve(None),
+                mesh,
+                transform: Transform::from_xyz(0.0, 0.0, 0.0),
+                object: object.clone(),
+                visibility: Visibility::Inherited,
+            };
+            commands.spawn((
+                snapshot.mesh.clone(),
+                material,
+                snapshot.transform,
+                snapshot.object.clone(),
+                snapshot.visibility,
+                snapshot.id,
+            ));
+            if spawned.is_empty() && matches!(object.kind, ObjKind::Stroke(_)) {
+                config.last_stroke = Some(snapshot.id);
+            }
+            spawned.push(Command::Spawn(Box::new(snapshot)));
+        }
+        let command = if spawned.len() == 1 {
+            spawned.remove(0)
+        } else {
+            Command::Batch(spawned)
+        };
+        history.push(command, &meshes);
     }
 }
 
+/// Everything an edit can change; lines have no standard material
+type EditableObject = (
+    Entity,
+    &'static ObjectId,
+    &'static mut Transform,
+    &'static mut SceneObject,
+    &'static mut Visibility,
+    &'static Mesh3d,
+    Option<&'static mut MeshMaterial3d<StandardMaterial>>,
+);
+
+#[allow(clippy::too_many_arguments)]
+fn handle_edit_event(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    mut selection: ResMut<Selection>,
+    mut history: ResMut<History>,
+    mut reader: EventReader<EditObjectEvent>,
+    mut objects: Query<EditableObject>,
+) {
+    for e in reader.read() {
+        let Some((entity, id, mut transform, mut object, mut visibility, mesh, mut material)) =
+            objects.iter_mut().find(|(_, id, ..)| **id == e.id)
+        else {
+            log::warn!("No object with id {}", e.id.0);
+            continue;
+        };
+        let snapshot = || ObjectSnapshot {
+            id: *id,
+            mesh: mesh.clone(),
+            transform: *transform,
+            object: object.clone(),
+            visibility: *visibility,
+        };
+        if e.record {
+            let command = match &e.edit {
+                ObjectEdit::Transform(t) => Command::Transform {
+                    id: *id,
+                    before: *transform,
+                    after: *t,
+                },
+                ObjectEdit::Material(m) => Command::Material {
+                    id: *id,
+                    before: object.material.clone(),
+                    after: m.clone(),
+                },
+                ObjectEdit::Visible(visible) => Command::Visible {
+                    id: *id,
+                    before: *visibility != Visibility::Hidden,
+                    after: *visible,
+                },
+                ObjectEdit::Duplicate(new_id) => Command::Spawn(Box::new(ObjectSnapshot {
+                    id: *new_id,
+                    ..snapshot()
+                })),
+                ObjectEdit::Delete => Command::Delete(Box::new(snapshot())),
+            };
+            history.push(command, &meshes);
+        }
+        match &e.edit {
+            ObjectEdit::Transform(t) => *transform = *t,
+            ObjectEdit::Material(m) => {
+                if let Some(mesh) = meshes.get_mut(&mesh.0) {
+                    ensure_tangents(mesh, m);
+                }
+                // Line strokes pick up the change from `object`, see `line_material`
+                if let Some(material) = material.as_mut() {
+                    material.0 = materials.add(m.to_standard_material(&asset_server));
+                }
+                object.material = m.clone();
+            }
+            ObjectEdit::Visible(visible) => {
+                *visibility = if *visible {
+                    Visibility::Inherited
+                } else {
+                    Visibility::Hidden
+                };
+            }
+            ObjectEdit::Duplicate(new_id) => {
+                // The mesh is shared, the material is not so it can be edited separately
+                commands.spawn((
+                    mesh.clone(),
+                    MeshMaterial3d(
+                        materials.add(object.material.to_standard_material(&asset_server)),
+                    ),
+                    *transform,
+                    object.clone(),
+                    *visibility,
+                    *new_id,
+                ));
+            }
+            ObjectEdit::Delete => {
+                if selection.entity == Some(entity) {
+                    selection.clear();
+                }
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_pick_event(
+    mut reader: EventReader<PickEvent>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    objects: Query<(
+        Entity,
+        &ObjectId,
+        &SceneObject,
+        &Mesh3d,
+        &GlobalTransform,
+        &InheritedVisibility,
+    )>,
+    meshes: Res<Assets<Mesh>>,
+    mut cache: ResMut<picking::MeshBvhCache>,
+    transforms: Query<&Transform, Without<Camera3d>>,
+    layers: Res<SketchLayers>,
+    mut gizmo: ResMut<TransformGizmo>,
+    mut selection: ResMut<Selection>,
+) {
+    for e in reader.read() {
+        let (Ok(window), Ok((camera, camera_transform))) = (windows.single(), cameras.single())
+        else {
+            continue;
+        };
+        // Touching a gizmo handle drags it instead of changing the selection
+        if gizmo::try_begin_drag(
+            &mut gizmo,
+            &selection,
+            &transforms,
+            (camera, camera_transform),
+            window.scale_factor(),
+            e.pos,
+        ) {
+            continue;
+        }
+        let Ok(ray) = camera.viewport_to_world(camera_transform, e.pos / window.scale_factor())
+        else {
+            continue;
+        };
+
+        let mut best: Option<(Entity, ObjectId, picking::RayHit)> = None;
+        for (entity, id, object, mesh, transform, visibility) in &objects {
+            if !visibility.get() || layers.locks(object) {
+                continue;
+            }
+            let Some(mesh) = cache.get(mesh, &meshes) else {
+                continue;
+            };
+            if let Some(hit) = picking::ray_mesh(ray, mesh, transform) {
+                if best.is_none_or(|(_, _, b)| hit.distance < b.distance) {
+                    best = Some((entity, *id, hit));
+                }
+            }
+        }
+
+        // Tapping empty space clears the selection
+        match best {
+            Some((entity, id, hit)) => {
+                log::info!("Selected object {} at {:?}", id.0, hit.point);
+                *selection = Selection {
+                    entity: Some(entity),
+                    id: Some(id),
+                    point: Some(hit.point),
+                };
+            }
+            None => selection.clear(),
+        }
+    }
+}
+
+/// Outline the selected object with its bounding box.
+fn draw_selection(
+    mut gizmos: Gizmos,
+    selection: Res<Selection>,
+    objects: Query<(&Mesh3d, &GlobalTransform)>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    let Some(entity) = selection.entity else {
+        return;
+    };
+    let Ok((mesh, transform)) = objects.get(entity) else {
+        return;
+    };
+    let Some((min, max)) = meshes.get(&mesh.0).and_then(picking::mesh_bounds) else {
+        return;
+    };
+    let bounds = Transform::from_translation((min + max) / 2.0).with_scale(max - min);
+    gizmos.cuboid(transform.mul_transform(bounds), Color::srgb(1.0, 0.8, 0.0));
+}
+
 /// set up a simple 3D scene
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    orbit: Res<OrbitCamera>,
+    mut ids: ResMut<ObjectIds>,
     config: Res<MyPluginConfig>,
 ) {
     // cube
@@ -111,32 +557,37 @@ fn setup(
     //     MeshMaterial3d(materials.add(Color::srgb_u8(200, 200, 200))),
     //     Transform::from_xyz(0.0, -1.0, 0.0),
     // ));
-    // light
-    commands.spawn((
-        PointLight {
-            shadows_enabled: true,
-            color: config.env_lightcolor,
-            ..default()
-        },
-        Transform::from_xyz(0.0, 8.0, 0.0),
-    ));
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_xyz(0.0, 0.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
-
-    // Spawn meshes from the config
+    // lights
+    for light in &config.lights {
+        light.spawn(&mut commands);
+    }
+    commands.spawn((Camera3d::default(), orbit.transform(config.camera_pos)));
+
+    // Spawn meshes from the config, keeping their saved IDs
+    for id in config.meshes.iter().filter_map(|m| m.id) {
+        ids.reserve(Some(id));
+    }
     for meshconfig in &config.meshes {
         // log::info!("Spawning mesh: {:?}", mesh);
-        let mesh_handle = meshes.add(meshconfig.mesh.clone());
+        let (mesh, material) = add_mesh_and_material(
+            &mut meshes,
+            &mut materials,
+            &asset_server,
+            &meshconfig.mesh,
+            &meshconfig.object.material,
+        );
         commands.spawn((
-            Mesh3d(mesh_handle),
-            MeshMaterial3d(materials.add(meshconfig.color)),
+            mesh,
+            material,
             meshconfig.transform.clone(),
+            meshconfig.object.clone(),
+            meshconfig.visibility,
+            ids.reserve(meshconfig.id),
         ));
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn move_camera(
     // mut commands: Commands,
     mut query: Query<&mut Transform, With<Camera3d>>,
@@ -148,8 +599,23 @@ fn move_camera(
     // mut meshes: ResMut<Assets<Mesh>>,
     // mut materials: ResMut<Assets<StandardMaterial>>,
     windows: Query<&Window>,
+    time: Res<Time>,
+    stroke: Res<StrokeSettings>,
+    objects: Query<(&Mesh3d, &GlobalTransform, &InheritedVisibility), With<ObjectId>>,
+    meshes: Res<Assets<Mesh>>,
+    mut cache: ResMut<picking::MeshBvhCache>,
 ) {
-    if config.sketch {
+    if input.is_multi_touch() {
+        // Handled by `handle_gestures`. Forget the last position so the
+        // remaining finger does not jump when the others lift.
+        lastinput.touch = None;
+        return;
+    }
+    if config.mode == InteractionMode::Select {
+        // Taps are handled by `handle_pick_event`
+        return;
+    }
+    if config.mode == InteractionMode::Sketch {
         // If sketch mode is enabled, we don't move the camera
 
         if let Some(cpos) = input.touch {
@@ -158,36 +624,16 @@ fn move_camera(
             let ccpos = cpos / window.scale_factor();
 
             if let Ok(ray) = camera.viewport_to_world(camera_transform, ccpos) {
-                let cam_pos = camera_transform.translation();
-                let cam_forward = camera_transform.forward() * 10.0; // +Z in Bevy
-
-                // Plane 1 unit in front of camera
-                let plane_origin = cam_pos + cam_forward;
-                let plane_normal = cam_forward;
-
-                let ray_origin = ray.origin;
-                let ray_dir = ray.direction;
-
-                // Ray-plane intersection: (P - plane_origin) ⋅ n = 0
-                // Solve for t: (O + tD - plane_origin) ⋅ n = 0
-                let denom = ray_dir.dot(plane_normal);
-                let mut mshp = Sphere::default();
-                mshp.radius = 0.5;
-                if denom.abs() > f32::EPSILON {
-                    let t = (plane_origin - ray_origin).dot(plane_normal) / denom;
-                    if t >= 0.0 {
-                        let intersection = ray_origin + ray_dir * t;
-                        log::info!("Hit point 1 unit in front of camera: {:?}", intersection);
-                        config.sketch_history.push(glam::Vec3::new(
-                            intersection.x,
-                            intersection.y,
-                            intersection.z,
-                        ));
-                    } else {
-                        log::info!("Intersection behind the camera");
-                    }
-                } else {
-                    log::info!("Ray is parallel to the plane");
+                let hit = stroke.projection.project(ray, camera_transform, |ray| {
+                    projection::cast_scene(ray, &objects, &meshes, &mut cache)
+                });
+                match hit {
+                    Some(point) => config.sketch_history.push(StrokeSample {
+                        pos: glam::Vec3::new(point.x, point.y, point.z),
+                        pressure: input.pressure,
+                        time: time.elapsed_secs(),
+                    }),
+                    None => log::info!("Touch does not hit the sketch target"),
                 }
             }
 
@@ -210,19 +656,7 @@ fn move_camera(
                         std::f32::consts::FRAC_PI_2 - 0.01,
                     );
 
-                    let center = Vec3::new(
-                        config.camera_pos[0],
-                        config.camera_pos[1],
-                        config.camera_pos[2],
-                    );
-                    // Convert spherical coordinates to cartesian
-                    let x = orbit.radius * orbit.elevation.cos() * orbit.azimuth.sin();
-                    let y = orbit.radius * orbit.elevation.sin();
-                    let z = orbit.radius * orbit.elevation.cos() * orbit.azimuth.cos();
-
-                    let position = center + Vec3::new(x, y, z);
-                    transform.translation = position;
-                    transform.look_at(center, Vec3::Y);
+                    *transform = orbit.transform(config.camera_pos);
                 }
             }
             // Update last touch input
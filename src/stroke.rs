@@ -0,0 +1,605 @@
+//! Turns the raw touch samples of a sketch stroke into a smooth curve.
+//!
+//! The samples go through these stages before being meshified:
+//! 1. optional resampling to an even spacing along the stroke
+//! 2. optional removal of outliers, e.g. from a touch glitch
+//! 3. a least squares cubic B-spline fit
+//! 4. sampling the spline, more densely where it bends
+//!
+//! The curve is then built in the chosen [`StrokeStyle`], its width
+//! following the touch pressure or drawing speed and tapering towards both
+//! ends.
+
+use bevy::prelude::{Mesh, Resource};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    file_io::{export_obj_to_string, to_bevy_mesh},
+    geometry::{self, FrameMode, Profile, TubeOptions},
+    line_material,
+    primitives::StrokeParams,
+    projection::ProjectionSettings,
+    symmetry::SymmetrySettings,
+    validation::Validator,
+};
+
+/// One touch sample of a stroke being drawn.
+#[derive(Debug, Clone, Copy)]
+pub struct StrokeSample {
+    pub pos: Vec3,
+    /// As reported by the touch screen, 1 when it has no pressure sensor.
+    /// Widths only use it up to 1.
+    pub pressure: f32,
+    /// Seconds since the app started
+    pub time: f32,
+}
+
+/// What the tube width follows.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WidthMode {
+    Constant,
+    /// Pressing harder draws wider
+    #[default]
+    Pressure,
+    /// Drawing faster draws thinner, like a brush
+    Velocity,
+}
+
+/// What a stroke is built as.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StrokeStyle {
+    /// Round tube
+    #[default]
+    Tube,
+    /// Flat band facing the camera it was drawn from
+    Ribbon,
+    /// Same width on screen at any distance, see [`line_material`]
+    Line,
+    /// The settings' `profile` swept along the stroke
+    Extrude,
+}
+
+#[derive(Resource, Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StrokeSettings {
+    /// Where touches land in the scene
+    pub projection: ProjectionSettings,
+    /// Mirrored and rotated copies drawn along with each stroke
+    pub symmetry: SymmetrySettings,
+    /// Distance between resampled points in world units, unset keeps the
+    /// samples as they came in
+    pub resample_spacing: Option<f32>,
+    /// A point is dropped when it is further than this many median steps from
+    /// the midpoint of its neighbours, unset keeps every point
+    pub outlier_threshold: Option<f32>,
+    /// Control points per input point
+    pub control_point_density: f32,
+    /// Penalty on bending of the fitted curve, 0 fits the points as closely
+    /// as possible
+    pub smoothing: f32,
+    /// Largest turn between consecutive sampled segments, in degrees
+    pub max_angle: f32,
+    /// How many times a spline span may be halved to meet `max_angle`
+    pub max_depth: u32,
+    pub style: StrokeStyle,
+    /// Tube radius at full width, in world units
+    pub radius: f32,
+    /// Width of line strokes at full width, in physical pixels
+    pub line_width: f32,
+    /// Cross-section of extruded strokes, scaled by `radius`
+    pub profile: Profile,
+    pub width: WidthMode,
+    /// Speed in world units per second at which a velocity driven tube is
+    /// half as wide
+    pub thin_speed: f32,
+    /// Narrowest the tube gets, as a fraction of `radius`
+    pub min_scale: f32,
+    /// Fraction of the stroke length over which each end narrows
+    pub taper: f32,
+    /// Vertices around the tube
+    pub radial_segments: u32,
+    /// Close the tube ends
+    pub caps: bool,
+    /// How the tube cross-section follows the curve
+    pub frames: FrameMode,
+}
+
+impl Default for StrokeSettings {
+    fn default() -> Self {
+        Self {
+            projection: ProjectionSettings::default(),
+            symmetry: SymmetrySettings::default(),
+            resample_spacing: None,
+            outlier_threshold: Some(5.0),
+            control_point_density: geometry::DEFAULT_CONTROL_POINT_DENSITY,
+            smoothing: 0.0,
+            max_angle: 5.0,
+            max_depth: 6,
+            style: StrokeStyle::Tube,
+            radius: 0.1,
+            line_width: 6.0,
+            profile: Profile::Square,
+            width: WidthMode::Pressure,
+            thin_speed: 2.0,
+            min_scale: 0.2,
+            taper: 0.15,
+            radial_segments: 8,
+            caps: true,
+            frames: FrameMode::ParallelTransport,
+        }
+    }
+}
+
+impl StrokeSettings {
+    pub fn validate(&self, v: &mut Validator) {
+        v.at("projection", |v| self.projection.validate(v));
+        v.at("symmetry", |v| self.symmetry.validate(v));
+        if let Some(spacing) = self.resample_spacing {
+            v.positive("resampleSpacing", spacing);
+        }
+        if let Some(threshold) = self.outlier_threshold {
+            v.positive("outlierThreshold", threshold);
+        }
+        if !(self.control_point_density > 0.0 && self.control_point_density <= 1.0) {
+            v.field_error("controlPointDensity", "must be in (0, 1]");
+        }
+        if !(self.smoothing.is_finite() && self.smoothing >= 0.0) {
+            v.field_error("smoothing", "must be a non-negative number");
+        }
+        v.in_range("maxAngle", self.max_angle, 0.1, 180.0);
+        if self.max_depth > 12 {
+            v.field_error("maxDepth", "must be at most 12");
+        }
+        v.positive("radius", self.radius);
+        v.positive("lineWidth", self.line_width);
+        v.at("profile", |v| self.profile.validate(v));
+        v.positive("thinSpeed", self.thin_speed);
+        v.in_range("minScale", self.min_scale, 0.01, 1.0);
+        v.in_range("taper", self.taper, 0.0, 0.5);
+        v.at_least("radialSegments", self.radial_segments, 3);
+        if self.radial_segments > 64 {
+            v.field_error("radialSegments", "must be at most 64");
+        }
+    }
+}
+
+/// Smooth a stroke and build it in the configured style, returning the
+/// render mesh and the parameters it is saved with.
+pub fn build(
+    samples: &[StrokeSample],
+    camera_pos: Vec3,
+    settings: &StrokeSettings,
+) -> (Mesh, StrokeParams) {
+    let points: Vec<Vec3> = samples.iter().map(|s| s.pos).collect();
+    let curve = process(&points, settings);
+    let scales = scales(samples, &curve, settings);
+    let mut params = StrokeParams {
+        style: settings.style,
+        radius: settings.radius,
+        samples: points.iter().map(|p| p.to_array()).collect(),
+        curve: curve.iter().map(|p| p.to_array()).collect(),
+        ..Default::default()
+    };
+
+    if settings.style == StrokeStyle::Line {
+        params.points = curve
+            .iter()
+            .zip(&scales)
+            .map(|(p, s)| [p.x, p.y, p.z, s * settings.line_width])
+            .collect();
+        return (line_material::line_mesh(&params.points), params);
+    }
+
+    let radii: Vec<f32> = scales.iter().map(|s| s * settings.radius).collect();
+    let options = TubeOptions {
+        radial_segments: settings.radial_segments as usize,
+        caps: settings.caps,
+        frames: settings.frames,
+    };
+    let mesh = match settings.style {
+        StrokeStyle::Ribbon => geometry::ribbon(&curve, &radii, camera_pos),
+        StrokeStyle::Extrude => {
+            params.profile = Some(settings.profile.clone());
+            geometry::extrude(&curve, &radii, &settings.profile, camera_pos, &options)
+        }
+        StrokeStyle::Tube | StrokeStyle::Line => {
+            geometry::meshify(&curve, &radii, camera_pos, &options)
+        }
+    };
+    params.data = export_obj_to_string(&mesh);
+    (to_bevy_mesh(&mesh), params)
+}
+
+/// Distance along the polyline at each point, as a fraction of its length.
+fn arc_fractions(points: &[Vec3]) -> Vec<f32> {
+    let mut out = Vec::with_capacity(points.len());
+    let mut length = 0.0;
+    for (i, p) in points.iter().enumerate() {
+        if i > 0 {
+            length += points[i - 1].distance(*p);
+        }
+        out.push(length);
+    }
+    if length > 0.0 {
+        for f in &mut out {
+            *f /= length;
+        }
+    }
+    out
+}
+
+/// Width factor of each sample, paired with where it lies along the stroke.
+fn sample_scales(samples: &[StrokeSample], settings: &StrokeSettings) -> Vec<(f32, f32)> {
+    let points: Vec<Vec3> = samples.iter().map(|s| s.pos).collect();
+    let fractions = arc_fractions(&points);
+    (0..samples.len())
+        .map(|i| {
+            let scale = match settings.width {
+                WidthMode::Constant => 1.0,
+                // Hard presses can read above 1, which must not blow up the
+                // tube. Light ones are kept visible by `min_scale`.
+                WidthMode::Pressure => samples[i].pressure.clamp(0.0, 1.0),
+                WidthMode::Velocity => {
+                    let a = samples[i.saturating_sub(1)];
+                    let b = samples[(i + 1).min(samples.len() - 1)];
+                    let dt = b.time - a.time;
+                    let speed = if dt > 0.0 {
+                        a.pos.distance(b.pos) / dt
+                    } else {
+                        0.0
+                    };
+                    1.0 / (1.0 + speed / settings.thin_speed)
+                }
+            };
+            (fractions[i], scale)
+        })
+        .collect()
+}
+
+/// Width at each point of the smoothed curve, as a fraction of the full
+/// width. The width of the raw samples is carried over by matching positions
+/// along the stroke, since smoothing changes the number of points.
+fn scales(samples: &[StrokeSample], curve: &[Vec3], settings: &StrokeSettings) -> Vec<f32> {
+    let scales = sample_scales(samples, settings);
+    arc_fractions(curve)
+        .into_iter()
+        .map(|f| {
+            let k = scales.partition_point(|(g, _)| *g < f);
+            let scale = match (k.checked_sub(1).map(|k| scales[k]), scales.get(k)) {
+                (Some((g0, s0)), Some(&(g1, s1))) if g1 > g0 => {
+                    s0 + (s1 - s0) * (f - g0) / (g1 - g0)
+                }
+                (_, Some(&(_, s))) | (Some((_, s)), None) => s,
+                (None, None) => 1.0,
+            };
+            let taper = if settings.taper > 0.0 {
+                // Ease out so the ends are round rather than pointed
+                let e = (f.min(1.0 - f) / settings.taper).min(1.0);
+                e * (2.0 - e)
+            } else {
+                1.0
+            };
+            (scale * taper).max(settings.min_scale)
+        })
+        .collect()
+}
+
+/// Run the whole pipeline over the samples of one stroke.
+pub fn process(points: &[Vec3], settings: &StrokeSettings) -> Vec<Vec3> {
+    // A finger resting in place repeats the same point, which would give
+    // the fit several samples at the same parameter
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| a.distance_squared(*b) < 1e-12);
+
+    if let Some(spacing) = settings.resample_spacing {
+        points = resample(&points, spacing);
+    }
+    if let Some(threshold) = settings.outlier_threshold {
+        points = remove_outliers(&points, threshold);
+    }
+    if points.len() < 4 {
+        return points;
+    }
+
+    let n_ctrl_pts =
+        geometry::determine_control_point_count(points.len(), settings.control_point_density);
+    let control_points =
+        geometry::fit_bspline_control_points(&points, n_ctrl_pts, settings.smoothing);
+    if control_points.len() < 4 {
+        return points;
+    }
+    sample_adaptive(&control_points, settings)
+}
+
+/// Points spaced `spacing` apart along the polyline, keeping both ends.
+pub fn resample(points: &[Vec3], spacing: f32) -> Vec<Vec3> {
+    let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+        return Vec::new();
+    };
+    let mut out = vec![first];
+    // Distance left until the next point is due
+    let mut remaining = spacing;
+    for w in points.windows(2) {
+        let (mut a, b) = (w[0], w[1]);
+        let mut len = a.distance(b);
+        while len >= remaining {
+            a = a.lerp(b, remaining / len);
+            out.push(a);
+            len -= remaining;
+            remaining = spacing;
+        }
+        remaining -= len;
+    }
+    if out.last().is_none_or(|p| p.distance(last) > spacing * 0.5) {
+        out.push(last);
+    } else if let Some(end) = out.last_mut() {
+        *end = last;
+    }
+    out
+}
+
+/// Drop interior points that stick out from their neighbours by more than
+/// `threshold` median steps.
+pub fn remove_outliers(points: &[Vec3], threshold: f32) -> Vec<Vec3> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut steps: Vec<f32> = points.windows(2).map(|w| w[0].distance(w[1])).collect();
+    steps.sort_by(f32::total_cmp);
+    let median = steps[steps.len() / 2];
+    if median <= 0.0 {
+        return points.to_vec();
+    }
+
+    let mut out = vec![points[0]];
+    for w in points.windows(3) {
+        let deviation = w[1].distance((w[0] + w[2]) / 2.0);
+        if deviation <= threshold * median {
+            out.push(w[1]);
+        }
+    }
+    out.push(points[points.len() - 1]);
+    out
+}
+
+/// Sample a cubic B-spline, halving each span until consecutive segments
+/// turn by at most `max_angle`.
+pub fn sample_adaptive(control_points: &[Vec3], settings: &StrokeSettings) -> Vec<Vec3> {
+    let max_angle = settings.max_angle.to_radians();
+    let spans = control_points.len() - 3;
+    let eval = |t: f32| geometry::eval_bspline_fixed(control_points, t);
+
+    let mut out = vec![eval(0.0)];
+    for span in 0..spans {
+        // Start from two halves, so an S bend inside one span is not missed
+        for half in 0..2 {
+            let t0 = span as f32 + half as f32 * 0.5;
+            let t1 = t0 + 0.5;
+            refine(
+                &eval,
+                (t0, eval(t0)),
+                (t1, eval(t1)),
+                max_angle,
+                settings.max_depth,
+                &mut out,
+            );
+        }
+    }
+    out
+}
+
+fn refine(
+    eval: &impl Fn(f32) -> Vec3,
+    (t0, p0): (f32, Vec3),
+    (t1, p1): (f32, Vec3),
+    max_angle: f32,
+    depth: u32,
+    out: &mut Vec<Vec3>,
+) {
+    let tm = (t0 + t1) / 2.0;
+    let pm = eval(tm);
+    let (a, b) = (pm - p0, p1 - pm);
+    let bent =
+        a.length_squared() > 0.0 && b.length_squared() > 0.0 && a.angle_between(b) > max_angle;
+    if depth > 0 && bent {
+        refine(eval, (t0, p0), (tm, pm), max_angle, depth - 1, out);
+        refine(eval, (tm, pm), (t1, p1), max_angle, depth - 1, out);
+    } else {
+        out.push(p1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(points: &[Vec3]) -> Vec<StrokeSample> {
+        points
+            .iter()
+            .enumerate()
+            .map(|(i, &pos)| StrokeSample {
+                pos,
+                pressure: 0.5,
+                time: i as f32 * 0.01,
+            })
+            .collect()
+    }
+
+    /// Quarter circle of radius 1 in the XY plane
+    fn arc(n: usize) -> Vec<Vec3> {
+        (0..n)
+            .map(|i| {
+                let a = i as f32 / (n - 1) as f32 * std::f32::consts::FRAC_PI_2;
+                Vec3::new(a.cos(), a.sin(), 0.0)
+            })
+            .collect()
+    }
+
+    fn finite(points: &[Vec3]) -> bool {
+        points.iter().all(|p| p.is_finite())
+    }
+
+    #[test]
+    fn short_strokes_pass_through() {
+        let settings = StrokeSettings::default();
+        for n in 0..4 {
+            let points: Vec<Vec3> = (0..n).map(|i| Vec3::X * i as f32).collect();
+            assert_eq!(process(&points, &settings), points);
+        }
+    }
+
+    #[test]
+    fn repeated_samples_become_one_point() {
+        let settings = StrokeSettings {
+            resample_spacing: Some(0.1),
+            ..Default::default()
+        };
+        let tap = vec![Vec3::new(1.0, 2.0, 3.0); 20];
+        assert_eq!(process(&tap, &settings), [tap[0]]);
+    }
+
+    #[test]
+    fn long_strokes_are_fitted() {
+        let settings = StrokeSettings::default();
+        let points = arc(50);
+        let curve = process(&points, &settings);
+        assert!(curve.len() >= 2);
+        assert!(finite(&curve));
+        assert!(curve[0].distance(points[0]) < 0.05);
+        assert!(curve[curve.len() - 1].distance(points[49]) < 0.05);
+        assert!(curve.iter().all(|p| (p.length() - 1.0).abs() < 0.05));
+    }
+
+    #[test]
+    fn resample_spaces_points_evenly() {
+        let points = [Vec3::ZERO, Vec3::X * 0.1, Vec3::X];
+        let out = resample(&points, 0.25);
+        assert_eq!(out.len(), 5);
+        for (i, p) in out.iter().enumerate() {
+            assert!(p.abs_diff_eq(Vec3::X * 0.25 * i as f32, 1e-6));
+        }
+        assert!(resample(&[], 0.25).is_empty());
+        assert_eq!(resample(&[Vec3::Y], 0.25), [Vec3::Y]);
+        assert_eq!(resample(&[Vec3::Y, Vec3::Y], 0.25), [Vec3::Y]);
+    }
+
+    #[test]
+    fn outliers_are_removed() {
+        let mut points: Vec<Vec3> = (0..10).map(|i| Vec3::X * i as f32).collect();
+        points[5].y = 20.0;
+        // The neighbours are judged against the spike as well
+        let out = remove_outliers(&points, 5.0);
+        assert_eq!(out.len(), 7);
+        assert!(out.iter().all(|p| p.y == 0.0));
+        // Too short to judge, or no steps to compare against
+        assert_eq!(remove_outliers(&points[..2], 5.0), &points[..2]);
+        let tap = vec![Vec3::ONE; 4];
+        assert_eq!(remove_outliers(&tap, 5.0), tap);
+    }
+
+    #[test]
+    fn sampling_is_denser_where_the_curve_bends() {
+        let settings = StrokeSettings::default();
+        let straight: Vec<Vec3> = (0..6).map(|i| Vec3::X * i as f32).collect();
+        // Two halves per span, none of them bent
+        assert_eq!(sample_adaptive(&straight, &settings).len(), 1 + 2 * 3);
+
+        let bent = arc(6);
+        let curve = sample_adaptive(&bent, &settings);
+        assert!(curve.len() > 1 + 2 * 3);
+        assert!(finite(&curve));
+        // Each piece is checked against its own halves, so segments either
+        // side of a split point may turn a little more
+        for w in curve.windows(3) {
+            let turn = (w[1] - w[0]).angle_between(w[2] - w[1]).to_degrees();
+            assert!(turn <= settings.max_angle * 1.5, "{}", turn);
+        }
+    }
+
+    #[test]
+    fn nan_samples_do_not_panic() {
+        let settings = StrokeSettings {
+            resample_spacing: Some(0.1),
+            ..Default::default()
+        };
+        let mut points = arc(20);
+        points[7] = Vec3::NAN;
+        process(&points, &settings);
+        process(&[Vec3::NAN; 6], &settings);
+        build(&samples(&[Vec3::NAN; 6]), Vec3::Z * 10.0, &settings);
+    }
+
+    #[test]
+    fn short_strokes_build_in_every_style() {
+        let points = [Vec3::ZERO, Vec3::X, Vec3::X];
+        for style in [
+            StrokeStyle::Tube,
+            StrokeStyle::Ribbon,
+            StrokeStyle::Line,
+            StrokeStyle::Extrude,
+        ] {
+            for frames in [FrameMode::ParallelTransport, FrameMode::CameraFacing] {
+                let settings = StrokeSettings {
+                    style,
+                    frames,
+                    ..Default::default()
+                };
+                for n in 0..=points.len() {
+                    let (_, params) = build(&samples(&points[..n]), Vec3::Z * 10.0, &settings);
+                    assert_eq!(params.style, style);
+                    assert!(params.curve.len() <= 2);
+                    assert!(params.points.iter().flatten().all(|x| x.is_finite()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn taper_scales_stay_finite() {
+        for width in [
+            WidthMode::Constant,
+            WidthMode::Pressure,
+            WidthMode::Velocity,
+        ] {
+            let settings = StrokeSettings {
+                width,
+                ..Default::default()
+            };
+            // The same time twice gives an infinite speed
+            let mut drawn = samples(&arc(30));
+            drawn[4].time = drawn[3].time;
+            drawn[10].pressure = 3.0;
+            let tap = samples(&[Vec3::ONE; 3]);
+            for stroke in [&drawn[..], &drawn[..1], &drawn[..2], &tap[..], &[]] {
+                let points: Vec<Vec3> = stroke.iter().map(|s| s.pos).collect();
+                let curve = process(&points, &settings);
+                for s in scales(stroke, &curve, &settings) {
+                    assert!(s.is_finite());
+                    assert!(
+                        (settings.min_scale..=1.0).contains(&s),
+                        "{:?}: {}",
+                        width,
+                        s
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ends_are_tapered() {
+        let settings = StrokeSettings {
+            width: WidthMode::Constant,
+            ..Default::default()
+        };
+        let points = arc(50);
+        let curve = process(&points, &settings);
+        let scales = scales(&samples(&points), &curve, &settings);
+        assert_eq!(scales[0], settings.min_scale);
+        assert_eq!(scales[scales.len() - 1], settings.min_scale);
+        assert_eq!(scales[scales.len() / 2], 1.0);
+    }
+}
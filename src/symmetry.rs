@@ -0,0 +1,220 @@
+//! Symmetric sketching: every stroke is repeated mirrored across chosen
+//! planes and rotated around an axis, for modeling symmetric objects.
+//!
+//! The copies are built from the mapped touch samples rather than by
+//! transforming the finished mesh, so mirrored tubes keep their triangles
+//! facing outwards and camera facing styles still face the camera.
+
+use std::f32::consts::TAU;
+
+use bevy::{math::Affine3A, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    projection::WorldAxis,
+    scene3d::{InteractionMode, MyPluginConfig},
+    stroke::{StrokeSample, StrokeSettings},
+    validation::Validator,
+};
+
+const MAX_RADIAL_COPIES: u32 = 64;
+
+/// Side length of the mirror planes drawn while sketching
+const PLANE_SIZE: f32 = 10.0;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SymmetrySettings {
+    /// Mirror across the plane through `origin` perpendicular to X
+    pub mirror_x: bool,
+    pub mirror_y: bool,
+    pub mirror_z: bool,
+    /// Point the mirror planes and the radial axis go through
+    pub origin: [f32; 3],
+    /// Copies spread evenly around `radial_axis`, counting the stroke
+    /// itself. 1 turns radial symmetry off.
+    pub radial_copies: u32,
+    pub radial_axis: WorldAxis,
+}
+
+impl Default for SymmetrySettings {
+    fn default() -> Self {
+        Self {
+            mirror_x: false,
+            mirror_y: false,
+            mirror_z: false,
+            origin: [0.0, 0.0, 0.0],
+            radial_copies: 1,
+            radial_axis: WorldAxis::Y,
+        }
+    }
+}
+
+impl SymmetrySettings {
+    pub fn validate(&self, v: &mut Validator) {
+        v.finite("origin", &self.origin);
+        v.at_least("radialCopies", self.radial_copies, 1);
+        if self.radial_copies > MAX_RADIAL_COPIES {
+            v.field_error(
+                "radialCopies",
+                format!("must be at most {}", MAX_RADIAL_COPIES),
+            );
+        }
+    }
+
+    /// Normals of the enabled mirror planes.
+    fn mirrors(&self) -> Vec<Vec3> {
+        [
+            (self.mirror_x, Vec3::X),
+            (self.mirror_y, Vec3::Y),
+            (self.mirror_z, Vec3::Z),
+        ]
+        .into_iter()
+        .filter_map(|(on, normal)| on.then_some(normal))
+        .collect()
+    }
+
+    /// Maps from the drawn stroke to each of its copies. Combinations that
+    /// land on the stroke itself or on an earlier copy are left out, e.g.
+    /// mirroring across X and Z with an even number of copies around Y.
+    pub fn copies(&self) -> Vec<Affine3A> {
+        let origin = Vec3::from_array(self.origin);
+        let mirrors = self.mirrors();
+        let n = self.radial_copies.max(1);
+        let mut copies = vec![Affine3A::IDENTITY];
+        for mask in 0..1u32 << mirrors.len() {
+            let scale = mirrors
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .fold(Vec3::ONE, |scale, (_, normal)| {
+                    scale * (Vec3::ONE - 2.0 * *normal)
+                });
+            for k in 0..n {
+                let angle = TAU * k as f32 / n as f32;
+                let rotation = Quat::from_axis_angle(self.radial_axis.direction(), angle);
+                let copy = Affine3A::from_scale_rotation_translation(scale, rotation, origin)
+                    * Affine3A::from_translation(-origin);
+                if !copies.iter().any(|c| c.abs_diff_eq(copy, 1e-4)) {
+                    copies.push(copy);
+                }
+            }
+        }
+        copies.split_off(1)
+    }
+}
+
+/// A point of the drawn stroke mapped to one of its copies.
+pub fn map_point(copy: &Affine3A, p: glam::Vec3) -> glam::Vec3 {
+    let mapped = copy.transform_point3(Vec3::from_array(p.to_array()));
+    glam::Vec3::from_array(mapped.to_array())
+}
+
+/// The touch samples of the drawn stroke mapped to one of its copies.
+pub fn map_samples(copy: &Affine3A, samples: &[StrokeSample]) -> Vec<StrokeSample> {
+    samples
+        .iter()
+        .map(|s| StrokeSample {
+            pos: map_point(copy, s.pos),
+            ..*s
+        })
+        .collect()
+}
+
+/// Show the mirror planes and the radial axis while sketching.
+pub fn draw_symmetry(
+    mut gizmos: Gizmos,
+    config: Res<MyPluginConfig>,
+    settings: Res<StrokeSettings>,
+) {
+    if config.mode != InteractionMode::Sketch {
+        return;
+    }
+    let symmetry = &settings.symmetry;
+    let origin = Vec3::from_array(symmetry.origin);
+    let color = Color::srgba(1.0, 0.6, 0.2, 0.6);
+    for normal in symmetry.mirrors() {
+        let rotation = Quat::from_rotation_arc(Vec3::Z, normal);
+        gizmos.rect(
+            Isometry3d::new(origin, rotation),
+            Vec2::splat(PLANE_SIZE),
+            color,
+        );
+    }
+    if symmetry.radial_copies > 1 {
+        let half = symmetry.radial_axis.direction() * PLANE_SIZE * 0.5;
+        gizmos.line(origin - half, origin + half, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(settings: &SymmetrySettings, p: Vec3) -> Vec<Vec3> {
+        settings
+            .copies()
+            .iter()
+            .map(|c| c.transform_point3(p))
+            .collect()
+    }
+
+    #[test]
+    fn no_symmetry_makes_no_copies() {
+        assert!(SymmetrySettings::default().copies().is_empty());
+    }
+
+    #[test]
+    fn mirrors_go_through_the_origin() {
+        let settings = SymmetrySettings {
+            mirror_x: true,
+            origin: [1.0, 0.0, 0.0],
+            ..default()
+        };
+        let copies = mapped(&settings, Vec3::new(3.0, 2.0, 1.0));
+        assert_eq!(copies.len(), 1);
+        assert!(copies[0].abs_diff_eq(Vec3::new(-1.0, 2.0, 1.0), 1e-5));
+    }
+
+    #[test]
+    fn radial_copies_are_spread_evenly() {
+        let settings = SymmetrySettings {
+            radial_copies: 4,
+            ..default()
+        };
+        let copies = mapped(&settings, Vec3::X);
+        assert_eq!(copies.len(), 3);
+        for expected in [Vec3::NEG_Z, Vec3::NEG_X, Vec3::Z] {
+            assert!(copies.iter().any(|c| c.abs_diff_eq(expected, 1e-5)));
+        }
+    }
+
+    #[test]
+    fn mirrors_combine_with_each_other() {
+        let settings = SymmetrySettings {
+            mirror_x: true,
+            mirror_y: true,
+            mirror_z: true,
+            ..default()
+        };
+        assert_eq!(settings.copies().len(), 7);
+    }
+
+    #[test]
+    fn repeated_copies_are_left_out() {
+        // Mirroring across X and Z is the same as a half turn around Y
+        let settings = SymmetrySettings {
+            mirror_x: true,
+            mirror_z: true,
+            radial_copies: 2,
+            ..default()
+        };
+        let p = Vec3::new(1.0, 2.0, 3.0);
+        let copies = mapped(&settings, p);
+        assert_eq!(copies.len(), 3);
+        for (i, a) in copies.iter().enumerate() {
+            assert!(!a.abs_diff_eq(p, 1e-4));
+            assert!(copies[i + 1..].iter().all(|b| !a.abs_diff_eq(*b, 1e-4)));
+        }
+    }
+}
@@ -0,0 +1,460 @@
+//! Parsing and validation of the app init JSON.
+//!
+//! Instead of stopping at the first problem, every error is collected
+//! together with the JSON path it was found at, e.g.
+//! `scene.objects[3].pos`, so the host can show them all at once.
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::{
+    AppInitOpts, LightConfig, ObjConfig,
+    primitives::{self, PrimitiveEntry},
+};
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Collects validation errors while walking a config tree.
+#[derive(Default)]
+pub struct Validator {
+    path: Vec<String>,
+    errors: Vec<ValidationError>,
+}
+
+impl Validator {
+    fn current_path(&self) -> String {
+        let mut out = String::new();
+        for seg in &self.path {
+            if !out.is_empty() && !seg.starts_with('[') {
+                out.push('.');
+            }
+            out.push_str(seg);
+        }
+        if out.is_empty() { ".".to_string() } else { out }
+    }
+
+    /// Run `f` with `segment` appended to the current path.
+    pub fn at(&mut self, segment: impl Into<String>, f: impl FnOnce(&mut Self)) {
+        self.path.push(segment.into());
+        f(self);
+        self.path.pop();
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.errors.push(ValidationError {
+            path: self.current_path(),
+            message: message.into(),
+        });
+    }
+
+    pub fn field_error(&mut self, field: &str, message: impl Into<String>) {
+        self.at(field, |v| v.error(message));
+    }
+
+    pub fn finite(&mut self, field: &str, values: &[f32]) {
+        if values.iter().any(|x| !x.is_finite()) {
+            self.field_error(field, "must contain only finite numbers");
+        }
+    }
+
+    pub fn positive(&mut self, field: &str, value: f32) {
+        if !(value.is_finite() && value > 0.0) {
+            self.field_error(field, format!("must be a positive number, got {}", value));
+        }
+    }
+
+    pub fn in_range<T>(&mut self, field: &str, value: T, min: T, max: T)
+    where
+        T: PartialOrd + Copy + std::fmt::Display,
+    {
+        if !(min..=max).contains(&value) {
+            self.field_error(
+                field,
+                format!("must be between {} and {}, got {}", min, max, value),
+            );
+        }
+    }
+
+    pub fn at_least(&mut self, field: &str, value: u32, min: u32) {
+        if value < min {
+            self.field_error(field, format!("must be at least {}, got {}", min, value));
+        }
+    }
+
+    /// Rotations are `[x, y, z, w]` quaternions, normalized on use.
+    pub fn rotation(&mut self, field: &str, q: &[f32; 4]) {
+        let len_sq: f32 = q.iter().map(|x| x * x).sum();
+        if !(len_sq.is_finite() && len_sq > 1e-8) {
+            self.field_error(field, "must be a non-zero quaternion [x, y, z, w]");
+        }
+    }
+
+    /// Colors are sRGB triples in 0..1, as passed to `Color::srgb`.
+    pub fn color(&mut self, field: &str, rgb: &[f32; 3]) {
+        if rgb.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            self.field_error(
+                field,
+                format!("color components must be in [0, 1], got {:?}", rgb),
+            );
+        }
+    }
+
+    /// Deserialize `value` at the current path, recording any error.
+    fn deserialize<T: DeserializeOwned>(&mut self, value: Value) -> Option<T> {
+        serde_path_to_error::deserialize::<_, T>(value)
+            .map_err(|e| self.deserialize_error(e))
+            .ok()
+    }
+
+    /// Record a deserialization error under the path it was found at.
+    fn deserialize_error(&mut self, e: serde_path_to_error::Error<serde_json::Error>) {
+        let inner = e.path().to_string();
+        let message = e.into_inner().to_string();
+        if inner == "." {
+            self.error(message);
+        } else {
+            self.at(inner, |v| v.error(message));
+        }
+    }
+
+    pub fn into_errors(self) -> Vec<ValidationError> {
+        self.errors
+    }
+}
+
+/// Types that can check their own semantic constraints.
+pub trait Validate {
+    fn validate(&self, v: &mut Validator);
+}
+
+impl Validate for AppInitOpts {
+    fn validate(&self, v: &mut Validator) {
+        v.color("backgroundColor", &self.background_color);
+        v.color("environmentLightColor", &self.light_color);
+        if !(self.move_strength.is_finite() && self.move_strength >= 0.0) {
+            v.field_error("moveStrength", "must be a non-negative number");
+        }
+        let g = &self.gestures;
+        v.at("gestures", |v| {
+            v.finite("pinchSensitivity", &[g.pinch_sensitivity]);
+            v.finite("panSensitivity", &[g.pan_sensitivity]);
+            v.finite("twistSensitivity", &[g.twist_sensitivity]);
+            v.positive("minRadius", g.min_radius);
+            v.finite("maxRadius", &[g.max_radius]);
+            if g.max_radius < g.min_radius {
+                v.field_error("maxRadius", "must not be smaller than minRadius");
+            }
+        });
+        v.at("stroke", |v| self.stroke.validate(v));
+        v.at("scene", |v| {
+            v.finite("cameraPos", &self.scene.camera_pos);
+            let camera = &self.scene.camera;
+            v.at("camera", |v| {
+                v.finite("azimuth", &[camera.azimuth]);
+                v.finite("elevation", &[camera.elevation]);
+                v.positive("radius", camera.radius);
+            });
+            for (i, light) in self.scene.lights.iter().enumerate() {
+                v.at("lights", |v| {
+                    v.at(format!("[{}]", i), |v| light.validate(v))
+                });
+            }
+            for (i, obj) in self.scene.objects.iter().enumerate() {
+                v.at("objects", |v| v.at(format!("[{}]", i), |v| obj.validate(v)));
+            }
+            for (i, layer) in self.scene.layers.iter().enumerate() {
+                v.at("layers", |v| {
+                    v.at(format!("[{}]", i), |v| {
+                        layer.validate(v);
+                        if self.scene.layers[..i].iter().any(|l| l.id == layer.id) {
+                            v.field_error("id", "is used by another layer");
+                        }
+                    })
+                });
+            }
+        });
+    }
+}
+
+impl Validate for ObjConfig {
+    fn validate(&self, v: &mut Validator) {
+        if self.id == Some(u32::MAX) {
+            v.field_error("id", format!("must be less than {}", u32::MAX));
+        }
+        v.finite("pos", &self.pos);
+        v.color("color", &self.color);
+        v.positive("scale", self.scale);
+        if let Some(axes) = &self.scale_axes {
+            if axes.iter().any(|x| !(x.is_finite() && *x > 0.0)) {
+                v.field_error("scaleAxes", "must contain only positive numbers");
+            }
+        }
+        v.rotation("rotation", &self.rotation);
+        if let Some(material) = &self.material {
+            v.at("material", |v| material.validate(v));
+        }
+        self.kind.as_primitive().validate(v);
+    }
+}
+
+impl Validate for LightConfig {
+    fn validate(&self, v: &mut Validator) {
+        v.color("color", &self.color);
+        if !(self.intensity.is_finite() && self.intensity >= 0.0) {
+            v.field_error("intensity", "must be a non-negative number");
+        }
+        v.finite("pos", &self.pos);
+        v.rotation("rotation", &self.rotation);
+    }
+}
+
+/// Parse a standalone JSON value of type `T` and check it with `check`.
+pub fn parse_with<T: DeserializeOwned>(
+    json: &str,
+    check: impl FnOnce(&T, &mut Validator),
+) -> Result<T, Vec<ValidationError>> {
+    let mut v = Validator::default();
+    let value = match serde_json::from_str(json) {
+        Ok(value) => value,
+        Err(e) => {
+            v.error(format!("invalid JSON: {}", e));
+            return Err(v.into_errors());
+        }
+    };
+    let Some(parsed) = v.deserialize::<T>(value) else {
+        return Err(v.into_errors());
+    };
+    check(&parsed, &mut v);
+    let errors = v.into_errors();
+    if errors.is_empty() {
+        Ok(parsed)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parse the app init JSON, collecting every error instead of panicking.
+pub fn parse_app_opts(json: &str) -> Result<AppInitOpts, Vec<ValidationError>> {
+    let mut v = Validator::default();
+    let mut root: Value = match serde_json::from_str(json) {
+        Ok(root) => root,
+        Err(e) => {
+            v.error(format!("invalid JSON: {}", e));
+            return Err(v.into_errors());
+        }
+    };
+
+    // Objects are parsed one by one so a bad object does not hide the others.
+    let objects = root
+        .get_mut("scene")
+        .and_then(|scene| scene.get_mut("objects"))
+        .map(|objects| std::mem::replace(objects, Value::Array(Vec::new())));
+
+    let opts: Option<AppInitOpts> = v.deserialize(root);
+
+    let mut parsed = Vec::new();
+    let mut ids = std::collections::HashSet::new();
+    v.at("scene", |v| {
+        v.at("objects", |v| match objects {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(items)) => {
+                for (i, item) in items.into_iter().enumerate() {
+                    v.at(format!("[{}]", i), |v| {
+                        if let Some(obj) = parse_object(v, item) {
+                            obj.validate(v);
+                            if let Some(id) = obj.id {
+                                if !ids.insert(id) {
+                                    v.field_error("id", format!("duplicate object id {}", id));
+                                }
+                            }
+                            parsed.push(obj);
+                        }
+                    });
+                }
+            }
+            Some(_) => v.error("must be an array"),
+        });
+    });
+
+    let Some(mut opts) = opts else {
+        return Err(v.into_errors());
+    };
+    // Objects were already validated as they were parsed, at their original index.
+    opts.validate(&mut v);
+    opts.scene.objects = parsed;
+
+    let errors = v.into_errors();
+    if errors.is_empty() {
+        Ok(opts)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parse one scene object, reporting errors at their path within it.
+fn parse_object(v: &mut Validator, item: Value) -> Option<ObjConfig> {
+    let entry = check_object_type(v, &item)?;
+    // The parameters are flattened into `ObjConfig`, which loses the path of
+    // their errors, so they are parsed on their own first
+    if let Err(e) = entry.parse(item.clone()) {
+        v.deserialize_error(e);
+        return None;
+    }
+    v.deserialize(item)
+}
+
+/// Look up the object's `type` in the primitive registry, reporting it when
+/// missing or unknown.
+fn check_object_type(v: &mut Validator, item: &Value) -> Option<&'static PrimitiveEntry> {
+    let expected = primitives::supported_kinds().join(", ");
+    match item.get("type") {
+        Some(Value::String(name)) => {
+            let entry = primitives::find(name);
+            if entry.is_none() {
+                v.field_error(
+                    "type",
+                    format!(
+                        "unknown object type `{}`, expected one of: {}",
+                        name, expected
+                    ),
+                );
+            }
+            entry
+        }
+        Some(_) => {
+            v.field_error("type", format!("must be one of: {}", expected));
+            None
+        }
+        None if item.is_object() => {
+            v.field_error("type", format!("missing, expected one of: {}", expected));
+            None
+        }
+        None => {
+            v.error("must be an object");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_paths(json: &str) -> Vec<String> {
+        let mut paths: Vec<String> = parse_app_opts(json)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn valid_scene_parses() {
+        let json = r#"{
+            "backgroundColor": [0.2, 0.2, 0.2],
+            "scene": {"objects": [
+                {"type": "sphere", "radius": 2, "id": 4},
+                {"type": "cube", "pos": [1, 2, 3], "rotation": [0, 0, 1, 0]}
+            ]}
+        }"#;
+        let opts = parse_app_opts(json).unwrap();
+        assert_eq!(opts.scene.objects.len(), 2);
+        assert_eq!(opts.scene.objects[0].id, Some(4));
+        assert_eq!(opts.scene.objects[1].pos, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn bad_values_are_reported_at_their_path() {
+        let json = r#"{
+            "backgroundColor": [2, 0, 0],
+            "scene": {"objects": [
+                {"type": "sphere", "radius": -1},
+                {"type": "cube", "color": [0, 0, 5]},
+                {"type": "teapot"},
+                {"type": "cube", "rotation": [0, 0, 0, 0]},
+                {"pos": [0, 0, 0]}
+            ]}
+        }"#;
+        assert_eq!(
+            error_paths(json),
+            [
+                "backgroundColor",
+                "scene.objects[0].radius",
+                "scene.objects[1].color",
+                "scene.objects[2].type",
+                "scene.objects[3].rotation",
+                "scene.objects[4].type",
+            ]
+        );
+    }
+
+    #[test]
+    fn type_errors_are_reported_at_their_path() {
+        let json = r#"{
+            "moveStrength": "fast",
+            "scene": {"objects": [{"type": "cube", "pos": [0, "up", 0]}, 7]}
+        }"#;
+        assert_eq!(
+            error_paths(json),
+            [
+                "moveStrength",
+                "scene.objects[0].pos[1]",
+                "scene.objects[1]"
+            ]
+        );
+        assert_eq!(error_paths("{"), ["."]);
+    }
+
+    #[test]
+    fn bad_objects_do_not_hide_the_others() {
+        let json = r#"{"scene": {"objects": [
+            {"type": "cube", "id": 1},
+            {"type": "cube", "scale": 0},
+            {"type": "cube", "id": 1}
+        ]}}"#;
+        assert_eq!(
+            error_paths(json),
+            ["scene.objects[1].scale", "scene.objects[2].id"]
+        );
+    }
+
+    #[test]
+    fn gesture_limits_are_checked() {
+        let json = r#"{"gestures": {"minRadius": 5, "maxRadius": 1, "pinchSensitivity": 1e99}}"#;
+        assert_eq!(
+            error_paths(json),
+            ["gestures.maxRadius", "gestures.pinchSensitivity"]
+        );
+        let mut opts = AppInitOpts::default();
+        opts.gestures.max_radius = f32::NAN;
+        let mut v = Validator::default();
+        opts.validate(&mut v);
+        let paths: Vec<_> = v.into_errors().into_iter().map(|e| e.path).collect();
+        assert_eq!(paths, ["gestures.maxRadius"]);
+    }
+
+    #[test]
+    fn bad_mesh_data_is_reported_at_its_path() {
+        let json = r#"{"scene": {"objects": [
+            {"type": "mesh", "data": "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n"},
+            {"type": "mesh", "data": "v 0 0 zero\n"},
+            {"type": "stroke", "style": "tube", "data": "v 0 0 0\nf 1 2 3\n"}
+        ]}}"#;
+        assert_eq!(
+            error_paths(json),
+            ["scene.objects[1].data", "scene.objects[2].data"]
+        );
+    }
+}